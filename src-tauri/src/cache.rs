@@ -1,5 +1,6 @@
 #![allow(unused_imports)]
 
+use crate::path_guard::validate_explorer_path;
 use std::fs;
 use tauri::{AppHandle, Manager};
 
@@ -42,7 +43,7 @@ pub fn open_or_create_custom_css(app_handle: AppHandle) -> Result<(), String> {
     }
 
     // 使用系统默认程序打开文件
-    open_file_explorer(css_file_path.to_string_lossy().to_string())
+    spawn_file_explorer(&css_file_path)
 }
 
 /// 获取自定义CSS文件内容
@@ -82,11 +83,18 @@ pub fn get_cache_size(app_handle: AppHandle) -> Result<u64, String> {
 
 /// 打开系统文件资源管理器
 #[tauri::command]
-pub fn open_file_explorer(path: String) -> Result<(), String> {
+pub fn open_file_explorer(app_handle: AppHandle, path: String) -> Result<(), String> {
+    // 只允许打开资源库、配置等目录
+    let path = validate_explorer_path(&app_handle, &path)?;
+    spawn_file_explorer(&path)
+}
+
+/// 调用系统文件管理器打开路径
+fn spawn_file_explorer(path: &std::path::Path) -> Result<(), String> {
     #[cfg(target_os = "windows")]
     {
         std::process::Command::new("explorer")
-            .arg(&path)
+            .arg(path)
             .spawn()
             .map_err(|e| format!("无法打开文件资源管理器: {}", e))?;
     }
@@ -94,7 +102,7 @@ pub fn open_file_explorer(path: String) -> Result<(), String> {
     #[cfg(target_os = "macos")]
     {
         std::process::Command::new("open")
-            .arg(&path)
+            .arg(path)
             .spawn()
             .map_err(|e| format!("无法打开 Finder: {}", e))?;
     }
@@ -102,7 +110,7 @@ pub fn open_file_explorer(path: String) -> Result<(), String> {
     #[cfg(target_os = "linux")]
     {
         std::process::Command::new("xdg-open")
-            .arg(&path)
+            .arg(path)
             .spawn()
             .map_err(|e| format!("无法打开文件管理器: {}", e))?;
     }
@@ -1,14 +1,45 @@
+use crate::download::ffmpeg::{detected_ffmpeg_path, remux_with_ffmpeg};
+use crate::download::hls::{
+    fetch_init_section, fetch_media_playlist, is_hls_content_type, is_hls_url, open_segment,
+    parse_media_playlist, HlsSegment, KeyCache, VariantStream,
+};
+use crate::download::http_client::{http_client, HttpClient};
+use crate::download::local_playlist::{remove_local_playlist, write_local_playlist};
 use crate::download::manga::{download_image, get_extension_from_filename, get_filename_from_url};
+use crate::download::manifest::{segment_file_path, Checksum, SegmentManifest};
+use crate::download::naming::{
+    join_relative, load_naming_config, render_file_name, resolve_collision, NamingContext,
+    TemplateKind,
+};
+use crate::download::probe::probe_media_file;
+use crate::download::remux::{remux_ts_to_mp4, RemuxInput, RemuxSummary};
+use crate::download::resume::{request_download, ResumeState};
+use crate::download::segmented::{
+    part_file_path, probe_range_support, request_part, PartRange, PartsState, RangeSupport,
+    MIN_SEGMENTED_SIZE,
+};
+use crate::download::settings::{load_download_settings, DownloadSettings};
+use crate::download::thumbnail::generate_thumbnail;
+use crate::download::trash::{move_to_trash, read_display_name};
 use crate::download::types::*;
+use crate::download::utils::{chapter_cmp, get_downloads_path, json_chapter_index};
+use crate::download::verify::{verify_merged_output, MergeExpectation};
+use crate::path_guard::{ensure_within, SafeSegment};
 use serde_json::{json, Value};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex as StdMutex};
 use tauri::{AppHandle, Manager};
 use tokio::fs;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::sync::Mutex;
 
+// 合并分片时的读取缓冲区大小，内存占用与分片大小无关
+const MERGE_BUFFER_SIZE: usize = 256 * 1024;
+// 单个分片在同一线路上的最多尝试次数
+const SEGMENT_ATTEMPTS: usize = 3;
+
 // 全局进度跟踪器
 use std::sync::OnceLock;
 static DOWNLOAD_PROGRESS: OnceLock<Arc<Mutex<HashMap<String, CartoonProgressTracker>>>> =
@@ -18,6 +49,14 @@ fn get_progress_tracker() -> &'static Arc<Mutex<HashMap<String, CartoonProgressT
     DOWNLOAD_PROGRESS.get_or_init(|| Arc::new(Mutex::new(HashMap::new())))
 }
 
+// 检查动画章节是否正在下载（进度跟踪器中存在记录）
+pub(crate) async fn is_cartoon_download_running(cartoon_uuid: &str, chapter_uuid: &str) -> bool {
+    let progress_key = format!("{}_{}", cartoon_uuid, chapter_uuid);
+    let tracker = get_progress_tracker();
+    let progress_map = tracker.lock().await;
+    progress_map.contains_key(&progress_key)
+}
+
 // 暂停标志管理
 lazy_static::lazy_static! {
     static ref CARTOON_PAUSE_FLAGS: Arc<StdMutex<HashMap<String, bool>>> = Arc::new(StdMutex::new(HashMap::new()));
@@ -68,38 +107,51 @@ pub async fn resume_cartoon_download(
 // ========== 动画下载相关代码 ==========
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn download_cartoon_chapter(
-    cartoon_uuid: String,
+    cartoon_uuid: SafeSegment,
     cartoon_name: String,
-    chapter_uuid: String,
+    chapter_uuid: SafeSegment,
     chapter_name: String,
     video_url: String,
+    video_lines: Option<Vec<VideoLine>>, // 候选线路，按顺序在当前线路失败时切换
     cover: String,
     cartoon_detail: Option<CartoonDetail>,
+    chapter_index: Option<usize>, // 剧集序号（从1开始），用于命名模板中的 {index}
     app_handle: AppHandle,
 ) -> Result<CartoonDownloadResult, String> {
     eprintln!("开始下载动画章节: {}", chapter_name);
     eprintln!("视频URL: {}", video_url);
 
+    // video_url 不在候选线路中时作为首选线路
+    let mut video_lines = video_lines.unwrap_or_default();
+    video_lines.retain(|line| !line.url.is_empty());
+    if !video_lines.iter().any(|line| line.url == video_url) {
+        video_lines.insert(
+            0,
+            VideoLine {
+                line: None,
+                url: video_url.clone(),
+            },
+        );
+    }
+
     let download_info = CartoonDownloadInfo {
-        cartoon_uuid: cartoon_uuid.clone(),
+        cartoon_uuid: cartoon_uuid.to_string(),
         cartoon_name: cartoon_name.clone(),
-        chapter_uuid: chapter_uuid.clone(),
+        chapter_uuid: chapter_uuid.to_string(),
         chapter_name: chapter_name.clone(),
         video_url,
+        video_lines,
         cover: cover.clone(),
         cartoon_detail: cartoon_detail.clone(),
     };
 
     // 获取应用资源目录
-    let resource_dir = app_handle
-        .path()
-        .resource_dir()
-        .map_err(|e| format!("获取资源目录失败: {}", e))?;
+    let downloads_path = get_downloads_path(&app_handle).await?;
 
     // 创建动画目录 - 使用 cartoons 而不是 anime
-    let cartoon_path = resource_dir
-        .join("downloads")
+    let cartoon_path = downloads_path
         .join("cartoons")
         .join(&download_info.cartoon_uuid);
 
@@ -112,7 +164,11 @@ pub async fn download_cartoon_chapter(
     if let Some(ref detail) = cartoon_detail {
         // 保存动画详情JSON文件
         let cartoon_detail_path = cartoon_path.join("cartoon_detail.json");
-        let detail_content = serde_json::to_string_pretty(detail)
+        let versioned_detail = CartoonDetail {
+            schema_version: METADATA_SCHEMA_VERSION,
+            ..detail.clone()
+        };
+        let detail_content = serde_json::to_string_pretty(&versioned_detail)
             .map_err(|e| format!("序列化动画详情失败: {}", e))?;
 
         if let Err(e) = fs::write(&cartoon_detail_path, detail_content).await {
@@ -129,11 +185,7 @@ pub async fn download_cartoon_chapter(
 
             // 检查封面是否已存在
             if !cover_path.exists() {
-                let client = reqwest::Client::builder()
-                    .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-                    .timeout(std::time::Duration::from_secs(30))
-                    .build()
-                    .map_err(|e| format!("创建HTTP客户端失败: {}", e))?;
+                let client = http_client(&app_handle);
 
                 match download_image(&client, &detail.cover, &cover_path).await {
                     Ok(_) => println!("封面下载成功: {}", cover_path.display()),
@@ -155,28 +207,85 @@ pub async fn download_cartoon_chapter(
         return Err(format!("创建目录失败: {}", e));
     }
 
-    // 下载视频文件
-    let video_filename = format!("{}.mp4", &download_info.chapter_name);
-    let video_path = chapter_path.join(&video_filename);
+    // 已有章节信息时沿用其中的文件名（断点续传或已完成的下载）
+    let info_path = chapter_path.join("info.json");
+    let existing_info = match fs::read_to_string(&info_path).await {
+        Ok(content) => serde_json::from_str::<CartoonChapterInfo>(&content).ok(),
+        Err(_) => None,
+    };
+
+    // 续传时未传入序号则沿用已有章节信息中的序号
+    let chapter_index =
+        chapter_index.or_else(|| existing_info.as_ref().and_then(|info| info.chapter_index));
+
+    // 否则按命名模板生成文件名，文件名经过清理，不会逃逸出章节目录
+    let video_filename = match &existing_info {
+        Some(info) if !info.video_file.is_empty() => info.video_file.clone(),
+        _ => {
+            let naming_config = load_naming_config(&app_handle).await?;
+            let context = NamingContext {
+                series: &download_info.cartoon_name,
+                chapter: &download_info.chapter_name,
+                index: chapter_index,
+                ..Default::default()
+            };
+            let file_name = render_file_name(
+                &naming_config.cartoon_episode_template,
+                TemplateKind::CartoonEpisode,
+                &context,
+                "mp4",
+            )?;
+            // 没有章节信息时，同名文件视为旧版本下载的结果
+            resolve_collision(&chapter_path, &file_name, &mut HashSet::new(), |_| {
+                existing_info.is_none()
+            })
+        }
+    };
+    let video_path = join_relative(&chapter_path, &video_filename);
+    if let Some(parent) = video_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("创建目录失败: {}", e))?;
+    }
 
     // 检查视频文件是否已存在
-    if video_path.exists() {
+    let resuming = existing_info
+        .as_ref()
+        .is_some_and(|info| !info.is_completed);
+    if video_path.exists() && !resuming {
         println!("视频文件已存在，跳过下载: {}", video_path.display()); // 创建章节信息文件
+        let file_size = fs::metadata(&video_path)
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0);
+        let media = probe_media_file(&video_path).await;
+        let thumbnail = match existing_info
+            .as_ref()
+            .and_then(|info| info.thumbnail.clone())
+        {
+            Some(thumbnail) if join_relative(&chapter_path, &thumbnail).exists() => Some(thumbnail),
+            _ => generate_thumbnail(&chapter_path, &video_filename, media.as_ref()).await,
+        };
         let chapter_info = CartoonChapterInfo {
+            schema_version: METADATA_SCHEMA_VERSION,
             cartoon_uuid: download_info.cartoon_uuid.clone(),
             cartoon_name: download_info.cartoon_name.clone(),
             chapter_uuid: download_info.chapter_uuid.clone(),
             chapter_name: download_info.chapter_name.clone(),
+            chapter_index,
             video_file: video_filename.clone(),
-            file_size: 0, // 已存在文件，暂不获取大小
+            file_size,
             download_time: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
             is_completed: true, // 文件已存在，标记为完成
+            quality: existing_info.as_ref().and_then(|info| info.quality.clone()),
+            video_line: existing_info.and_then(|info| info.video_line),
+            media,
+            thumbnail,
         };
 
         let info_content = serde_json::to_string_pretty(&chapter_info)
             .map_err(|e| format!("序列化章节信息失败: {}", e))?;
 
-        let info_path = chapter_path.join("info.json");
         if let Err(e) = fs::write(&info_path, info_content).await {
             return Err(format!("写入章节信息失败: {}", e));
         }
@@ -186,12 +295,8 @@ pub async fn download_cartoon_chapter(
             message: format!("章节 \"{}\" 已存在", download_info.chapter_name),
             file_path: video_path.to_string_lossy().to_string(),
         });
-    } // 创建HTTP客户端
-    let client = reqwest::Client::builder()
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-        .timeout(std::time::Duration::from_secs(300)) // 5分钟超时
-        .build()
-        .map_err(|e| format!("创建HTTP客户端失败: {}", e))?; // 初始化下载进度跟踪
+    } // 使用共享的HTTP客户端
+    let client = http_client(&app_handle); // 初始化下载进度跟踪
     let progress_key = format!("{}_{}", cartoon_uuid, chapter_uuid);
     let pause_key = format!("{}|{}", cartoon_uuid, chapter_uuid); // 暂停键使用不同格式
     eprintln!("Progress key: {}, Pause key: {}", progress_key, pause_key);
@@ -214,51 +319,99 @@ pub async fn download_cartoon_chapter(
     eprintln!("开始下载视频: {}", download_info.video_url);
 
     // 先创建章节信息文件（包含预估信息）
-    let video_filename = format!("{}.mp4", &download_info.chapter_name);
     let initial_chapter_info = CartoonChapterInfo {
+        schema_version: METADATA_SCHEMA_VERSION,
         cartoon_uuid: download_info.cartoon_uuid.clone(),
         cartoon_name: download_info.cartoon_name.clone(),
         chapter_uuid: download_info.chapter_uuid.clone(),
         chapter_name: download_info.chapter_name.clone(),
+        chapter_index,
         video_file: video_filename.clone(),
         file_size: 0, // 初始为0，下载完成后更新
         download_time: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
         is_completed: false, // 初始为false，下载完成后设为true
+        quality: None,
+        video_line: None,
+        media: None,
+        thumbnail: None,
     };
 
     let info_content = serde_json::to_string_pretty(&initial_chapter_info)
         .map_err(|e| format!("序列化章节信息失败: {}", e))?;
 
-    let info_path = chapter_path.join("info.json");
     if let Err(e) = fs::write(&info_path, info_content).await {
         return Err(format!("写入初始章节信息失败: {}", e));
     }
     eprintln!("已创建初始info.json文件: {}", info_path.display());
 
-    match download_video(
+    let settings = load_download_settings(&app_handle).await?;
+    match download_video_from_lines(
         &client,
-        &download_info.video_url,
+        &download_info.video_lines,
         &video_path,
+        &chapter_path.join("temp_segments"),
+        &settings,
         &progress_key,
         &pause_key,
     )
     .await
     {
-        Ok(file_size) => {
+        Ok((
+            VideoDownloadOutcome {
+                file_path,
+                file_size,
+                quality,
+                media,
+            },
+            video_line,
+        )) => {
             eprintln!(
                 "视频下载成功: {} ({}MB)",
-                video_path.display(),
+                file_path.display(),
                 file_size / 1024 / 1024
-            ); // 更新章节信息文件，包含实际文件大小
+            );
+            // 转封装失败时保留的是 TS 文件，文件名随之变化
+            let video_filename = if file_path == video_path {
+                video_filename
+            } else {
+                Path::new(&video_filename)
+                    .with_extension("ts")
+                    .to_string_lossy()
+                    .to_string()
+            };
+            let media = match media {
+                Some(media) => Some(media),
+                None => probe_media_file(&file_path).await,
+            };
+            if let Some(media) = &media {
+                eprintln!(
+                    "媒体信息: {}x{} {:.1}秒 {:?}/{:?}",
+                    media.width,
+                    media.height,
+                    media.duration_secs,
+                    media.video_codec,
+                    media.audio_codec
+                );
+            }
+            let thumbnail =
+                generate_thumbnail(&chapter_path, &video_filename, media.as_ref()).await;
+
+            // 更新章节信息文件，包含实际文件大小
             let final_chapter_info = CartoonChapterInfo {
+                schema_version: METADATA_SCHEMA_VERSION,
                 cartoon_uuid: download_info.cartoon_uuid.clone(),
                 cartoon_name: download_info.cartoon_name.clone(),
                 chapter_uuid: download_info.chapter_uuid.clone(),
                 chapter_name: download_info.chapter_name.clone(),
+                chapter_index,
                 video_file: video_filename,
                 file_size,
                 download_time: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
                 is_completed: true, // 下载完成，标记为true
+                quality,
+                video_line: Some(video_line),
+                media,
+                thumbnail,
             };
 
             let final_info_content = serde_json::to_string_pretty(&final_chapter_info)
@@ -280,7 +433,7 @@ pub async fn download_cartoon_chapter(
             Ok(CartoonDownloadResult {
                 success: true,
                 message: format!("章节 \"{}\" 下载完成", download_info.chapter_name),
-                file_path: video_path.to_string_lossy().to_string(),
+                file_path: file_path.to_string_lossy().to_string(),
             })
         }
         Err(e) => {
@@ -299,40 +452,250 @@ pub async fn download_cartoon_chapter(
     }
 }
 
+// 视频下载结果
+struct VideoDownloadOutcome {
+    file_path: PathBuf, // 实际保存的文件，转封装失败时为 .ts 文件
+    file_size: u64,
+    quality: Option<VideoQuality>, // 仅多码率HLS流有值
+    media: Option<MediaInfo>,      // HLS 流校验输出时解析出的媒体信息
+}
+
+// 视频下载失败的原因：网络错误换一条线路可能成功，本地错误（写入磁盘、合并、转封装、校验）
+// 与线路无关，切换线路只会重复下载
+enum VideoDownloadError {
+    Network(String),
+    Local(String),
+}
+
+impl VideoDownloadError {
+    fn map_message(self, f: impl FnOnce(String) -> String) -> Self {
+        match self {
+            VideoDownloadError::Network(message) => VideoDownloadError::Network(f(message)),
+            VideoDownloadError::Local(message) => VideoDownloadError::Local(f(message)),
+        }
+    }
+}
+
+// 未标明为网络错误的都按本地错误处理
+impl From<String> for VideoDownloadError {
+    fn from(message: String) -> Self {
+        VideoDownloadError::Local(message)
+    }
+}
+
+// 暂停时等待恢复，每个分片开始下载前调用
+async fn wait_while_paused(progress_key: &str, pause_key: &str) {
+    if !is_cartoon_paused(pause_key) {
+        return;
+    }
+    eprintln!("下载被暂停: {}", pause_key);
+
+    // 更新进度为暂停状态
+    {
+        let tracker = get_progress_tracker();
+        let mut progress_map = tracker.lock().await;
+        if let Some(progress) = progress_map.get_mut(progress_key) {
+            progress.status = "paused".to_string();
+            progress.current_file = "下载已暂停".to_string();
+        }
+    }
+
+    // 等待恢复 - 使用正确的 pause_key
+    while is_cartoon_paused(pause_key) {
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
+
+    // 更新进度为继续状态，具体片段信息在下一个分片完成时刷新
+    {
+        let tracker = get_progress_tracker();
+        let mut progress_map = tracker.lock().await;
+        if let Some(progress) = progress_map.get_mut(progress_key) {
+            if progress.status == "paused" {
+                eprintln!("下载恢复: {}", pause_key);
+                progress.status = "downloading".to_string();
+            }
+        }
+    }
+}
+
+// 按顺序尝试各条线路，当前线路出现网络错误（如分片多次重试仍失败）时切换到下一条，
+// 本地错误直接返回。临时目录中已下载的内容保留，分片与新线路一致时从相同的分片继续下载
+async fn download_video_from_lines(
+    client: &HttpClient,
+    video_lines: &[VideoLine],
+    save_path: &Path,
+    temp_dir: &Path,
+    settings: &DownloadSettings,
+    progress_key: &str,
+    pause_key: &str,
+) -> Result<(VideoDownloadOutcome, VideoLine), String> {
+    let mut errors = Vec::new();
+    for (index, video_line) in video_lines.iter().enumerate() {
+        let line_name = video_line
+            .line
+            .clone()
+            .unwrap_or_else(|| format!("线路{}", index + 1));
+        if index > 0 {
+            eprintln!("切换到{}: {}", line_name, video_line.url);
+            let tracker = get_progress_tracker();
+            let mut progress_map = tracker.lock().await;
+            if let Some(progress) = progress_map.get_mut(progress_key) {
+                progress.current_file = format!("切换到{}...", line_name);
+            }
+        }
+
+        match download_video(
+            client,
+            &video_line.url,
+            save_path,
+            temp_dir,
+            settings,
+            progress_key,
+            pause_key,
+        )
+        .await
+        {
+            Ok(mut outcome) => {
+                // HLS 流在返回前已校验输出；直链视频能解析出媒体信息才算可用
+                if outcome.media.is_none() {
+                    outcome.media = probe_media_file(&outcome.file_path).await;
+                }
+                // 切换前线路留下的分片或续传信息已不再需要，输出未通过校验时保留
+                if index > 0 && outcome.media.is_some() {
+                    let _ = fs::remove_dir_all(temp_dir).await;
+                }
+                return Ok((outcome, video_line.clone()));
+            }
+            Err(VideoDownloadError::Network(e)) => {
+                eprintln!("{}下载失败: {}", line_name, e);
+                errors.push((line_name, e));
+            }
+            Err(VideoDownloadError::Local(e)) => {
+                eprintln!("{}下载失败（本地错误，不切换线路）: {}", line_name, e);
+                errors.push((line_name, e));
+                break;
+            }
+        }
+    }
+
+    match errors.len() {
+        0 => Err("没有可用的视频线路".to_string()),
+        1 => Err(errors.remove(0).1),
+        _ => Err(errors
+            .into_iter()
+            .map(|(line_name, e)| format!("{}: {}", line_name, e))
+            .collect::<Vec<_>>()
+            .join("；")),
+    }
+}
+
 // 下载视频文件
 async fn download_video(
-    client: &reqwest::Client,
+    client: &HttpClient,
     url: &str,
-    save_path: &PathBuf,
+    save_path: &Path,
+    temp_dir: &Path,
+    settings: &DownloadSettings,
     progress_key: &str,
     pause_key: &str,
-) -> Result<u64, String> {
+) -> Result<VideoDownloadOutcome, VideoDownloadError> {
     // 检查是否是HLS流（m3u8文件）
-    if url.ends_with(".m3u8") {
-        return download_hls_stream(client, url, save_path, progress_key, pause_key).await;
+    if is_hls_url(url) {
+        return download_hls_stream(
+            client,
+            url,
+            save_path,
+            temp_dir,
+            settings,
+            progress_key,
+            pause_key,
+        )
+        .await;
     } // 普通视频文件下载
     eprintln!("开始下载普通视频文件: {}", url);
 
-    let response = client
-        .get(url)
-        .send()
+    // 大文件且服务器支持范围请求时多连接分段下载，已有单连接的续传进度时继续单连接下载
+    if settings.direct_connections > 1 && ResumeState::load(temp_dir, url).await.is_none() {
+        if let Some(support) = probe_range_support(client, url).await {
+            if !support.is_playlist && support.total_size >= MIN_SEGMENTED_SIZE {
+                return download_segmented(
+                    client,
+                    url,
+                    save_path,
+                    temp_dir,
+                    &support,
+                    settings.direct_connections,
+                    progress_key,
+                    pause_key,
+                )
+                .await;
+            }
+        }
+    }
+
+    // 已有部分文件时尝试从断点继续下载
+    let partial_size = fs::metadata(save_path)
+        .await
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    let download = request_download(client, url, temp_dir, partial_size)
         .await
-        .map_err(|e| format!("请求失败: {}", e))?;
+        .map_err(VideoDownloadError::Network)?;
+    let resume_from = download.resume_from;
+    let total_size = download.total_size.unwrap_or(0);
+    let Some(response) = download.response else {
+        eprintln!("视频文件已完整下载: {} bytes", partial_size);
+        ResumeState::remove(temp_dir).await;
+        return Ok(VideoDownloadOutcome {
+            file_path: save_path.to_path_buf(),
+            file_size: partial_size,
+            quality: None,
+            media: None,
+        });
+    };
 
-    if !response.status().is_success() {
-        return Err(format!("HTTP状态错误: {}", response.status()));
-    } // 获取文件大小
-    let total_size = response.content_length().unwrap_or(0);
+    // 地址没有 .m3u8 后缀但返回的是播放列表
+    let is_playlist = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(is_hls_content_type);
+    if is_playlist {
+        drop(response);
+        ResumeState::remove(temp_dir).await;
+        return download_hls_stream(
+            client,
+            url,
+            save_path,
+            temp_dir,
+            settings,
+            progress_key,
+            pause_key,
+        )
+        .await;
+    }
     eprintln!("文件总大小: {} bytes", total_size);
 
-    let mut file = fs::File::create(save_path)
-        .await
-        .map_err(|e| format!("创建文件失败: {}", e))?;
+    // 续传时追加到已有文件末尾，否则重新创建文件并记录续传校验信息
+    let mut file = if resume_from > 0 {
+        fs::OpenOptions::new()
+            .append(true)
+            .open(save_path)
+            .await
+            .map_err(|e| format!("打开文件失败: {}", e))?
+    } else {
+        ResumeState::from_response(url, &response)
+            .save(temp_dir)
+            .await?;
+        fs::File::create(save_path)
+            .await
+            .map_err(|e| format!("创建文件失败: {}", e))?
+    };
 
     // 分块下载以支持暂停
     use futures_util::StreamExt;
     let mut stream = response.bytes_stream();
-    let mut downloaded = 0u64;
+    let mut downloaded = resume_from;
 
     while let Some(chunk_result) = stream.next().await {
         // 检查暂停标志
@@ -349,7 +712,8 @@ async fn download_video(
             }
         }
 
-        let chunk = chunk_result.map_err(|e| format!("读取数据块失败: {}", e))?;
+        let chunk = chunk_result
+            .map_err(|e| VideoDownloadError::Network(format!("读取数据块失败: {}", e)))?;
 
         file.write_all(&chunk)
             .await
@@ -377,257 +741,626 @@ async fn download_video(
         .await
         .map_err(|e| format!("刷新文件失败: {}", e))?;
 
+    if total_size > 0 && downloaded != total_size {
+        return Err(VideoDownloadError::Network(format!(
+            "下载的文件不完整: {}/{} bytes",
+            downloaded, total_size
+        )));
+    }
+    ResumeState::remove(temp_dir).await;
+
     eprintln!("普通视频文件下载完成: {} bytes", downloaded);
-    Ok(downloaded)
+    Ok(VideoDownloadOutcome {
+        file_path: save_path.to_path_buf(),
+        file_size: downloaded,
+        quality: None,
+        media: None,
+    })
 }
 
 // 下载HLS流
 async fn download_hls_stream(
-    client: &reqwest::Client,
+    client: &HttpClient,
     m3u8_url: &str,
-    save_path: &PathBuf,
+    save_path: &Path,
+    temp_dir: &Path,
+    settings: &DownloadSettings,
     progress_key: &str,
     pause_key: &str,
-) -> Result<u64, String> {
+) -> Result<VideoDownloadOutcome, VideoDownloadError> {
     eprintln!("检测到HLS流，开始解析m3u8文件: {}", m3u8_url);
 
-    // 获取m3u8文件内容
-    let response = client
-        .get(m3u8_url)
-        .send()
-        .await
-        .map_err(|e| format!("请求m3u8文件失败: {}", e))?;
-
-    if !response.status().is_success() {
-        return Err(format!("HTTP状态错误: {}", response.status()));
-    }
-
-    let m3u8_content = response
-        .text()
-        .await
-        .map_err(|e| format!("读取m3u8内容失败: {}", e))?;
+    // 获取m3u8文件内容，主播放列表按清晰度偏好选择码流
+    let (m3u8_content, playlist_url, variant) =
+        fetch_media_playlist(client, m3u8_url, &settings.video_quality)
+            .await
+            .map_err(VideoDownloadError::Network)?;
+    let quality = variant.as_ref().map(VariantStream::to_quality);
 
-    // 解析m3u8文件，获取视频片段URL列表
-    let base_url = get_base_url(m3u8_url);
-    let segment_urls = parse_m3u8_segments(&m3u8_content, &base_url)?;
+    // 解析m3u8文件，相对地址基于重定向后的播放列表地址
+    let playlist =
+        parse_media_playlist(&m3u8_content, &playlist_url).map_err(VideoDownloadError::Network)?;
+    let segments = &playlist.segments;
 
-    if segment_urls.is_empty() {
-        return Err("未找到视频片段".to_string());
+    if segments.is_empty() {
+        return Err(VideoDownloadError::Network("未找到视频片段".to_string()));
     }
 
-    eprintln!("找到 {} 个视频片段", segment_urls.len()); // 创建临时目录存储片段
-    let temp_dir = save_path.parent().unwrap().join("temp_segments");
-    if let Err(e) = fs::create_dir_all(&temp_dir).await {
-        return Err(format!("创建临时目录失败: {}", e));
+    let discontinuities = segments.iter().filter(|s| s.discontinuity).count();
+    eprintln!(
+        "找到 {} 个视频片段，{} 个初始化片段，{} 处不连续",
+        segments.len(),
+        playlist.init_sections.len(),
+        discontinuities
+    ); // 创建临时目录存储片段
+    if let Err(e) = fs::create_dir_all(temp_dir).await {
+        return Err(format!("创建临时目录失败: {}", e).into());
     }
 
-    // 检查已存在的分片文件，支持断点续传
-    let mut existing_segments = Vec::new();
-    let mut start_index = 0;
-    let mut total_downloaded = 0u64;
-
-    if temp_dir.exists() {
-        let mut entries = fs::read_dir(&temp_dir)
+    // fMP4 流先下载初始化片段，合并时写在对应片段之前
+    let key_cache = KeyCache::default();
+    let mut init_files = Vec::new();
+    for (index, init_section) in playlist.init_sections.iter().enumerate() {
+        let init_data = fetch_init_section(client, init_section, &key_cache)
             .await
-            .map_err(|e| format!("读取临时目录失败: {}", e))?;
-
-        while let Some(entry) = entries
-            .next_entry()
+            .map_err(|e| {
+                VideoDownloadError::Network(format!("下载初始化片段{}失败: {}", index, e))
+            })?;
+        let init_path = temp_dir.join(format!("init_{:02}.mp4", index));
+        fs::write(&init_path, &init_data)
             .await
-            .map_err(|e| format!("遍历临时目录失败: {}", e))?
-        {
-            let file_name = entry.file_name();
-            let file_name_str = file_name.to_string_lossy();
+            .map_err(|e| format!("写入初始化片段{}失败: {}", index, e))?;
+        init_files.push(init_path);
+    }
 
-            if file_name_str.starts_with("segment_") && file_name_str.ends_with(".ts") {
-                if let Some(index_str) = file_name_str
-                    .strip_prefix("segment_")
-                    .and_then(|s| s.strip_suffix(".ts"))
+    // 读取分片清单并与当前播放列表校验，只下载缺失或校验失败的分片
+    let mut manifest = SegmentManifest::load_or_create(
+        temp_dir,
+        playlist_url.as_str(),
+        &playlist,
+        variant.as_ref(),
+    )
+    .await;
+    let pending = manifest.pending_indices();
+    let mut total_downloaded = manifest.completed_bytes();
+    if manifest.completed_count() > 0 {
+        eprintln!(
+            "发现 {} 个已下载的分片，继续下载剩余 {} 个",
+            manifest.completed_count(),
+            pending.len()
+        );
+    }
+    manifest.save(temp_dir).await?;
+
+    // 本地播放列表只包含从头开始连续完成的分片，随下载进度增长
+    let mut ready_segments = manifest.completed_prefix();
+    if settings.local_playlist {
+        update_local_playlist(temp_dir, segments, &init_files, ready_segments).await;
+    }
+
+    // 并发下载分片，完成顺序不固定，合并时按索引排序
+    use futures_util::StreamExt;
+    let concurrency = settings.segment_concurrency.max(1);
+    eprintln!("使用 {} 个并发下载 {} 个分片", concurrency, pending.len());
+
+    let key_cache = &key_cache;
+    let mut downloads = futures_util::stream::iter(pending)
+        .map(|index| async move {
+            wait_while_paused(progress_key, pause_key).await;
+
+            // 网络错误重试几次，仍失败时由调用方切换线路；本地错误重试无用，直接返回
+            let segment_path = segment_file_path(temp_dir, index);
+            let mut attempt = 1;
+            let (size, segment_checksum) = loop {
+                match download_segment_file(client, &segments[index], key_cache, &segment_path)
+                    .await
                 {
-                    if let Ok(index) = index_str.parse::<usize>() {
-                        let file_path = entry.path();
-                        let file_size = entry
-                            .metadata()
-                            .await
-                            .map_err(|e| format!("获取文件元数据失败: {}", e))?
-                            .len();
-
-                        existing_segments.push((index, file_path, file_size));
-                        total_downloaded += file_size;
+                    Ok(result) => break result,
+                    Err(VideoDownloadError::Network(e)) if attempt < SEGMENT_ATTEMPTS => {
+                        eprintln!("片段{}第{}次下载失败，稍后重试: {}", index, attempt, e);
+                        tokio::time::sleep(std::time::Duration::from_secs(attempt as u64)).await;
+                        attempt += 1;
+                    }
+                    Err(e) => {
+                        return Err(e.map_message(|e| format!("下载片段{}失败: {}", index, e)))
                     }
                 }
-            }
-        }
-
-        // 按索引排序
-        existing_segments.sort_by_key(|&(index, _, _)| index);
+            };
 
-        if !existing_segments.is_empty() {
-            let last_index = existing_segments.last().unwrap().0;
-            eprintln!(
-                "发现 {} 个已下载的分片，最后一个索引: {}",
-                existing_segments.len(),
-                last_index
-            );
+            Ok::<_, VideoDownloadError>((index, size, segment_checksum))
+        })
+        .buffer_unordered(concurrency);
 
-            // 删除最后一个分片文件（可能不完整）
-            if let Some((_, last_file_path, last_file_size)) = existing_segments.pop() {
-                if let Err(e) = fs::remove_file(&last_file_path).await {
-                    eprintln!("删除最后一个分片失败: {}", e);
-                } else {
-                    eprintln!(
-                        "已删除可能不完整的最后一个分片: {}",
-                        last_file_path.display()
-                    );
-                    total_downloaded -= last_file_size;
-                }
+    let mut finished = manifest.completed_count();
+    let mut last_saved = std::time::Instant::now();
+    while let Some(result) = downloads.next().await {
+        // 任一分片失败时停止下载，保存清单后已完成的分片可用于续传
+        let (index, size, segment_checksum) = match result {
+            Ok(completed) => completed,
+            Err(e) => {
+                let _ = manifest.save(temp_dir).await;
+                return Err(e);
             }
+        };
+        manifest.mark_completed(index, size, segment_checksum);
+        total_downloaded += size;
+        finished += 1;
 
-            // 从下一个分片开始下载
-            start_index = if existing_segments.is_empty() {
-                0
-            } else {
-                existing_segments.last().unwrap().0 + 1
-            };
-            eprintln!("将从分片 {} 开始继续下载", start_index + 1);
+        if settings.local_playlist && manifest.completed_prefix() > ready_segments {
+            ready_segments = manifest.completed_prefix();
+            update_local_playlist(temp_dir, segments, &init_files, ready_segments).await;
         }
-    }
 
-    let mut segment_files = Vec::new();
-
-    // 下载分片（从 start_index 开始）
-    for (index, url) in segment_urls.iter().enumerate().skip(start_index) {
-        let segment_path = temp_dir.join(format!("segment_{:04}.ts", index));
+        // 限制清单写入频率，未记录的分片续传时会重新下载
+        if last_saved.elapsed() >= std::time::Duration::from_secs(1) {
+            manifest.save(temp_dir).await?;
+            last_saved = std::time::Instant::now();
+        }
 
         // 更新进度
         {
             let tracker = get_progress_tracker();
             let mut progress_map = tracker.lock().await;
             if let Some(progress) = progress_map.get_mut(progress_key) {
-                progress.current_segment = index + 1;
-                progress.total_segments = segment_urls.len();
-                progress.percent = (index as f64 / segment_urls.len() as f64) * 80.0; // 80%用于下载，20%用于合并
-                progress.status = "downloading".to_string();
-                progress.current_file = format!("下载片段 {}/{}", index + 1, segment_urls.len());
+                progress.current_segment = finished;
+                progress.total_segments = segments.len();
+                progress.percent = (finished as f64 / segments.len() as f64) * 80.0; // 80%用于下载，20%用于合并
+                progress.downloaded_bytes = total_downloaded;
+                if progress.status != "paused" {
+                    progress.status = "downloading".to_string();
+                    progress.current_file = format!("下载片段 {}/{}", finished, segments.len());
+                }
             }
         }
+    }
+    drop(downloads);
+    manifest.save(temp_dir).await?;
 
-        // eprintln!("下载片段 {}/{}: {}", index + 1, segment_urls.len(), url);
+    // 合并所有片段为单个视频文件
+    eprintln!("合并视频片段...");
 
-        let segment_response = client
-            .get(url)
-            .send()
-            .await
-            .map_err(|e| format!("下载片段{}失败: {}", index, e))?;
+    // 所有分片都已下载完成，按索引顺序合并
+    let all_segment_files: Vec<(usize, PathBuf)> = (0..segments.len())
+        .map(|index| (index, segment_file_path(temp_dir, index)))
+        .collect();
 
-        if !segment_response.status().is_success() {
-            return Err(format!(
-                "片段{}下载失败，HTTP状态: {}",
-                index,
-                segment_response.status()
-            ));
+    eprintln!("准备合并 {} 个分片文件", all_segment_files.len());
+
+    // 更新进度为合并阶段
+    {
+        let tracker = get_progress_tracker();
+        let mut progress_map = tracker.lock().await;
+        if let Some(progress) = progress_map.get_mut(progress_key) {
+            progress.percent = 80.0;
+            progress.status = "merging".to_string();
+            progress.current_file = "正在合并视频片段...".to_string();
         }
+    }
 
-        let segment_data = segment_response
-            .bytes()
-            .await
-            .map_err(|e| format!("读取片段{}数据失败: {}", index, e))?;
+    let mut output_path = save_path.to_path_buf();
+    let mut remuxed = false;
+    let remux_to_mp4 = save_path.extension().is_some_and(|ext| ext != "ts");
 
-        fs::write(&segment_path, &segment_data)
+    // 优先交给 ffmpeg 转封装（-c copy），失败或不可用时使用内置处理
+    let ffmpeg = if settings.use_ffmpeg && remux_to_mp4 {
+        detected_ffmpeg_path().await
+    } else {
+        None
+    };
+    if let Some(ffmpeg) = ffmpeg {
+        let (segment_files, init_files) = (&all_segment_files, &init_files);
+        let write_input = |mut stdin| async move {
+            write_merged_segments(
+                &mut stdin,
+                segment_files,
+                segments,
+                init_files,
+                progress_key,
+            )
             .await
-            .map_err(|e| format!("写入片段{}失败: {}", index, e))?;
-
-        total_downloaded += segment_data.len() as u64;
-        segment_files.push(segment_path);
-
-        // 更新下载字节数
-        {
-            let tracker = get_progress_tracker();
-            let mut progress_map = tracker.lock().await;
-            if let Some(progress) = progress_map.get_mut(progress_key) {
-                progress.downloaded_bytes = total_downloaded;
-            }
-        } // 检查暂停标志
-        if is_cartoon_paused(pause_key) {
-            eprintln!("下载被暂停: {}", pause_key);
-
-            // 更新进度为暂停状态
-            {
-                let tracker = get_progress_tracker();
-                let mut progress_map = tracker.lock().await;
-                if let Some(progress) = progress_map.get_mut(progress_key) {
-                    progress.status = "paused".to_string();
-                    progress.current_file = "下载已暂停".to_string();
-                }
+        };
+        match remux_with_ffmpeg(&ffmpeg, save_path, write_input).await {
+            Ok(()) => {
+                eprintln!("ffmpeg 转封装完成: {}", save_path.display());
+                remuxed = true;
             }
+            Err(e) => eprintln!("ffmpeg 转封装失败，改用内置处理: {}", e),
+        }
+    }
 
-            // 等待恢复 - 使用正确的 pause_key
-            loop {
-                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-                if !is_cartoon_paused(pause_key) {
-                    eprintln!("下载恢复: {}", pause_key);
-                    break;
-                }
+    // MPEG-TS 流转封装为带索引的 MP4，失败时按原样合并为 TS 文件保留
+    if !remuxed && init_files.is_empty() && remux_to_mp4 {
+        let inputs = all_segment_files
+            .iter()
+            .map(|(index, segment_file)| RemuxInput {
+                path: segment_file.clone(),
+                discontinuity: segments[*index].discontinuity,
+            })
+            .collect();
+        match remux_hls_segments(inputs, save_path.to_path_buf(), progress_key).await {
+            Ok(summary) => {
+                eprintln!(
+                    "转封装为MP4完成: {:?} {}x{}，音频: {}，时长 {:.1}s",
+                    summary.video_codec,
+                    summary.width,
+                    summary.height,
+                    summary.has_audio,
+                    summary.duration_secs
+                );
+                remuxed = true;
             }
-
-            // 更新进度为继续状态
-            {
-                let tracker = get_progress_tracker();
-                let mut progress_map = tracker.lock().await;
-                if let Some(progress) = progress_map.get_mut(progress_key) {
-                    progress.status = "downloading".to_string();
-                    progress.current_file =
-                        format!("下载片段 {}/{}", index + 1, segment_urls.len());
-                }
+            Err(e) => {
+                eprintln!("转封装为MP4失败，保留原始TS文件: {}", e);
+                output_path = save_path.with_extension("ts");
             }
         }
     }
 
-    // 合并所有片段为单个视频文件
-    eprintln!("合并视频片段...");
-
-    // 收集所有分片文件路径（包括已存在的和新下载的），按索引排序
-    let mut all_segment_files = Vec::new();
+    if !remuxed {
+        merge_segment_files(
+            &output_path,
+            &all_segment_files,
+            segments,
+            &init_files,
+            progress_key,
+        )
+        .await?;
+    }
+    let file_size = fs::metadata(&output_path)
+        .await
+        .map(|metadata| metadata.len())
+        .unwrap_or(total_downloaded);
 
-    // 添加已存在的分片
-    for (index, file_path, _) in &existing_segments {
-        all_segment_files.push((*index, file_path.clone()));
+    // 校验输出文件，失败时删除输出但保留分片和清单，修复问题后可以重新合并
+    {
+        let tracker = get_progress_tracker();
+        let mut progress_map = tracker.lock().await;
+        if let Some(progress) = progress_map.get_mut(progress_key) {
+            progress.status = "verifying".to_string();
+            progress.current_file = "正在校验视频文件...".to_string();
+        }
     }
+    let expectation = MergeExpectation {
+        part_sizes: if remuxed {
+            Vec::new()
+        } else {
+            merged_part_sizes(&manifest, segments, &init_files).await
+        },
+        source_size: manifest.completed_bytes(),
+        playlist_duration: segments.iter().map(|segment| segment.duration).sum(),
+        discontinuous: discontinuities > 0,
+    };
+    let media = match verify_merged_output(&output_path, expectation).await {
+        Ok(media) => media,
+        Err(e) => {
+            let _ = fs::remove_file(&output_path).await;
+            return Err(format!("视频校验失败（已保留分片）: {}", e).into());
+        }
+    };
+    eprintln!("视频校验通过: {}", output_path.display());
 
-    // 添加新下载的分片
-    for (i, file_path) in segment_files.iter().enumerate() {
-        let actual_index = start_index + i;
-        all_segment_files.push((actual_index, file_path.clone()));
+    // 更新为完成状态
+    {
+        let tracker = get_progress_tracker();
+        let mut progress_map = tracker.lock().await;
+        if let Some(progress) = progress_map.get_mut(progress_key) {
+            progress.percent = 100.0;
+            progress.status = "completed".to_string();
+            progress.current_file = "下载完成".to_string();
+            progress.total_bytes = total_downloaded;
+        }
+    } // 清理临时文件
+    for (_, segment_file) in all_segment_files {
+        let _ = fs::remove_file(segment_file).await;
+    }
+    for init_file in init_files {
+        let _ = fs::remove_file(init_file).await;
     }
+    let _ = fs::remove_file(temp_dir.join("manifest.json")).await;
+    remove_local_playlist(temp_dir).await;
+    let _ = fs::remove_dir(temp_dir).await;
 
-    // 按索引排序
-    all_segment_files.sort_by_key(|&(index, _)| index);
+    eprintln!("视频合并完成，总大小: {}MB", file_size / 1024 / 1024);
+    Ok(VideoDownloadOutcome {
+        file_path: output_path,
+        file_size,
+        quality,
+        media: Some(media),
+    })
+}
 
-    eprintln!("准备合并 {} 个分片文件", all_segment_files.len());
+// 多连接分段下载直链视频，各分段写入临时目录中的独立文件，全部完成后按顺序拼接
+// 分段文件保留到拼接完成，服务器上的文件没有变化时下次从各分段已下载的位置继续
+#[allow(clippy::too_many_arguments)]
+async fn download_segmented(
+    client: &HttpClient,
+    url: &str,
+    save_path: &Path,
+    temp_dir: &Path,
+    support: &RangeSupport,
+    connections: usize,
+    progress_key: &str,
+    pause_key: &str,
+) -> Result<VideoDownloadOutcome, VideoDownloadError> {
+    let state = match PartsState::load(temp_dir, url, support).await {
+        Some(state) => state,
+        None => {
+            PartsState::remove(temp_dir).await;
+            let state = PartsState::plan(url, support, connections);
+            state.save(temp_dir).await?;
+            state
+        }
+    };
+    let total_size = state.total_size;
+    let sizes = state.downloaded_sizes(temp_dir).await;
+    let downloaded = AtomicU64::new(sizes.iter().sum());
+    eprintln!(
+        "使用 {} 个连接分段下载: {} bytes，已下载 {} bytes",
+        state.parts.len(),
+        total_size,
+        downloaded.load(Ordering::Relaxed)
+    );
+
+    let (state, downloaded) = (&state, &downloaded);
+    let downloads =
+        state
+            .parts
+            .iter()
+            .zip(sizes)
+            .enumerate()
+            .map(|(index, (part, existing))| async move {
+                download_part(
+                    client,
+                    url,
+                    &part_file_path(temp_dir, index),
+                    part,
+                    existing,
+                    state.if_range_value(),
+                    downloaded,
+                    total_size,
+                    progress_key,
+                    pause_key,
+                )
+                .await
+                .map_err(|e| e.map_message(|e| format!("下载分段{}失败: {}", index, e)))
+            });
+    // 任一分段失败时取消其余分段，已写入的数据保留用于续传
+    futures_util::future::try_join_all(downloads).await?;
 
     // 更新进度为合并阶段
     {
         let tracker = get_progress_tracker();
         let mut progress_map = tracker.lock().await;
         if let Some(progress) = progress_map.get_mut(progress_key) {
-            progress.percent = 80.0;
             progress.status = "merging".to_string();
-            progress.current_file = "正在合并视频片段...".to_string();
+            progress.current_file = "正在合并视频分段...".to_string();
         }
     }
 
     let mut output_file = fs::File::create(save_path)
+        .await
+        .map_err(|e| format!("创建文件失败: {}", e))?;
+    for index in 0..state.parts.len() {
+        copy_file_into(&part_file_path(temp_dir, index), &mut output_file)
+            .await
+            .map_err(|e| format!("合并分段{}失败: {}", index, e))?;
+    }
+    output_file
+        .flush()
+        .await
+        .map_err(|e| format!("刷新文件失败: {}", e))?;
+    drop(output_file);
+
+    let file_size = fs::metadata(save_path)
+        .await
+        .map(|metadata| metadata.len())
+        .map_err(|e| format!("读取文件信息失败: {}", e))?;
+    if file_size != total_size {
+        return Err(format!("下载的文件不完整: {}/{} bytes", file_size, total_size).into());
+    }
+    PartsState::remove(temp_dir).await;
+
+    eprintln!("分段下载完成: {} bytes", file_size);
+    Ok(VideoDownloadOutcome {
+        file_path: save_path.to_path_buf(),
+        file_size,
+        quality: None,
+        media: None,
+    })
+}
+
+// 下载一个分段的剩余部分并追加到分段文件，所有分段共用已下载字节数更新进度
+#[allow(clippy::too_many_arguments)]
+async fn download_part(
+    client: &HttpClient,
+    url: &str,
+    part_path: &Path,
+    part: &PartRange,
+    existing: u64,
+    if_range: Option<&str>,
+    downloaded: &AtomicU64,
+    total_size: u64,
+    progress_key: &str,
+    pause_key: &str,
+) -> Result<(), VideoDownloadError> {
+    let mut remaining = part.len() - existing;
+    if remaining == 0 {
+        return Ok(());
+    }
+
+    wait_while_paused(progress_key, pause_key).await;
+    let mut response = request_part(client, url, part.start + existing, part.end, if_range)
+        .await
+        .map_err(VideoDownloadError::Network)?;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(part_path)
+        .await
+        .map_err(|e| format!("打开分段文件失败: {}", e))?;
+
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| VideoDownloadError::Network(format!("读取数据块失败: {}", e)))?
+    {
+        wait_while_paused(progress_key, pause_key).await;
+
+        // 服务器多返回的数据属于下一个分段，丢弃
+        let chunk = &chunk[..chunk.len().min(remaining as usize)];
+        file.write_all(chunk)
+            .await
+            .map_err(|e| format!("写入分段文件失败: {}", e))?;
+        remaining -= chunk.len() as u64;
+        let total_downloaded =
+            downloaded.fetch_add(chunk.len() as u64, Ordering::Relaxed) + chunk.len() as u64;
+
+        // 更新进度
+        {
+            let tracker = get_progress_tracker();
+            let mut progress_map = tracker.lock().await;
+            if let Some(progress) = progress_map.get_mut(progress_key) {
+                progress.downloaded_bytes = total_downloaded;
+                progress.total_bytes = total_size;
+                progress.percent = (total_downloaded as f64 / total_size as f64) * 100.0;
+                if progress.status != "paused" {
+                    progress.status = "downloading".to_string();
+                    progress.current_file = format!("下载进度: {:.1}%", progress.percent);
+                }
+            }
+        }
+        if remaining == 0 {
+            break;
+        }
+    }
+    file.flush()
+        .await
+        .map_err(|e| format!("写入分段文件失败: {}", e))?;
+
+    if remaining > 0 {
+        return Err(VideoDownloadError::Network(format!(
+            "连接提前断开，还差 {} bytes",
+            remaining
+        )));
+    }
+    Ok(())
+}
+
+// 更新本地播放列表，失败时只影响边下边播，不中断下载
+async fn update_local_playlist(
+    temp_dir: &Path,
+    segments: &[HlsSegment],
+    init_files: &[PathBuf],
+    ready_segments: usize,
+) {
+    if let Err(e) = write_local_playlist(temp_dir, segments, init_files, ready_segments).await {
+        eprintln!("{}", e);
+    }
+}
+
+// 边下载边写入分片文件，加密片段解密后再保存，合并时不需要再处理
+// 先写入 .part 文件，完整写完后再重命名，返回分片大小和校验和
+async fn download_segment_file(
+    client: &HttpClient,
+    segment: &HlsSegment,
+    key_cache: &KeyCache,
+    segment_path: &Path,
+) -> Result<(u64, String), VideoDownloadError> {
+    let mut reader = open_segment(client, segment, key_cache)
+        .await
+        .map_err(VideoDownloadError::Network)?;
+
+    let part_path = segment_path.with_extension("ts.part");
+    let mut file = fs::File::create(&part_path)
+        .await
+        .map_err(|e| format!("创建片段文件失败: {}", e))?;
+    let mut checksum = Checksum::default();
+    let mut size = 0u64;
+    while let Some(chunk) = reader
+        .next_chunk()
+        .await
+        .map_err(VideoDownloadError::Network)?
+    {
+        checksum.update(&chunk);
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| format!("写入片段文件失败: {}", e))?;
+        size += chunk.len() as u64;
+    }
+    file.flush()
+        .await
+        .map_err(|e| format!("写入片段文件失败: {}", e))?;
+    drop(file);
+
+    fs::rename(&part_path, segment_path)
+        .await
+        .map_err(|e| format!("写入片段文件失败: {}", e))?;
+    Ok((size, checksum.finish()))
+}
+
+// 在后台线程中将 TS 分片转封装为 MP4，转封装进度占合并阶段的 20%
+async fn remux_hls_segments(
+    inputs: Vec<RemuxInput>,
+    save_path: PathBuf,
+    progress_key: &str,
+) -> Result<RemuxSummary, String> {
+    let progress_key = progress_key.to_string();
+    tokio::task::spawn_blocking(move || {
+        remux_ts_to_mp4(&inputs, &save_path, |done, total| {
+            let mut progress_map = get_progress_tracker().blocking_lock();
+            if let Some(progress) = progress_map.get_mut(&progress_key) {
+                progress.percent = 80.0 + (done as f64 / total as f64) * 20.0;
+                progress.current_file = format!("转封装片段 {}/{}", done, total);
+            }
+        })
+    })
+    .await
+    .map_err(|e| format!("转封装任务异常退出: {}", e))?
+}
+
+// 按索引顺序直接拼接分片到文件
+async fn merge_segment_files(
+    output_path: &Path,
+    all_segment_files: &[(usize, PathBuf)],
+    segments: &[HlsSegment],
+    init_files: &[PathBuf],
+    progress_key: &str,
+) -> Result<(), String> {
+    let mut output_file = fs::File::create(output_path)
         .await
         .map_err(|e| format!("创建输出文件失败: {}", e))?;
+    write_merged_segments(
+        &mut output_file,
+        all_segment_files,
+        segments,
+        init_files,
+        progress_key,
+    )
+    .await
+}
+
+// 按索引顺序写出分片数据，fMP4 流在初始化片段变化处写入新的初始化片段
+async fn write_merged_segments<W: AsyncWrite + Unpin>(
+    output_file: &mut W,
+    all_segment_files: &[(usize, PathBuf)],
+    segments: &[HlsSegment],
+    init_files: &[PathBuf],
+    progress_key: &str,
+) -> Result<(), String> {
+    let mut written_init = None;
     for (file_index, (segment_index, segment_file)) in all_segment_files.iter().enumerate() {
-        let segment_data = fs::read(segment_file)
-            .await
-            .map_err(|e| format!("读取片段文件失败: {}", e))?;
+        // 初始化片段变化时（如不连续处切换编码参数）先写入新的初始化片段
+        let init_section = segments[*segment_index].init_section;
+        if let Some(init_index) = init_section.filter(|_| init_section != written_init) {
+            copy_file_into(&init_files[init_index], output_file)
+                .await
+                .map_err(|e| format!("合并初始化片段失败: {}", e))?;
+            written_init = init_section;
+        }
 
-        output_file
-            .write_all(&segment_data)
+        copy_file_into(segment_file, output_file)
             .await
-            .map_err(|e| format!("写入合并文件失败: {}", e))?;
+            .map_err(|e| format!("合并片段文件失败: {}", e))?;
 
         // 更新合并进度
         {
@@ -649,65 +1382,48 @@ async fn download_hls_stream(
     output_file
         .flush()
         .await
-        .map_err(|e| format!("刷新输出文件失败: {}", e))?;
-
-    // 更新为完成状态
-    {
-        let tracker = get_progress_tracker();
-        let mut progress_map = tracker.lock().await;
-        if let Some(progress) = progress_map.get_mut(progress_key) {
-            progress.percent = 100.0;
-            progress.status = "completed".to_string();
-            progress.current_file = "下载完成".to_string();
-            progress.total_bytes = total_downloaded;
-        }
-    } // 清理临时文件
-    for (_, segment_file) in all_segment_files {
-        let _ = fs::remove_file(segment_file).await;
-    }
-    let _ = fs::remove_dir(temp_dir).await;
-
-    eprintln!("视频合并完成，总大小: {}MB", total_downloaded / 1024 / 1024);
-    Ok(total_downloaded)
+        .map_err(|e| format!("刷新输出文件失败: {}", e))
 }
 
-// 获取基础URL
-fn get_base_url(url: &str) -> String {
-    if let Some(last_slash) = url.rfind('/') {
-        url[..last_slash + 1].to_string()
-    } else {
-        String::new()
+// 按 write_merged_segments 的写入顺序计算直接拼接时各部分的大小
+async fn merged_part_sizes(
+    manifest: &SegmentManifest,
+    segments: &[HlsSegment],
+    init_files: &[PathBuf],
+) -> Vec<u64> {
+    let mut sizes = Vec::new();
+    let mut written_init = None;
+    for (record, segment) in manifest.segments.iter().zip(segments) {
+        let init_section = segment.init_section;
+        if let Some(init_index) = init_section.filter(|_| init_section != written_init) {
+            let init_size = fs::metadata(&init_files[init_index])
+                .await
+                .map(|metadata| metadata.len())
+                .unwrap_or(0);
+            sizes.push(init_size);
+            written_init = init_section;
+        }
+        sizes.push(record.size);
     }
+    sizes
 }
 
-// 解析m3u8文件，提取视频片段URL
-fn parse_m3u8_segments(content: &str, base_url: &str) -> Result<Vec<String>, String> {
-    let mut segments = Vec::new();
-
-    for line in content.lines() {
-        let line = line.trim();
-
-        // 跳过注释行和空行
-        if line.starts_with('#') || line.is_empty() {
-            continue;
-        }
-
-        // 检查是否是相对URL还是绝对URL
-        let segment_url = if line.starts_with("http") {
-            line.to_string()
-        } else {
-            format!("{}{}", base_url, line)
-        };
-
-        segments.push(segment_url);
-    }
-    Ok(segments)
+// 用固定大小的缓冲区把文件内容复制到输出中
+async fn copy_file_into<W: AsyncWrite + Unpin>(path: &Path, output: &mut W) -> Result<(), String> {
+    let file = fs::File::open(path)
+        .await
+        .map_err(|e| format!("读取 {} 失败: {}", path.display(), e))?;
+    let mut reader = BufReader::with_capacity(MERGE_BUFFER_SIZE, file);
+    tokio::io::copy_buf(&mut reader, output)
+        .await
+        .map_err(|e| format!("写入失败: {}", e))?;
+    Ok(())
 }
 
 #[tauri::command]
 pub async fn get_cartoon_download_progress(
-    cartoon_uuid: String,
-    chapter_uuid: String,
+    cartoon_uuid: SafeSegment,
+    chapter_uuid: SafeSegment,
     app_handle: AppHandle,
 ) -> Result<CartoonDownloadProgress, String> {
     // 先检查进度跟踪器中是否有实时进度
@@ -726,20 +1442,15 @@ pub async fn get_cartoon_download_progress(
     }
 
     // 如果进度跟踪器中没有，检查是否已完成下载
-    let resource_dir = app_handle
-        .path()
-        .resource_dir()
-        .map_err(|e| format!("获取资源目录失败: {}", e))?;
+    let downloads_path = get_downloads_path(&app_handle).await?;
 
     // 检查两个可能的路径：新的 cartoons 和旧的 anime（向后兼容）
     let possible_paths = vec![
-        resource_dir
-            .join("downloads")
+        downloads_path
             .join("cartoons")
             .join(&cartoon_uuid)
             .join(&chapter_uuid),
-        resource_dir
-            .join("downloads")
+        downloads_path
             .join("anime")
             .join(&cartoon_uuid)
             .join(&chapter_uuid),
@@ -772,25 +1483,20 @@ pub async fn get_cartoon_download_progress(
 
 #[tauri::command]
 pub async fn delete_downloaded_cartoon_chapter(
-    cartoon_uuid: String,
-    chapter_uuid: String,
+    cartoon_uuid: SafeSegment,
+    chapter_uuid: SafeSegment,
     app_handle: AppHandle,
 ) -> Result<DeleteChapterResult, String> {
     // 获取应用资源目录
-    let resource_dir = app_handle
-        .path()
-        .resource_dir()
-        .map_err(|e| format!("获取资源目录失败: {}", e))?;
+    let downloads_path = get_downloads_path(&app_handle).await?;
 
     // 检查两个可能的路径：新的 cartoons 和旧的 anime（向后兼容）
     let possible_paths = vec![
-        resource_dir
-            .join("downloads")
+        downloads_path
             .join("cartoons")
             .join(&cartoon_uuid)
             .join(&chapter_uuid),
-        resource_dir
-            .join("downloads")
+        downloads_path
             .join("anime")
             .join(&cartoon_uuid)
             .join(&chapter_uuid),
@@ -798,14 +1504,19 @@ pub async fn delete_downloaded_cartoon_chapter(
 
     for chapter_path in possible_paths {
         if chapter_path.exists() {
-            // 删除整个章节目录
-            fs::remove_dir_all(&chapter_path)
+            // 移动章节目录到回收站
+            let chapter_name = read_display_name(&chapter_path.join("info.json"), "chapter_name")
                 .await
-                .map_err(|e| format!("删除章节目录失败: {}", e))?;
+                .unwrap_or_else(|| chapter_uuid.to_string());
+            let trash_item =
+                move_to_trash(&app_handle, &chapter_path, "cartoon_chapter", &chapter_name)
+                    .await
+                    .map_err(|e| format!("删除章节目录失败: {}", e))?;
 
             return Ok(DeleteChapterResult {
                 success: true,
-                message: "章节删除成功".to_string(),
+                message: "章节已移至回收站".to_string(),
+                trash_id: Some(trash_item.id),
             });
         }
     }
@@ -815,37 +1526,32 @@ pub async fn delete_downloaded_cartoon_chapter(
 
 #[tauri::command]
 pub async fn delete_local_cartoon(
-    cartoon_uuid: String,
+    cartoon_uuid: SafeSegment,
     app_handle: AppHandle,
 ) -> Result<DeleteChapterResult, String> {
     // 获取应用资源目录
-    let resource_dir = app_handle
-        .path()
-        .resource_dir()
-        .map_err(|e| format!("获取资源目录失败: {}", e))?;
+    let downloads_path = get_downloads_path(&app_handle).await?;
 
     // 检查两个可能的路径：新的 cartoons 和旧的 anime（向后兼容）
     let possible_paths = vec![
-        resource_dir
-            .join("downloads")
-            .join("cartoons")
-            .join(&cartoon_uuid),
-        resource_dir
-            .join("downloads")
-            .join("anime")
-            .join(&cartoon_uuid),
+        downloads_path.join("cartoons").join(&cartoon_uuid),
+        downloads_path.join("anime").join(&cartoon_uuid),
     ];
 
     for cartoon_path in possible_paths {
         if cartoon_path.exists() {
-            // 删除整个动画目录
-            fs::remove_dir_all(&cartoon_path)
+            // 移动整个动画目录到回收站
+            let cartoon_name = read_display_name(&cartoon_path.join("cartoon_detail.json"), "name")
+                .await
+                .unwrap_or_else(|| cartoon_uuid.to_string());
+            let trash_item = move_to_trash(&app_handle, &cartoon_path, "cartoon", &cartoon_name)
                 .await
                 .map_err(|e| format!("删除动画目录失败: {}", e))?;
 
             return Ok(DeleteChapterResult {
                 success: true,
-                message: "动画删除成功".to_string(),
+                message: "动画已移至回收站".to_string(),
+                trash_id: Some(trash_item.id),
             });
         }
     }
@@ -860,17 +1566,14 @@ pub async fn get_downloaded_cartoon_list(
     println!("开始获取已下载的动画列表");
 
     // 获取应用资源目录
-    let resource_dir = app_handle
-        .path()
-        .resource_dir()
-        .map_err(|e| format!("获取资源目录失败: {}", e))?;
+    let downloads_path = get_downloads_path(&app_handle).await?;
 
     let mut cartoon_list = Vec::new();
 
     // 检查两个可能的下载目录：新的 cartoons 和旧的 anime（向后兼容）
     let download_dirs = vec![
-        resource_dir.join("downloads").join("cartoons"),
-        resource_dir.join("downloads").join("anime"),
+        downloads_path.join("cartoons"),
+        downloads_path.join("anime"),
     ];
 
     for download_dir in download_dirs {
@@ -1118,16 +1821,12 @@ async fn find_cartoon_cover_file(cartoon_path: &PathBuf) -> Option<String> {
 #[tauri::command]
 pub async fn open_local_video_directory(
     app_handle: AppHandle,
-    cartoon_uuid: String,
-    chapter_uuid: String,
+    cartoon_uuid: SafeSegment,
+    chapter_uuid: SafeSegment,
 ) -> Result<Value, String> {
-    let resource_dir = app_handle
-        .path()
-        .resource_dir()
-        .map_err(|e| format!("获取资源目录失败: {}", e))?;
+    let downloads_path = get_downloads_path(&app_handle).await?;
 
-    let chapter_path = resource_dir
-        .join("downloads")
+    let chapter_path = downloads_path
         .join("cartoons")
         .join(&cartoon_uuid)
         .join(&chapter_uuid);
@@ -1160,17 +1859,11 @@ pub async fn open_local_video_directory(
 #[tauri::command]
 pub async fn get_local_cartoon_detail(
     app_handle: AppHandle,
-    cartoon_uuid: String,
+    cartoon_uuid: SafeSegment,
 ) -> Result<Value, String> {
-    let resource_dir = app_handle
-        .path()
-        .resource_dir()
-        .map_err(|e| format!("获取资源目录失败: {}", e))?;
+    let downloads_path = get_downloads_path(&app_handle).await?;
 
-    let cartoon_path = resource_dir
-        .join("downloads")
-        .join("cartoons")
-        .join(&cartoon_uuid);
+    let cartoon_path = downloads_path.join("cartoons").join(&cartoon_uuid);
 
     let detail_file = cartoon_path.join("cartoon_detail.json");
 
@@ -1188,20 +1881,60 @@ pub async fn get_local_cartoon_detail(
     Ok(detail)
 }
 
+// 读取下载完成的章节信息和视频文件路径，视频文件必须位于章节目录内
+pub(crate) async fn read_completed_chapter(
+    chapter_path: &Path,
+) -> Option<(CartoonChapterInfo, PathBuf)> {
+    let content = fs::read_to_string(chapter_path.join("info.json"))
+        .await
+        .ok()?;
+    let mut info = serde_json::from_str::<CartoonChapterInfo>(&content).ok()?;
+    if !info.is_completed || info.video_file.is_empty() {
+        return None;
+    }
+
+    let video_path = join_relative(chapter_path, &info.video_file);
+    ensure_within(chapter_path, &video_path).ok()?;
+    if !video_path.is_file() {
+        return None;
+    }
+    if info.chapter_uuid.is_empty() {
+        info.chapter_uuid = chapter_path.file_name()?.to_string_lossy().to_string();
+    }
+    Some((info, video_path))
+}
+
+// 动画目录下所有下载完成的剧集，按章节序号排序
+pub(crate) async fn list_completed_chapters(
+    cartoon_path: &Path,
+) -> Vec<(CartoonChapterInfo, PathBuf)> {
+    let mut chapters = Vec::new();
+    if let Ok(mut entries) = fs::read_dir(cartoon_path).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if let Some(chapter) = read_completed_chapter(&entry.path()).await {
+                chapters.push(chapter);
+            }
+        }
+    }
+    chapters.sort_by(|(a, _), (b, _)| {
+        chapter_cmp(
+            a.chapter_index,
+            &a.chapter_name,
+            b.chapter_index,
+            &b.chapter_name,
+        )
+    });
+    chapters
+}
+
 #[tauri::command]
 pub async fn get_local_cartoon_chapters(
     app_handle: AppHandle,
-    cartoon_uuid: String,
+    cartoon_uuid: SafeSegment,
 ) -> Result<Vec<Value>, String> {
-    let resource_dir = app_handle
-        .path()
-        .resource_dir()
-        .map_err(|e| format!("获取资源目录失败: {}", e))?;
+    let downloads_path = get_downloads_path(&app_handle).await?;
 
-    let cartoon_path = resource_dir
-        .join("downloads")
-        .join("cartoons")
-        .join(&cartoon_uuid);
+    let cartoon_path = downloads_path.join("cartoons").join(&cartoon_uuid);
 
     if !cartoon_path.exists() {
         return Ok(Vec::new());
@@ -1239,6 +1972,20 @@ pub async fn get_local_cartoon_chapters(
         match fs::read_to_string(&info_file).await {
             Ok(content) => match serde_json::from_str::<Value>(&content) {
                 Ok(mut chapter_info) => {
+                    // 缩略图存在时返回完整路径，供前端转换为可显示的地址
+                    let thumbnail_path = chapter_info
+                        .get("thumbnail")
+                        .and_then(|v| v.as_str())
+                        .map(|thumbnail| join_relative(&chapter_path, thumbnail))
+                        .filter(|path| path.exists());
+                    if let (Some(path), Some(obj)) = (thumbnail_path, chapter_info.as_object_mut())
+                    {
+                        obj.insert(
+                            "thumbnail_path".to_string(),
+                            Value::String(path.to_string_lossy().to_string()),
+                        );
+                    }
+
                     // 检查是否下载完成
                     if let Some(is_completed) =
                         chapter_info.get("is_completed").and_then(|v| v.as_bool())
@@ -1298,10 +2045,7 @@ pub async fn get_local_cartoon_chapters(
                                 );
                                 // 为旧版本数据添加 is_completed 标记
                                 if let Some(obj) = chapter_info.as_object_mut() {
-                                    obj.insert(
-                                        "is_completed".to_string(),
-                                        Value::Bool(false),
-                                    );
+                                    obj.insert("is_completed".to_string(), Value::Bool(false));
                                 }
                             }
                         }
@@ -1318,11 +2062,14 @@ pub async fn get_local_cartoon_chapters(
         }
     }
 
-    // 按章节名称排序
+    // 按章节序号排序，与播放列表和本地视频服务的顺序一致
     chapters.sort_by(|a, b| {
-        let name_a = a.get("chapter_name").and_then(|v| v.as_str()).unwrap_or("");
-        let name_b = b.get("chapter_name").and_then(|v| v.as_str()).unwrap_or("");
-        name_a.cmp(name_b)
+        chapter_cmp(
+            json_chapter_index(a),
+            a["chapter_name"].as_str().unwrap_or(""),
+            json_chapter_index(b),
+            b["chapter_name"].as_str().unwrap_or(""),
+        )
     });
 
     Ok(chapters)
@@ -1333,10 +2080,7 @@ pub async fn debug_find_downloaded_files(
     app_handle: AppHandle,
     cartoon_uuid: String,
 ) -> Result<Vec<String>, String> {
-    let resource_dir = app_handle
-        .path()
-        .resource_dir()
-        .map_err(|e| format!("获取资源目录失败: {}", e))?;
+    let downloads_path = get_downloads_path(&app_handle).await?;
 
     let mut found_paths = Vec::new();
 
@@ -1364,7 +2108,7 @@ pub async fn debug_find_downloaded_files(
         Ok(())
     }
 
-    let _ = search_recursive(&resource_dir, &cartoon_uuid, &mut found_paths);
+    let _ = search_recursive(&downloads_path, &cartoon_uuid, &mut found_paths);
 
     println!("搜索动画UUID {} 的下载文件:", cartoon_uuid);
     for path in &found_paths {
@@ -0,0 +1,378 @@
+use crate::download::hls::{
+    fetch_media_playlist, is_hls_content_type, is_hls_url, parse_media_playlist, HlsPlaylist,
+};
+use crate::download::http_client::{http_client, HttpClient};
+use crate::download::resume::{header_string, parse_content_range};
+use crate::download::settings::{load_download_settings, VideoQualityPreference};
+use crate::download::types::ImageInfo;
+use futures_util::StreamExt;
+use reqwest::header::{CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE};
+use reqwest::StatusCode;
+use serde::Serialize;
+use tauri::AppHandle;
+
+// 并发探测的请求数，以及每批图片最多探测的数量（超过时均匀抽样）
+const PROBE_CONCURRENCY: usize = 8;
+const MAX_IMAGE_PROBES: usize = 64;
+// HLS 分片抽样数量，成功数不足时改用码率估算
+const SEGMENT_SAMPLES: usize = 6;
+const MIN_SEGMENT_SAMPLES: usize = 3;
+// 所有请求都失败时按经验值估算单张图片大小
+const FALLBACK_IMAGE_SIZE: u64 = 400 * 1024;
+
+/// 下载大小的估算结果
+#[derive(Debug, Serialize, Clone)]
+pub struct SizeEstimate {
+    pub total_bytes: u64,
+    pub confidence: String, // "high"：实际大小；"medium"：抽样或码率推算；"low"：经验值或部分失败
+    pub method: String,     // 使用的估算方式，多种方式混合时以 "+" 连接
+    pub measured_items: usize, // 实际取得大小的图片或分片数
+    pub total_items: usize,
+}
+
+impl SizeEstimate {
+    fn new(
+        total_bytes: u64,
+        confidence: &str,
+        method: &str,
+        measured: usize,
+        total: usize,
+    ) -> Self {
+        SizeEstimate {
+            total_bytes,
+            confidence: confidence.to_string(),
+            method: method.to_string(),
+            measured_items: measured,
+            total_items: total,
+        }
+    }
+
+    // 合并多个估算结果，可信度取最低的一个
+    fn combine(estimates: Vec<SizeEstimate>) -> SizeEstimate {
+        let mut methods: Vec<String> = Vec::new();
+        for estimate in &estimates {
+            if !methods.contains(&estimate.method) {
+                methods.push(estimate.method.clone());
+            }
+        }
+        let confidence = ["low", "medium", "high"]
+            .into_iter()
+            .find(|level| estimates.iter().any(|e| e.confidence == *level))
+            .unwrap_or("high");
+
+        SizeEstimate {
+            total_bytes: estimates.iter().map(|e| e.total_bytes).sum(),
+            confidence: confidence.to_string(),
+            method: methods.join("+"),
+            measured_items: estimates.iter().map(|e| e.measured_items).sum(),
+            total_items: estimates.iter().map(|e| e.total_items).sum(),
+        }
+    }
+}
+
+/// 估算下载大小
+///
+/// `image_lists` 为若干漫画章节的图片列表，`video_urls` 为若干动画剧集的视频地址，
+/// 两者可以同时提供，结果为总和。
+#[tauri::command]
+pub async fn estimate_download_size(
+    app_handle: AppHandle,
+    image_lists: Option<Vec<Vec<ImageInfo>>>,
+    video_urls: Option<Vec<String>>,
+) -> Result<SizeEstimate, String> {
+    let image_lists = image_lists.unwrap_or_default();
+    let video_urls = video_urls.unwrap_or_default();
+    if image_lists.is_empty() && video_urls.is_empty() {
+        return Err("没有需要估算的内容".to_string());
+    }
+
+    let client = http_client(&app_handle);
+
+    let mut estimates = Vec::new();
+    for images in &image_lists {
+        estimates.push(estimate_images(&client, images).await);
+    }
+    if !video_urls.is_empty() {
+        let settings = load_download_settings(&app_handle).await?;
+        for video_url in &video_urls {
+            // 单个视频估算失败时不影响其它项目，记为低可信度继续
+            let estimate = match estimate_video(&client, video_url, &settings.video_quality).await {
+                Ok(estimate) => estimate,
+                Err(e) => {
+                    eprintln!("估算视频大小失败 {}: {}", video_url, e);
+                    SizeEstimate::new(0, "low", "unavailable", 0, 1)
+                }
+            };
+            estimates.push(estimate);
+        }
+    }
+
+    Ok(SizeEstimate::combine(estimates))
+}
+
+// 用 HEAD 请求取得图片大小；图片过多时均匀抽样后按平均值推算
+async fn estimate_images(client: &HttpClient, images: &[ImageInfo]) -> SizeEstimate {
+    if images.is_empty() {
+        return SizeEstimate::new(0, "high", "content_length", 0, 0);
+    }
+
+    let urls: Vec<&str> = sample_evenly(images.len(), MAX_IMAGE_PROBES)
+        .into_iter()
+        .map(|index| images[index].url.as_str())
+        .collect();
+    let sizes = fetch_sizes(client, &urls).await;
+
+    let total = images.len();
+    if sizes.is_empty() {
+        return SizeEstimate::new(
+            FALLBACK_IMAGE_SIZE * total as u64,
+            "low",
+            "fallback",
+            0,
+            total,
+        );
+    }
+    if sizes.len() == total {
+        return SizeEstimate::new(sizes.iter().sum(), "high", "content_length", total, total);
+    }
+
+    let average = sizes.iter().sum::<u64>() / sizes.len() as u64;
+    // 抽样全部成功时可信度为中等，有请求失败时为低
+    let confidence = if sizes.len() == urls.len() {
+        "medium"
+    } else {
+        "low"
+    };
+    SizeEstimate::new(
+        average * total as u64,
+        confidence,
+        "sampling",
+        sizes.len(),
+        total,
+    )
+}
+
+// 直链视频取 Content-Length；HLS 按字节范围、抽样分片或码率×时长估算
+async fn estimate_video(
+    client: &HttpClient,
+    url: &str,
+    preference: &VideoQualityPreference,
+) -> Result<SizeEstimate, String> {
+    if !is_hls_url(url) {
+        match fetch_size(client, url).await {
+            Some(ResourceSize::Bytes(size)) => {
+                return Ok(SizeEstimate::new(size, "high", "content_length", 1, 1));
+            }
+            // 地址没有 .m3u8 后缀但返回的是播放列表
+            Some(ResourceSize::Playlist) => {}
+            None => return Err(format!("无法获取视频大小: {}", url)),
+        }
+    }
+
+    let (content, playlist_url, variant) = fetch_media_playlist(client, url, preference).await?;
+    let playlist = parse_media_playlist(&content, &playlist_url)?;
+    let segments = &playlist.segments;
+    if segments.is_empty() {
+        return Err("未找到视频片段".to_string());
+    }
+    let total = segments.len();
+    let duration: f64 = segments.iter().map(|segment| segment.duration).sum();
+
+    // 全部分片都带有字节范围时，大小就是各范围长度之和
+    if let Some(size) = byte_range_total(&playlist) {
+        return Ok(SizeEstimate::new(size, "high", "byte_range", total, total));
+    }
+
+    // 均匀抽取几个分片，按平均每秒字节数乘以总时长推算
+    let samples = sample_evenly(total, SEGMENT_SAMPLES);
+    let urls: Vec<&str> = samples
+        .iter()
+        .map(|index| segments[*index].url.as_str())
+        .collect();
+    let sizes = fetch_sizes(client, &urls).await;
+    if sizes.len() >= MIN_SEGMENT_SAMPLES.min(total) {
+        let sampled_duration: f64 = samples.iter().map(|index| segments[*index].duration).sum();
+        let sampled_bytes: u64 = sizes.iter().sum();
+        let estimate = if duration > 0.0 && sampled_duration > 0.0 && sizes.len() == urls.len() {
+            (sampled_bytes as f64 / sampled_duration * duration) as u64
+        } else {
+            sampled_bytes / sizes.len() as u64 * total as u64
+        };
+        let confidence = if sizes.len() == total {
+            "high"
+        } else {
+            "medium"
+        };
+        return Ok(SizeEstimate::new(
+            estimate,
+            confidence,
+            "sampling",
+            sizes.len(),
+            total,
+        ));
+    }
+
+    // 分片不支持 HEAD 时按主播放列表中的码率估算（BANDWIDTH 为峰值，结果偏大）
+    match variant.and_then(|variant| variant.bandwidth) {
+        Some(bandwidth) if duration > 0.0 => Ok(SizeEstimate::new(
+            (bandwidth as f64 * duration / 8.0) as u64,
+            "low",
+            "bandwidth",
+            0,
+            total,
+        )),
+        _ => Err("无法估算视频大小：分片大小和码率都不可用".to_string()),
+    }
+}
+
+fn byte_range_total(playlist: &HlsPlaylist) -> Option<u64> {
+    let segments: Option<u64> = playlist
+        .segments
+        .iter()
+        .map(|segment| segment.byte_range.map(|range| range.length))
+        .sum();
+    let init_sections: Option<u64> = playlist
+        .init_sections
+        .iter()
+        .map(|init| init.byte_range.map(|range| range.length))
+        .sum();
+    Some(segments? + init_sections.unwrap_or(0))
+}
+
+// 在 0..total 中均匀选出至多 count 个下标，包含首尾
+fn sample_evenly(total: usize, count: usize) -> Vec<usize> {
+    if total <= count {
+        return (0..total).collect();
+    }
+    let mut indices: Vec<usize> = (0..count)
+        .map(|i| i * (total - 1) / (count - 1).max(1))
+        .collect();
+    indices.dedup();
+    indices
+}
+
+enum ResourceSize {
+    Bytes(u64),
+    Playlist,
+}
+
+// 并发获取多个资源的大小，只返回成功取得的结果
+async fn fetch_sizes(client: &HttpClient, urls: &[&str]) -> Vec<u64> {
+    futures_util::stream::iter(urls.iter().copied())
+        .map(|url| fetch_size(client, url))
+        .buffer_unordered(PROBE_CONCURRENCY)
+        .filter_map(|size| async move {
+            match size {
+                Some(ResourceSize::Bytes(size)) => Some(size),
+                _ => None,
+            }
+        })
+        .collect()
+        .await
+}
+
+// 先发 HEAD 请求；服务器不支持或没有返回长度时，请求首字节并从 Content-Range 中读取总大小
+async fn fetch_size(client: &HttpClient, url: &str) -> Option<ResourceSize> {
+    let is_playlist = |response: &reqwest::Response| {
+        header_string(response.headers(), CONTENT_TYPE)
+            .is_some_and(|value| is_hls_content_type(&value))
+    };
+
+    if let Ok(response) = client.head(url).send().await {
+        if response.status().is_success() {
+            if is_playlist(&response) {
+                return Some(ResourceSize::Playlist);
+            }
+            // HEAD 响应没有正文，reqwest 的 content_length() 总是返回 0，需要直接读取响应头
+            if let Some(size) = header_string(response.headers(), CONTENT_LENGTH)
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .filter(|size| *size > 0)
+            {
+                return Some(ResourceSize::Bytes(size));
+            }
+        }
+    }
+
+    let response = client
+        .get(url)
+        .header(RANGE, "bytes=0-0")
+        .send()
+        .await
+        .ok()?;
+    if is_playlist(&response) {
+        return Some(ResourceSize::Playlist);
+    }
+    match response.status() {
+        StatusCode::PARTIAL_CONTENT => header_string(response.headers(), CONTENT_RANGE)
+            .and_then(|value| parse_content_range(&value))
+            .and_then(|(_, total)| total)
+            .map(ResourceSize::Bytes),
+        // 不支持范围请求时返回完整内容，只读取长度，不下载正文
+        status if status.is_success() => response.content_length().map(ResourceSize::Bytes),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::download::hls::{ByteRange, HlsSegment, InitSection};
+
+    fn segment(byte_range: Option<ByteRange>) -> HlsSegment {
+        HlsSegment {
+            url: "https://example.com/video.mp4".to_string(),
+            sequence: 0,
+            key: None,
+            byte_range,
+            init_section: None,
+            discontinuity: false,
+            duration: 4.0,
+        }
+    }
+
+    #[test]
+    fn sample_evenly_includes_both_ends() {
+        assert_eq!(sample_evenly(0, 6), Vec::<usize>::new());
+        assert_eq!(sample_evenly(4, 6), vec![0, 1, 2, 3]);
+        assert_eq!(sample_evenly(100, 6), vec![0, 19, 39, 59, 79, 99]);
+        assert_eq!(sample_evenly(10, 1), vec![0]);
+    }
+
+    #[test]
+    fn byte_range_total_requires_every_segment_range() {
+        let range = |offset, length| Some(ByteRange { offset, length });
+        let mut playlist = HlsPlaylist {
+            segments: vec![segment(range(0, 100)), segment(range(100, 250))],
+            init_sections: vec![InitSection {
+                url: "https://example.com/video.mp4".to_string(),
+                byte_range: range(0, 50),
+                key: None,
+            }],
+        };
+        assert_eq!(byte_range_total(&playlist), Some(400));
+
+        playlist.segments.push(segment(None));
+        assert_eq!(byte_range_total(&playlist), None);
+    }
+
+    #[test]
+    fn combine_sums_and_keeps_lowest_confidence() {
+        let combined = SizeEstimate::combine(vec![
+            SizeEstimate::new(100, "high", "content_length", 2, 2),
+            SizeEstimate::new(300, "medium", "sampling", 3, 10),
+            SizeEstimate::new(50, "high", "content_length", 1, 1),
+        ]);
+        assert_eq!(combined.total_bytes, 450);
+        assert_eq!(combined.confidence, "medium");
+        assert_eq!(combined.method, "content_length+sampling");
+        assert_eq!(combined.measured_items, 6);
+        assert_eq!(combined.total_items, 13);
+
+        let failed = SizeEstimate::combine(vec![
+            SizeEstimate::new(100, "high", "content_length", 1, 1),
+            SizeEstimate::new(0, "low", "unavailable", 0, 1),
+        ]);
+        assert_eq!(failed.confidence, "low");
+        assert_eq!(SizeEstimate::combine(Vec::new()).confidence, "high");
+    }
+}
@@ -0,0 +1,454 @@
+use crate::download::naming::{join_relative, resolve_collision, resolve_dir_collision};
+use crate::download::types::*;
+use crate::download::utils::*;
+use crate::path_guard::SafeSegment;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Component, Path, PathBuf};
+use tauri::AppHandle;
+use tokio::fs;
+
+// 导出目录中记录导出创建过哪些文件，只有这些文件允许在再次导出时被替换
+const EXPORT_RECORD_FILE: &str = ".library_export.json";
+
+/// 导出统计，在遍历过程中累加
+#[derive(Default)]
+struct ExportStats {
+    exported_files: usize,
+    skipped_files: usize,
+    linked_files: usize,
+    errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
+struct ExportRecord {
+    schema_version: u32,
+    files: BTreeSet<String>, // 相对导出目录的路径
+}
+
+/// 一次导出的上下文
+struct ExportContext {
+    target_root: PathBuf,
+    use_hardlink: bool,
+    record: ExportRecord,
+    stats: ExportStats,
+    used_names: HashSet<String>, // 本次导出已使用的相对路径（小写），漫画和动画共用
+}
+
+impl ExportContext {
+    // 为导出的目录或文件去重，`relative` 为相对导出目录、以 `/` 分隔的路径；
+    // 之前导出创建的文件，以及包含这些文件的目录可以沿用，不视为冲突
+    fn unique_path(&mut self, relative: &str, is_dir: bool) -> String {
+        let ExportContext {
+            target_root,
+            record,
+            used_names,
+            ..
+        } = self;
+        let is_own = |candidate: &str| {
+            let key = record_key(target_root, &join_relative(target_root, candidate));
+            record
+                .files
+                .iter()
+                .any(|file| Path::new(file).starts_with(&key))
+        };
+        if is_dir {
+            resolve_dir_collision(target_root, relative, used_names, is_own)
+        } else {
+            resolve_collision(target_root, relative, used_names, is_own)
+        }
+    }
+}
+
+/// 将下载库导出为便于浏览的目录结构
+///
+/// 漫画: `漫画名/分组/0001 - 章节名/001.jpg`
+/// 动画: `动画名/0001 - 集名.mp4`
+///
+/// 编号取自下载时保存的章节序号，后补下载的章节不会改变其他章节的编号；
+/// 旧版本下载的章节没有序号，只以名称命名。
+///
+/// mode 为 "copy" 或 "hardlink"，硬链接失败（如跨磁盘）时自动改为复制。
+/// 目标中已存在且大小一致的文件会被跳过，因此可以重复执行来同步新下载的内容；
+/// 不是由导出创建的文件不会被覆盖或删除。
+#[tauri::command]
+pub async fn export_library(
+    app_handle: AppHandle,
+    target_dir: String,
+    mode: String,
+    media_type: Option<String>,
+    owner_uuid: Option<SafeSegment>,
+) -> Result<LibraryExportResult, String> {
+    let use_hardlink = match mode.as_str() {
+        "copy" => false,
+        "hardlink" => true,
+        _ => return Err(format!("不支持的导出方式: {}", mode)),
+    };
+
+    let target_root = resolve_target_dir(&target_dir)?;
+    let downloads_path = get_downloads_path(&app_handle).await?;
+    let downloads_path = std::fs::canonicalize(&downloads_path).unwrap_or(downloads_path);
+    if target_root.starts_with(&downloads_path) {
+        return Err("不能导出到下载目录内".to_string());
+    }
+
+    fs::create_dir_all(&target_root)
+        .await
+        .map_err(|e| format!("创建导出目录失败: {}", e))?;
+
+    let mut context = ExportContext {
+        record: read_export_record(&target_root).await,
+        target_root,
+        use_hardlink,
+        stats: ExportStats::default(),
+        used_names: HashSet::new(),
+    };
+    let media_type = media_type.unwrap_or_else(|| "all".to_string());
+
+    if matches!(media_type.as_str(), "all" | "manga") {
+        let manga_root = get_manga_downloads_path(&app_handle).await?;
+        for manga_path in list_owner_dirs(&manga_root, owner_uuid.as_ref()).await {
+            let series_name =
+                unique_series_name(&manga_path, "manga_detail.json", &mut context.used_names).await;
+            export_manga(&manga_path, &series_name, &mut context).await;
+        }
+    }
+
+    if matches!(media_type.as_str(), "all" | "cartoon") {
+        let cartoon_root = get_cartoon_downloads_path(&app_handle).await?;
+        for cartoon_path in list_owner_dirs(&cartoon_root, owner_uuid.as_ref()).await {
+            let series_name = unique_series_name(
+                &cartoon_path,
+                "cartoon_detail.json",
+                &mut context.used_names,
+            )
+            .await;
+            export_cartoon(&cartoon_path, &series_name, &mut context).await;
+        }
+    }
+
+    if let Err(e) = write_export_record(&context.target_root, &context.record).await {
+        context.stats.errors.push(e);
+    }
+    let ExportContext {
+        target_root, stats, ..
+    } = context;
+
+    println!(
+        "导出完成: {} 个文件，跳过 {} 个，失败 {} 个",
+        stats.exported_files,
+        stats.skipped_files,
+        stats.errors.len()
+    );
+
+    Ok(LibraryExportResult {
+        success: stats.errors.is_empty(),
+        target_path: target_root.to_string_lossy().to_string(),
+        exported_files: stats.exported_files,
+        skipped_files: stats.skipped_files,
+        linked_files: stats.linked_files,
+        errors: stats.errors,
+    })
+}
+
+// 导出单部漫画
+async fn export_manga(manga_path: &Path, series_name: &str, context: &mut ExportContext) {
+    let Ok(mut group_entries) = fs::read_dir(manga_path).await else {
+        return;
+    };
+
+    while let Ok(Some(group_entry)) = group_entries.next_entry().await {
+        let group_path = group_entry.path();
+        if !group_path.is_dir() {
+            continue;
+        }
+
+        // 读取该分组下所有章节信息，编号取自各章节保存的序号
+        let mut chapters = Vec::new();
+        if let Ok(mut chapter_entries) = fs::read_dir(&group_path).await {
+            while let Ok(Some(chapter_entry)) = chapter_entries.next_entry().await {
+                let chapter_path = chapter_entry.path();
+                let Ok(content) = fs::read_to_string(chapter_path.join("info.json")).await else {
+                    continue;
+                };
+                if let Ok(info) = serde_json::from_str::<ChapterInfo>(&content) {
+                    chapters.push((chapter_path, info));
+                }
+            }
+        }
+        // 按目录排序，重名章节在每次导出时得到相同的编号
+        chapters.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let group_name = group_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let group_dir = context.unique_path(
+            &format!("{}/{}", series_name, sanitize_filename(&group_name)),
+            true,
+        );
+
+        for (chapter_path, info) in &chapters {
+            let chapter_name =
+                sanitize_filename(&numbered_name(info.chapter_index, &info.chapter_name));
+            let chapter_dir = context.unique_path(&format!("{}/{}", group_dir, chapter_name), true);
+            let chapter_dir = join_relative(&context.target_root, &chapter_dir);
+
+            let images = chapter_image_order(chapter_path, info).await;
+            let width = images.len().to_string().len().max(3);
+            for (page, image_name) in images.iter().enumerate() {
+                let extension = Path::new(image_name)
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_lowercase())
+                    .unwrap_or_else(|| "jpg".to_string());
+                let target =
+                    chapter_dir.join(format!("{:0width$}.{}", page + 1, extension, width = width));
+                export_file(&chapter_path.join(image_name), &target, context).await;
+            }
+        }
+    }
+}
+
+// 导出单部动画，只导出已完成的剧集
+async fn export_cartoon(cartoon_path: &Path, series_name: &str, context: &mut ExportContext) {
+    let mut chapters = Vec::new();
+    if let Ok(mut chapter_entries) = fs::read_dir(cartoon_path).await {
+        while let Ok(Some(chapter_entry)) = chapter_entries.next_entry().await {
+            let chapter_path = chapter_entry.path();
+            let Ok(content) = fs::read_to_string(chapter_path.join("info.json")).await else {
+                continue;
+            };
+            if let Ok(info) = serde_json::from_str::<CartoonChapterInfo>(&content) {
+                if info.is_completed {
+                    chapters.push((chapter_path, info));
+                }
+            }
+        }
+    }
+    chapters.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (chapter_path, info) in &chapters {
+        let source = chapter_path.join(&info.video_file);
+        let extension = Path::new(&info.video_file)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_else(|| "mp4".to_string());
+        let file_name = sanitize_filename(&numbered_name(info.chapter_index, &info.chapter_name));
+        let relative = context.unique_path(
+            &format!("{}/{}.{}", series_name, file_name, extension),
+            false,
+        );
+        let target = join_relative(&context.target_root, &relative);
+        export_file(&source, &target, context).await;
+    }
+}
+
+// 有章节序号时加上编号前缀，如 "0003 - 第3话"
+fn numbered_name(chapter_index: Option<usize>, chapter_name: &str) -> String {
+    match chapter_index {
+        Some(index) => format!("{:04} - {}", index, chapter_name),
+        None => chapter_name.to_string(),
+    }
+}
+
+// 复制或硬链接单个文件
+async fn export_file(source: &Path, target: &Path, context: &mut ExportContext) {
+    let stats = &mut context.stats;
+    let Ok(source_meta) = fs::metadata(source).await else {
+        stats
+            .errors
+            .push(format!("源文件不存在: {}", source.display()));
+        return;
+    };
+
+    // 已存在且大小一致则跳过；大小不同时只替换之前导出创建的文件
+    let record_key = record_key(&context.target_root, target);
+    if let Ok(target_meta) = fs::metadata(target).await {
+        if target_meta.len() == source_meta.len() {
+            stats.skipped_files += 1;
+            return;
+        }
+        if !context.record.files.contains(&record_key) {
+            stats.errors.push(format!(
+                "目标位置已有不是导出创建的文件，未覆盖: {}",
+                target.display()
+            ));
+            return;
+        }
+        if let Err(e) = fs::remove_file(target).await {
+            stats
+                .errors
+                .push(format!("替换文件失败 {}: {}", target.display(), e));
+            return;
+        }
+    }
+
+    if let Some(parent) = target.parent() {
+        if let Err(e) = fs::create_dir_all(parent).await {
+            stats
+                .errors
+                .push(format!("创建目录失败 {}: {}", parent.display(), e));
+            return;
+        }
+    }
+
+    if context.use_hardlink && fs::hard_link(source, target).await.is_ok() {
+        stats.exported_files += 1;
+        stats.linked_files += 1;
+        context.record.files.insert(record_key);
+        return;
+    }
+
+    match fs::copy(source, target).await {
+        Ok(_) => {
+            stats.exported_files += 1;
+            context.record.files.insert(record_key);
+        }
+        Err(e) => {
+            stats
+                .errors
+                .push(format!("复制文件失败 {}: {}", source.display(), e));
+            // 复制中断时留下的不完整文件是导出创建的，记录下来以便下次替换
+            if target.exists() {
+                context.record.files.insert(record_key);
+            }
+        }
+    }
+}
+
+// 导出记录中的键：相对导出目录的路径
+fn record_key(target_root: &Path, path: &Path) -> String {
+    path.strip_prefix(target_root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+// 规范化导出目录：拒绝 `..` 等组成部分，并解析已存在部分中的符号链接
+fn resolve_target_dir(target_dir: &str) -> Result<PathBuf, String> {
+    let target = PathBuf::from(target_dir);
+    if !target.is_absolute() {
+        return Err("导出目录必须是绝对路径".to_string());
+    }
+    if target
+        .components()
+        .any(|c| matches!(c, Component::ParentDir | Component::CurDir))
+    {
+        return Err(format!("非法的导出目录: {}", target_dir));
+    }
+
+    // 目录可能尚未创建，规范化最近的已存在上级目录后拼接其余部分
+    let mut existing = target.as_path();
+    let mut remaining = Vec::new();
+    while !existing.exists() {
+        let Some(parent) = existing.parent() else {
+            break;
+        };
+        remaining.push(existing.file_name().unwrap_or_default().to_os_string());
+        existing = parent;
+    }
+    let mut resolved = std::fs::canonicalize(existing)
+        .map_err(|e| format!("无法解析导出目录 {}: {}", target_dir, e))?;
+    resolved.extend(remaining.iter().rev());
+    Ok(resolved)
+}
+
+async fn read_export_record(target_root: &Path) -> ExportRecord {
+    match fs::read_to_string(target_root.join(EXPORT_RECORD_FILE)).await {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => ExportRecord::default(),
+    }
+}
+
+async fn write_export_record(target_root: &Path, record: &ExportRecord) -> Result<(), String> {
+    let record = ExportRecord {
+        schema_version: METADATA_SCHEMA_VERSION,
+        files: record.files.clone(),
+    };
+    let content =
+        serde_json::to_string_pretty(&record).map_err(|e| format!("序列化导出记录失败: {}", e))?;
+    fs::write(target_root.join(EXPORT_RECORD_FILE), content)
+        .await
+        .map_err(|e| format!("写入导出记录失败: {}", e))
+}
+
+// 按 info.json 中的顺序列出章节图片，缺失时按文件名排序
+async fn chapter_image_order(chapter_path: &Path, info: &ChapterInfo) -> Vec<String> {
+    let listed: Vec<String> = info
+        .images
+        .iter()
+        .filter(|name| chapter_path.join(name).is_file())
+        .cloned()
+        .collect();
+    if !listed.is_empty() {
+        return listed;
+    }
+
+    let mut images = Vec::new();
+    if let Ok(mut entries) = fs::read_dir(chapter_path).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let lower = name.to_lowercase();
+            if [".jpg", ".jpeg", ".png", ".webp"]
+                .iter()
+                .any(|ext| lower.ends_with(ext))
+            {
+                images.push(name);
+            }
+        }
+    }
+    images.sort_by(|a, b| natural_cmp(a, b));
+    images
+}
+
+// 列出需要导出的漫画/动画目录
+async fn list_owner_dirs(root: &Path, owner_uuid: Option<&SafeSegment>) -> Vec<PathBuf> {
+    if let Some(uuid) = owner_uuid {
+        let path = root.join(uuid);
+        return if path.is_dir() { vec![path] } else { vec![] };
+    }
+
+    let mut dirs = Vec::new();
+    if let Ok(mut entries) = fs::read_dir(root).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if entry.path().is_dir() {
+                dirs.push(entry.path());
+            }
+        }
+    }
+    dirs
+}
+
+// 根据详情文件生成作品目录名，重名时追加 uuid 前缀区分
+async fn unique_series_name(
+    owner_path: &Path,
+    detail_file_name: &str,
+    used_names: &mut HashSet<String>,
+) -> String {
+    let uuid = owner_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+
+    let name = match fs::read_to_string(owner_path.join(detail_file_name)).await {
+        Ok(content) => serde_json::from_str::<Value>(&content)
+            .ok()
+            .and_then(|v| {
+                v.get("name")
+                    .and_then(|n| n.as_str())
+                    .map(|n| n.to_string())
+            })
+            .filter(|n| !n.trim().is_empty()),
+        Err(_) => None,
+    };
+
+    let mut series_name = sanitize_filename(name.as_deref().unwrap_or(&uuid));
+    if !used_names.insert(series_name.to_lowercase()) {
+        let short_uuid: String = uuid.chars().take(8).collect();
+        series_name = sanitize_filename(&format!("{} ({})", series_name, short_uuid));
+        used_names.insert(series_name.to_lowercase());
+    }
+    series_name
+}
@@ -0,0 +1,307 @@
+use crate::download::settings::load_download_settings;
+use serde::Serialize;
+use std::ffi::OsString;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tauri::AppHandle;
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+use tokio::process::{ChildStdin, Command};
+use tokio::sync::Mutex;
+
+// 未配置路径时从 PATH 中查找
+const DEFAULT_FFMPEG_COMMAND: &str = "ffmpeg";
+// 配置的路径只允许指向这些文件名，避免把任意程序当作 ffmpeg 运行
+const FFMPEG_FILE_NAMES: [&str; 2] = ["ffmpeg", "ffmpeg.exe"];
+const DETECT_TIMEOUT: Duration = Duration::from_secs(10);
+const THUMBNAIL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// ffmpeg 检测结果
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct FfmpegStatus {
+    pub available: bool,
+    pub path: String,            // 实际调用的可执行文件路径或命令名
+    pub version: Option<String>, // 如 "6.1.1"
+    pub error: Option<String>,   // 不可用的原因
+}
+
+// 启动时检测一次，修改下载设置后重新检测
+static FFMPEG_STATUS: OnceLock<Mutex<Option<FfmpegStatus>>> = OnceLock::new();
+
+fn get_status_cache() -> &'static Mutex<Option<FfmpegStatus>> {
+    FFMPEG_STATUS.get_or_init(|| Mutex::new(None))
+}
+
+/// 获取 ffmpeg 检测结果，尚未检测时立即检测
+#[tauri::command]
+pub async fn get_ffmpeg_status(app_handle: AppHandle) -> Result<FfmpegStatus, String> {
+    if let Some(status) = get_status_cache().lock().await.clone() {
+        return Ok(status);
+    }
+    refresh_ffmpeg_status(&app_handle).await
+}
+
+/// 重新检测 ffmpeg
+#[tauri::command]
+pub async fn detect_ffmpeg(app_handle: AppHandle) -> Result<FfmpegStatus, String> {
+    refresh_ffmpeg_status(&app_handle).await
+}
+
+/// 按下载设置中的路径检测 ffmpeg 并缓存结果
+pub async fn refresh_ffmpeg_status(app_handle: &AppHandle) -> Result<FfmpegStatus, String> {
+    let settings = load_download_settings(app_handle).await?;
+    let status = probe_ffmpeg(settings.ffmpeg_path.as_deref()).await;
+    if status.available {
+        eprintln!(
+            "检测到 ffmpeg: {} ({})",
+            status.path,
+            status.version.as_deref().unwrap_or("未知版本")
+        );
+    } else {
+        eprintln!(
+            "ffmpeg 不可用: {}",
+            status.error.as_deref().unwrap_or("未知错误")
+        );
+    }
+
+    *get_status_cache().lock().await = Some(status.clone());
+    Ok(status)
+}
+
+/// 清除缓存的检测结果，下次获取状态或手动检测时重新检测
+pub async fn clear_ffmpeg_status() {
+    *get_status_cache().lock().await = None;
+}
+
+/// 检查配置的 ffmpeg 路径：必须是绝对路径，且指向名为 ffmpeg 或 ffmpeg.exe 的文件
+pub fn validate_ffmpeg_path(path: &str) -> Result<(), String> {
+    let path = Path::new(path.trim());
+    if !path.is_absolute() {
+        return Err("ffmpeg 路径必须是绝对路径".to_string());
+    }
+
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if !FFMPEG_FILE_NAMES.contains(&file_name.as_str()) {
+        return Err(format!(
+            "ffmpeg 路径必须指向 {} 文件",
+            FFMPEG_FILE_NAMES.join(" 或 ")
+        ));
+    }
+
+    if !path.is_file() {
+        return Err(format!("找不到 ffmpeg: {}", path.display()));
+    }
+    Ok(())
+}
+
+/// 已检测到的可用 ffmpeg，尚未检测完成或不可用时返回 None
+pub async fn detected_ffmpeg_path() -> Option<String> {
+    get_status_cache()
+        .lock()
+        .await
+        .as_ref()
+        .filter(|status| status.available)
+        .map(|status| status.path.clone())
+}
+
+async fn probe_ffmpeg(configured_path: Option<&str>) -> FfmpegStatus {
+    let program = configured_path
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .unwrap_or(DEFAULT_FFMPEG_COMMAND);
+    let mut status = FfmpegStatus {
+        path: program.to_string(),
+        ..Default::default()
+    };
+    if program != DEFAULT_FFMPEG_COMMAND {
+        if let Err(e) = validate_ffmpeg_path(program) {
+            status.error = Some(e);
+            return status;
+        }
+    }
+
+    let mut command = Command::new(program);
+    command
+        .arg("-version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    hide_console_window(&mut command);
+
+    match tokio::time::timeout(DETECT_TIMEOUT, command.output()).await {
+        Ok(Ok(output)) if output.status.success() => {
+            // 第一行形如 "ffmpeg version 6.1.1 Copyright ..."
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            status.available = true;
+            status.version = stdout
+                .lines()
+                .next()
+                .and_then(|line| line.strip_prefix("ffmpeg version "))
+                .and_then(|rest| rest.split_whitespace().next())
+                .map(str::to_string);
+        }
+        Ok(Ok(output)) => status.error = Some(format!("ffmpeg 运行失败: {}", output.status)),
+        Ok(Err(e)) => status.error = Some(format!("无法运行 {}: {}", program, e)),
+        Err(_) => status.error = Some("检测 ffmpeg 超时".to_string()),
+    }
+    status
+}
+
+/// 用 ffmpeg 将从标准输入写入的合并数据流不重新编码地封装为 MP4
+///
+/// `write_input` 负责按顺序写入分片数据，写完后释放标准输入；
+/// 先输出到临时文件，成功后再重命名为目标文件。
+pub async fn remux_with_ffmpeg<F, Fut>(
+    ffmpeg: &str,
+    output: &Path,
+    write_input: F,
+) -> Result<(), String>
+where
+    F: FnOnce(ChildStdin) -> Fut,
+    Fut: Future<Output = Result<(), String>>,
+{
+    let part_path = part_path_of(output);
+
+    let mut command = Command::new(ffmpeg);
+    command
+        .args(["-hide_banner", "-loglevel", "error", "-y", "-i", "pipe:0"])
+        // 只保留音视频流，ID3 等数据流无法写入 MP4
+        .args(["-map", "0:v?", "-map", "0:a?", "-c", "copy"])
+        .args(["-movflags", "+faststart", "-f", "mp4"])
+        .arg(&part_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    hide_console_window(&mut command);
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("启动 ffmpeg 失败: {}", e))?;
+    let stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| "无法写入 ffmpeg 标准输入".to_string())?;
+
+    // 同时读取错误输出，避免管道写满后 ffmpeg 阻塞
+    let stderr = child.stderr.take();
+    let stderr_task = tokio::spawn(async move {
+        let mut message = String::new();
+        if let Some(mut stderr) = stderr {
+            let _ = stderr.read_to_string(&mut message).await;
+        }
+        message
+    });
+
+    let write_result = write_input(stdin).await;
+    if write_result.is_err() {
+        let _ = child.start_kill();
+    }
+    let exit_status = child
+        .wait()
+        .await
+        .map_err(|e| format!("等待 ffmpeg 结束失败: {}", e));
+    let stderr_message = stderr_task.await.unwrap_or_default();
+
+    let result = match (write_result, exit_status) {
+        (Ok(()), Ok(exit_status)) if exit_status.success() => Ok(()),
+        // ffmpeg 提前退出时写入会失败，此时错误输出更有参考价值
+        (_, Ok(exit_status)) if !exit_status.success() && !stderr_message.trim().is_empty() => Err(
+            format!("ffmpeg 转封装失败: {}", last_lines(&stderr_message, 3)),
+        ),
+        (Err(e), _) | (_, Err(e)) => Err(e),
+        (Ok(()), Ok(exit_status)) => Err(format!("ffmpeg 转封装失败: {}", exit_status)),
+    };
+
+    match result {
+        Ok(()) => fs::rename(&part_path, output)
+            .await
+            .map_err(|e| format!("重命名输出文件失败: {}", e)),
+        Err(e) => {
+            let _ = fs::remove_file(&part_path).await;
+            Err(e)
+        }
+    }
+}
+
+/// 用 ffmpeg 截取视频中指定位置之后的第一个关键帧，缩放后保存为 JPEG
+///
+/// 只用软件解码（不启用硬件加速），并且只解码关键帧，单线程即可很快完成。
+pub async fn extract_keyframe_with_ffmpeg(
+    ffmpeg: &str,
+    input: &Path,
+    seek_secs: f64,
+    width: u32,
+    output: &Path,
+) -> Result<(), String> {
+    let part_path = part_path_of(output);
+
+    let mut command = Command::new(ffmpeg);
+    command
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args(["-hwaccel", "none", "-threads", "1", "-skip_frame", "nokey"])
+        .args(["-ss", &format!("{:.3}", seek_secs)])
+        .arg("-i")
+        .arg(input)
+        .args(["-map", "0:v:0", "-frames:v", "1"])
+        .args(["-vf", &format!("scale={}:-2", width)])
+        .args(["-q:v", "4", "-c:v", "mjpeg", "-f", "image2", "-update", "1"])
+        .arg(&part_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    hide_console_window(&mut command);
+
+    let result = match tokio::time::timeout(THUMBNAIL_TIMEOUT, command.output()).await {
+        Ok(Ok(output)) if output.status.success() => Ok(()),
+        Ok(Ok(output)) => Err(format!(
+            "ffmpeg 截取画面失败: {}",
+            last_lines(&String::from_utf8_lossy(&output.stderr), 3)
+        )),
+        Ok(Err(e)) => Err(format!("启动 ffmpeg 失败: {}", e)),
+        Err(_) => Err("ffmpeg 截取画面超时".to_string()),
+    };
+
+    // ffmpeg 没有找到关键帧时也会正常退出，但不会生成文件
+    match result {
+        Ok(()) => fs::rename(&part_path, output)
+            .await
+            .map_err(|e| format!("保存缩略图失败: {}", e)),
+        Err(e) => {
+            let _ = fs::remove_file(&part_path).await;
+            Err(e)
+        }
+    }
+}
+
+pub(crate) fn part_path_of(output: &Path) -> PathBuf {
+    let mut name = OsString::from(output.as_os_str());
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+fn last_lines(text: &str, count: usize) -> String {
+    let lines: Vec<&str> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    lines[lines.len().saturating_sub(count)..].join("; ")
+}
+
+// Windows 下调用命令行程序时不弹出控制台窗口
+#[cfg(windows)]
+fn hide_console_window(command: &mut Command) {
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    command.creation_flags(CREATE_NO_WINDOW);
+}
+
+#[cfg(not(windows))]
+fn hide_console_window(_command: &mut Command) {}
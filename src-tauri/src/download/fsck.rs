@@ -0,0 +1,972 @@
+use crate::download::cartoon::is_cartoon_download_running;
+use crate::download::http_client::http_client;
+use crate::download::manga::{download_image, get_extension_from_filename, get_filename_from_url};
+use crate::download::naming::join_relative;
+use crate::download::probe::probe_media_file;
+use crate::download::task_manager::{read_all_tasks, save_all_tasks, DownloadTask};
+use crate::download::thumbnail::thumbnail_file_name;
+use crate::download::trash::move_to_trash;
+use crate::download::types::*;
+use crate::download::utils::*;
+use crate::path_guard::ensure_within;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use tokio::fs;
+
+pub(crate) const IMAGE_EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "webp"];
+pub(crate) const VIDEO_EXTENSIONS: [&str; 3] = ["mp4", "ts", "mkv"];
+
+/// 扫描下载目录，检查资源库一致性
+#[tauri::command]
+pub async fn check_library_consistency(
+    app_handle: AppHandle,
+) -> Result<LibraryCheckReport, String> {
+    let report = scan_library(&app_handle).await?;
+    println!("资源库检查完成，发现 {} 个问题", report.issues.len());
+    Ok(report)
+}
+
+/// 对检查发现的问题执行修复操作
+///
+/// 前端只传入问题 ID，问题的类型、路径和可用操作以重新扫描的结果为准。
+#[tauri::command]
+pub async fn repair_library_issue(
+    app_handle: AppHandle,
+    issue_id: String,
+    action: String,
+) -> Result<LibraryRepairResult, String> {
+    let issue = scan_library(&app_handle)
+        .await?
+        .issues
+        .into_iter()
+        .find(|issue| issue.id == issue_id)
+        .ok_or("问题已不存在，请重新检查资源库")?;
+    if !issue.fixes.iter().any(|fix| fix == &action) {
+        return Err(format!("问题 {} 不支持修复操作: {}", issue.kind, action));
+    }
+
+    // 只允许修改下载目录内的文件
+    let downloads_path = get_downloads_path(&app_handle).await?;
+    let target_path = PathBuf::from(&issue.path);
+    if issue.kind != "stale_task" {
+        ensure_within(&downloads_path, &target_path)?;
+    }
+
+    let message = match (action.as_str(), issue.kind.as_str()) {
+        ("delete", "stale_task") => {
+            remove_task_entry(&app_handle, &issue).await?;
+            "已删除过期任务记录".to_string()
+        }
+        ("delete", _) if target_path.is_dir() => {
+            // 扫描之后目录内容可能已经变化，删除前再确认一次
+            let unchanged = match issue.kind.as_str() {
+                "empty_folder" => is_dir_empty(&target_path).await,
+                "orphan_temp_segments" => holds_only_temp_segments(&target_path).await,
+                _ => true,
+            };
+            if !unchanged {
+                return Err(format!(
+                    "目录内容已变化，请重新检查: {}",
+                    target_path.display()
+                ));
+            }
+
+            // 目录一律移动到回收站，可以撤销
+            let media_type = if issue.chapter_uuid.is_some() {
+                format!("{}_chapter", issue.media_type)
+            } else {
+                issue.media_type.clone()
+            };
+            let name = file_name_of(&target_path);
+            let trash_item = move_to_trash(&app_handle, &target_path, &media_type, &name).await?;
+            format!("已移至回收站: {}", trash_item.id)
+        }
+        ("delete", _) => {
+            if target_path.exists() {
+                fs::remove_file(&target_path)
+                    .await
+                    .map_err(|e| format!("删除文件失败: {}", e))?;
+            }
+            format!("已删除: {}", target_path.display())
+        }
+        ("rebuild_metadata", "missing_chapter_info")
+        | ("rebuild_metadata", "image_list_mismatch") => {
+            if issue.media_type == "cartoon" {
+                rebuild_cartoon_chapter_info(&target_path, &issue).await?
+            } else {
+                rebuild_manga_chapter_info(&target_path, &issue).await?
+            }
+        }
+        ("rebuild_metadata", "missing_detail") => rebuild_detail(&target_path, &issue).await?,
+        ("rebuild_metadata", "missing_cover") => {
+            rebuild_cover(&app_handle, &target_path, &issue).await?
+        }
+        ("resume", _) => {
+            set_task_paused(&app_handle, &issue).await?;
+            "已恢复为暂停任务，可在下载列表中继续".to_string()
+        }
+        _ => return Err(format!("问题 {} 不支持修复操作: {}", issue.kind, action)),
+    };
+
+    println!("已修复资源库问题 {}: {}", issue.id, message);
+
+    Ok(LibraryRepairResult {
+        success: true,
+        message,
+    })
+}
+
+// 扫描漫画、动画目录和下载任务，生成检查报告
+async fn scan_library(app_handle: &AppHandle) -> Result<LibraryCheckReport, String> {
+    let tasks = read_all_tasks(app_handle).await?;
+    let mut issues = Vec::new();
+
+    let manga_root = get_manga_downloads_path(app_handle).await?;
+    let scanned_manga = scan_manga_library(&manga_root, &mut issues).await;
+
+    let mut scanned_cartoons = 0;
+    let downloads_path = get_downloads_path(app_handle).await?;
+    // 同时检查新的 cartoons 和旧的 anime 目录
+    for cartoon_root in [
+        get_cartoon_downloads_path(app_handle).await?,
+        downloads_path.join("anime"),
+    ] {
+        scanned_cartoons += scan_cartoon_library(&cartoon_root, &tasks, &mut issues).await;
+    }
+
+    scan_cartoon_tasks(app_handle, &tasks, &mut issues).await?;
+
+    Ok(LibraryCheckReport {
+        issues,
+        scanned_manga,
+        scanned_cartoons,
+        scanned_tasks: tasks.len(),
+        check_time: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+    })
+}
+
+// 扫描漫画下载目录，返回扫描的漫画数量
+async fn scan_manga_library(manga_root: &Path, issues: &mut Vec<LibraryIssue>) -> usize {
+    let mut scanned = 0;
+    let Ok(mut manga_entries) = fs::read_dir(manga_root).await else {
+        return 0;
+    };
+
+    while let Ok(Some(manga_entry)) = manga_entries.next_entry().await {
+        let manga_path = manga_entry.path();
+        if !manga_path.is_dir() {
+            continue;
+        }
+        scanned += 1;
+        let manga_uuid = file_name_of(&manga_path);
+
+        if is_dir_empty(&manga_path).await {
+            issues.push(new_issue(
+                "empty_folder",
+                "manga",
+                &manga_path,
+                "漫画目录为空",
+                &["delete"],
+                Some(&manga_uuid),
+                None,
+            ));
+            continue;
+        }
+
+        let mut has_chapter_info = false;
+
+        if let Ok(mut group_entries) = fs::read_dir(&manga_path).await {
+            while let Ok(Some(group_entry)) = group_entries.next_entry().await {
+                let group_path = group_entry.path();
+                if !group_path.is_dir() {
+                    continue;
+                }
+
+                if is_dir_empty(&group_path).await {
+                    issues.push(new_issue(
+                        "empty_folder",
+                        "manga",
+                        &group_path,
+                        "分组目录为空",
+                        &["delete"],
+                        Some(&manga_uuid),
+                        None,
+                    ));
+                    continue;
+                }
+
+                let Ok(mut chapter_entries) = fs::read_dir(&group_path).await else {
+                    continue;
+                };
+                while let Ok(Some(chapter_entry)) = chapter_entries.next_entry().await {
+                    let chapter_path = chapter_entry.path();
+                    if !chapter_path.is_dir() {
+                        continue;
+                    }
+                    let chapter_uuid = file_name_of(&chapter_path);
+                    if check_manga_chapter(&chapter_path, &manga_uuid, &chapter_uuid, issues).await
+                    {
+                        has_chapter_info = true;
+                    }
+                }
+            }
+        }
+
+        check_detail_and_cover(
+            &manga_path,
+            "manga",
+            "manga_detail.json",
+            &manga_uuid,
+            has_chapter_info,
+            issues,
+        )
+        .await;
+    }
+
+    scanned
+}
+
+// 检查单个漫画章节，返回章节是否有可用的 info.json
+async fn check_manga_chapter(
+    chapter_path: &Path,
+    manga_uuid: &str,
+    chapter_uuid: &str,
+    issues: &mut Vec<LibraryIssue>,
+) -> bool {
+    let images_on_disk = list_files_with_extensions(chapter_path, &IMAGE_EXTENSIONS).await;
+    let info_file = chapter_path.join("info.json");
+
+    if !info_file.exists() {
+        if images_on_disk.is_empty() && is_dir_empty(chapter_path).await {
+            issues.push(new_issue(
+                "empty_folder",
+                "manga",
+                chapter_path,
+                "章节目录为空",
+                &["delete"],
+                Some(manga_uuid),
+                Some(chapter_uuid),
+            ));
+        } else {
+            issues.push(new_issue(
+                "missing_chapter_info",
+                "manga",
+                chapter_path,
+                &format!("章节目录缺少 info.json（{} 张图片）", images_on_disk.len()),
+                &["rebuild_metadata", "delete"],
+                Some(manga_uuid),
+                Some(chapter_uuid),
+            ));
+        }
+        return false;
+    }
+
+    let chapter_info = match fs::read_to_string(&info_file).await {
+        Ok(content) => serde_json::from_str::<ChapterInfo>(&content).ok(),
+        Err(_) => None,
+    };
+    let Some(chapter_info) = chapter_info else {
+        issues.push(new_issue(
+            "missing_chapter_info",
+            "manga",
+            chapter_path,
+            "章节 info.json 无法解析",
+            &["rebuild_metadata", "delete"],
+            Some(manga_uuid),
+            Some(chapter_uuid),
+        ));
+        return false;
+    };
+
+    let listed: HashSet<String> = chapter_info
+        .images
+        .iter()
+        .map(|image| get_filename_from_url(image))
+        .collect();
+    let on_disk: HashSet<String> = images_on_disk.into_iter().collect();
+    let missing = listed.difference(&on_disk).count();
+    let unlisted = on_disk.difference(&listed).count();
+
+    if missing > 0 || unlisted > 0 {
+        issues.push(new_issue(
+            "image_list_mismatch",
+            "manga",
+            chapter_path,
+            &format!(
+                "章节 \"{}\" 图片列表与磁盘不一致：缺失 {} 张，未记录 {} 张",
+                chapter_info.chapter_name, missing, unlisted
+            ),
+            &["rebuild_metadata", "delete"],
+            Some(manga_uuid),
+            Some(chapter_uuid),
+        ));
+    }
+
+    true
+}
+
+// 扫描动画下载目录，返回扫描的动画数量
+async fn scan_cartoon_library(
+    cartoon_root: &Path,
+    tasks: &[DownloadTask],
+    issues: &mut Vec<LibraryIssue>,
+) -> usize {
+    let mut scanned = 0;
+    let Ok(mut cartoon_entries) = fs::read_dir(cartoon_root).await else {
+        return 0;
+    };
+
+    while let Ok(Some(cartoon_entry)) = cartoon_entries.next_entry().await {
+        let cartoon_path = cartoon_entry.path();
+        if !cartoon_path.is_dir() {
+            continue;
+        }
+        scanned += 1;
+        let cartoon_uuid = file_name_of(&cartoon_path);
+
+        if is_dir_empty(&cartoon_path).await {
+            issues.push(new_issue(
+                "empty_folder",
+                "cartoon",
+                &cartoon_path,
+                "动画目录为空",
+                &["delete"],
+                Some(&cartoon_uuid),
+                None,
+            ));
+            continue;
+        }
+
+        let mut has_chapter_info = false;
+
+        if let Ok(mut chapter_entries) = fs::read_dir(&cartoon_path).await {
+            while let Ok(Some(chapter_entry)) = chapter_entries.next_entry().await {
+                let chapter_path = chapter_entry.path();
+                if !chapter_path.is_dir() {
+                    continue;
+                }
+                let chapter_uuid = file_name_of(&chapter_path);
+                if check_cartoon_chapter(&chapter_path, &cartoon_uuid, &chapter_uuid, tasks, issues)
+                    .await
+                {
+                    has_chapter_info = true;
+                }
+            }
+        }
+
+        check_detail_and_cover(
+            &cartoon_path,
+            "cartoon",
+            "cartoon_detail.json",
+            &cartoon_uuid,
+            has_chapter_info,
+            issues,
+        )
+        .await;
+    }
+
+    scanned
+}
+
+// 检查单个动画章节，返回章节是否有可用的 info.json
+async fn check_cartoon_chapter(
+    chapter_path: &Path,
+    cartoon_uuid: &str,
+    chapter_uuid: &str,
+    tasks: &[DownloadTask],
+    issues: &mut Vec<LibraryIssue>,
+) -> bool {
+    let temp_dir = chapter_path.join("temp_segments");
+    let is_running = is_cartoon_download_running(cartoon_uuid, chapter_uuid).await;
+    let task = find_task(tasks, cartoon_uuid, chapter_uuid);
+    let has_active_task =
+        is_running || task.is_some_and(|t| matches!(t.status.as_str(), "downloading" | "paused"));
+    // 只有任务记录存在时才能恢复下载（需要其中的视频地址）
+    let resume_fixes: &[&str] = if task.is_some() {
+        &["resume", "delete"]
+    } else {
+        &["delete"]
+    };
+
+    let info_file = chapter_path.join("info.json");
+    if !info_file.exists() {
+        if is_running {
+            return false;
+        }
+        let videos = list_files_with_extensions(chapter_path, &VIDEO_EXTENSIONS).await;
+        if videos.is_empty() && is_dir_empty(chapter_path).await {
+            issues.push(new_issue(
+                "empty_folder",
+                "cartoon",
+                chapter_path,
+                "章节目录为空",
+                &["delete"],
+                Some(cartoon_uuid),
+                Some(chapter_uuid),
+            ));
+        } else if videos.is_empty() {
+            issues.push(new_issue(
+                "missing_chapter_info",
+                "cartoon",
+                chapter_path,
+                "章节目录缺少 info.json 且没有视频文件",
+                resume_fixes,
+                Some(cartoon_uuid),
+                Some(chapter_uuid),
+            ));
+        } else {
+            issues.push(new_issue(
+                "missing_chapter_info",
+                "cartoon",
+                chapter_path,
+                "章节目录缺少 info.json",
+                &["rebuild_metadata", "delete"],
+                Some(cartoon_uuid),
+                Some(chapter_uuid),
+            ));
+        }
+        return false;
+    }
+
+    let chapter_info = match fs::read_to_string(&info_file).await {
+        Ok(content) => serde_json::from_str::<CartoonChapterInfo>(&content).ok(),
+        Err(_) => None,
+    };
+    let Some(chapter_info) = chapter_info else {
+        issues.push(new_issue(
+            "missing_chapter_info",
+            "cartoon",
+            chapter_path,
+            "章节 info.json 无法解析",
+            &["rebuild_metadata", "delete"],
+            Some(cartoon_uuid),
+            Some(chapter_uuid),
+        ));
+        return false;
+    };
+
+    if !chapter_info.is_completed && !has_active_task {
+        issues.push(new_issue(
+            "stale_incomplete_episode",
+            "cartoon",
+            chapter_path,
+            &format!(
+                "章节 \"{}\" 未下载完成且没有对应的下载任务",
+                chapter_info.chapter_name
+            ),
+            resume_fixes,
+            Some(cartoon_uuid),
+            Some(chapter_uuid),
+        ));
+    } else if temp_dir.is_dir() && !has_active_task {
+        issues.push(new_issue(
+            "orphan_temp_segments",
+            "cartoon",
+            &temp_dir,
+            &format!(
+                "章节 \"{}\" 存在残留的临时分片目录",
+                chapter_info.chapter_name
+            ),
+            &["delete"],
+            Some(cartoon_uuid),
+            Some(chapter_uuid),
+        ));
+    }
+
+    true
+}
+
+// 检查详情文件和封面
+async fn check_detail_and_cover(
+    owner_path: &Path,
+    media_type: &str,
+    detail_file_name: &str,
+    owner_uuid: &str,
+    has_chapter_info: bool,
+    issues: &mut Vec<LibraryIssue>,
+) {
+    let detail_file = owner_path.join(detail_file_name);
+    let detail = match fs::read_to_string(&detail_file).await {
+        Ok(content) => serde_json::from_str::<Value>(&content).ok(),
+        Err(_) => None,
+    };
+
+    let Some(detail) = detail else {
+        // 详情缺失时只能根据章节信息重建
+        let fixes: &[&str] = if has_chapter_info {
+            &["rebuild_metadata", "delete"]
+        } else {
+            &["delete"]
+        };
+        issues.push(new_issue(
+            "missing_detail",
+            media_type,
+            owner_path,
+            &format!("缺少或无法解析 {}", detail_file_name),
+            fixes,
+            Some(owner_uuid),
+            None,
+        ));
+        return;
+    };
+
+    if find_cover_file(owner_path).is_none() {
+        let cover_url = detail.get("cover").and_then(|v| v.as_str()).unwrap_or("");
+        let fixes: &[&str] = if cover_url.is_empty() {
+            &[]
+        } else {
+            &["rebuild_metadata"]
+        };
+        issues.push(new_issue(
+            "missing_cover",
+            media_type,
+            owner_path,
+            "缺少封面图片",
+            fixes,
+            Some(owner_uuid),
+            None,
+        ));
+    }
+}
+
+// 检查 cartoon_tasks.json 中的过期任务
+async fn scan_cartoon_tasks(
+    app_handle: &AppHandle,
+    tasks: &[DownloadTask],
+    issues: &mut Vec<LibraryIssue>,
+) -> Result<(), String> {
+    let cartoon_root = get_cartoon_downloads_path(app_handle).await?;
+
+    for task in tasks {
+        let info_file = cartoon_root
+            .join(&task.cartoon_uuid)
+            .join(&task.chapter_uuid)
+            .join("info.json");
+        let is_completed = match fs::read_to_string(&info_file).await {
+            Ok(content) => serde_json::from_str::<CartoonChapterInfo>(&content)
+                .map(|info| info.is_completed)
+                .unwrap_or(false),
+            Err(_) => false,
+        };
+
+        let message = match task.status.as_str() {
+            "downloading" | "paused" if is_completed => "任务对应的章节已下载完成",
+            "downloading" | "paused" => continue,
+            _ => "任务已结束但记录未清理",
+        };
+
+        issues.push(LibraryIssue {
+            id: format!("stale_task:{}|{}", task.cartoon_uuid, task.chapter_uuid),
+            kind: "stale_task".to_string(),
+            media_type: "task".to_string(),
+            path: info_file.to_string_lossy().to_string(),
+            message: format!(
+                "{} ({} - {})",
+                message, task.cartoon_name, task.chapter_name
+            ),
+            fixes: vec!["delete".to_string()],
+            owner_uuid: Some(task.cartoon_uuid.clone()),
+            chapter_uuid: Some(task.chapter_uuid.clone()),
+        });
+    }
+
+    Ok(())
+}
+
+// 根据磁盘上的图片重建漫画章节信息
+async fn rebuild_manga_chapter_info(
+    chapter_path: &Path,
+    issue: &LibraryIssue,
+) -> Result<String, String> {
+    let info_file = chapter_path.join("info.json");
+    let existing = match fs::read_to_string(&info_file).await {
+        Ok(content) => serde_json::from_str::<ChapterInfo>(&content).ok(),
+        Err(_) => None,
+    };
+
+    let mut images = list_files_with_extensions(chapter_path, &IMAGE_EXTENSIONS).await;
+    images.sort();
+
+    let chapter_info = match existing {
+        Some(mut info) => {
+            info.total_images = info.total_images.max(images.len());
+            info.images = images;
+            info
+        }
+        None => {
+            let manga_path = chapter_path.parent().and_then(|p| p.parent());
+            let manga_name = match manga_path {
+                Some(path) => read_detail_name(&path.join("manga_detail.json")).await,
+                None => None,
+            };
+            let group_path_word = chapter_path.parent().map(file_name_of).unwrap_or_default();
+            let chapter_uuid = issue
+                .chapter_uuid
+                .clone()
+                .unwrap_or_else(|| file_name_of(chapter_path));
+            ChapterInfo {
+                schema_version: METADATA_SCHEMA_VERSION,
+                manga_uuid: issue.owner_uuid.clone().unwrap_or_default(),
+                manga_name: manga_name.unwrap_or_default(),
+                group_path_word,
+                chapter_name: chapter_uuid.clone(),
+                chapter_uuid,
+                chapter_index: None,
+                total_images: images.len(),
+                images,
+                download_time: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            }
+        }
+    };
+
+    let content = serde_json::to_string_pretty(&chapter_info)
+        .map_err(|e| format!("序列化章节信息失败: {}", e))?;
+    fs::write(&info_file, content)
+        .await
+        .map_err(|e| format!("写入章节信息失败: {}", e))?;
+
+    Ok(format!(
+        "已重建章节信息，共 {} 张图片",
+        chapter_info.images.len()
+    ))
+}
+
+// 根据磁盘上的视频文件重建动画章节信息
+async fn rebuild_cartoon_chapter_info(
+    chapter_path: &Path,
+    issue: &LibraryIssue,
+) -> Result<String, String> {
+    let mut videos = list_files_with_extensions(chapter_path, &VIDEO_EXTENSIONS).await;
+    videos.sort();
+    let Some(video_file) = videos.into_iter().next() else {
+        return Err("章节目录中没有视频文件，无法重建".to_string());
+    };
+
+    let file_size = fs::metadata(chapter_path.join(&video_file))
+        .await
+        .map(|m| m.len())
+        .unwrap_or(0);
+    let cartoon_name = match chapter_path.parent() {
+        Some(path) => read_detail_name(&path.join("cartoon_detail.json")).await,
+        None => None,
+    };
+    let chapter_name = Path::new(&video_file)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let media = if file_size > 0 {
+        probe_media_file(&chapter_path.join(&video_file)).await
+    } else {
+        None
+    };
+    // 缩略图仍在时继续使用，不重新生成
+    let thumbnail = Some(thumbnail_file_name(&video_file))
+        .filter(|thumbnail| join_relative(chapter_path, thumbnail).exists());
+
+    let chapter_info = CartoonChapterInfo {
+        schema_version: METADATA_SCHEMA_VERSION,
+        cartoon_uuid: issue.owner_uuid.clone().unwrap_or_default(),
+        cartoon_name: cartoon_name.unwrap_or_default(),
+        chapter_uuid: issue
+            .chapter_uuid
+            .clone()
+            .unwrap_or_else(|| file_name_of(chapter_path)),
+        chapter_name,
+        chapter_index: None,
+        video_file,
+        file_size,
+        download_time: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        // 临时分片目录仍在说明合并未完成
+        is_completed: file_size > 0 && !chapter_path.join("temp_segments").exists(),
+        quality: None,
+        video_line: None,
+        media,
+        thumbnail,
+    };
+
+    let content = serde_json::to_string_pretty(&chapter_info)
+        .map_err(|e| format!("序列化章节信息失败: {}", e))?;
+    fs::write(chapter_path.join("info.json"), content)
+        .await
+        .map_err(|e| format!("写入章节信息失败: {}", e))?;
+
+    Ok(format!("已重建章节信息: {}", chapter_info.video_file))
+}
+
+// 根据章节信息重建最简详情文件
+async fn rebuild_detail(owner_path: &Path, issue: &LibraryIssue) -> Result<String, String> {
+    let owner_uuid = issue
+        .owner_uuid
+        .clone()
+        .unwrap_or_else(|| file_name_of(owner_path));
+
+    let (detail_file, detail) = if issue.media_type == "cartoon" {
+        let name = find_chapter_owner_name(owner_path, "cartoon_name", 1).await;
+        let detail = CartoonDetail {
+            schema_version: METADATA_SCHEMA_VERSION,
+            uuid: owner_uuid,
+            name: name.unwrap_or_default(),
+            path_word: String::new(),
+            cover: String::new(),
+            company: None,
+            theme: Vec::new(),
+            cartoon_type: None,
+            category: None,
+            grade: None,
+            popular: None,
+            brief: None,
+            years: None,
+            datetime_updated: None,
+        };
+        (
+            owner_path.join("cartoon_detail.json"),
+            serde_json::to_value(detail).map_err(|e| format!("序列化动画详情失败: {}", e))?,
+        )
+    } else {
+        let name = find_chapter_owner_name(owner_path, "manga_name", 2).await;
+        let detail = MangaDetail {
+            schema_version: METADATA_SCHEMA_VERSION,
+            uuid: owner_uuid,
+            name: name.unwrap_or_default(),
+            path_word: String::new(),
+            cover: String::new(),
+            author: Vec::new(),
+            theme: Vec::new(),
+            status: String::new(),
+            popular: None,
+            brief: None,
+        };
+        (
+            owner_path.join("manga_detail.json"),
+            serde_json::to_value(detail).map_err(|e| format!("序列化漫画详情失败: {}", e))?,
+        )
+    };
+
+    let content =
+        serde_json::to_string_pretty(&detail).map_err(|e| format!("序列化详情失败: {}", e))?;
+    fs::write(&detail_file, content)
+        .await
+        .map_err(|e| format!("写入详情文件失败: {}", e))?;
+
+    Ok(format!("已根据章节信息重建 {}", detail_file.display()))
+}
+
+// 根据详情中的封面地址重新下载封面
+async fn rebuild_cover(
+    app_handle: &AppHandle,
+    owner_path: &Path,
+    issue: &LibraryIssue,
+) -> Result<String, String> {
+    let detail_file_name = if issue.media_type == "cartoon" {
+        "cartoon_detail.json"
+    } else {
+        "manga_detail.json"
+    };
+    let content = fs::read_to_string(owner_path.join(detail_file_name))
+        .await
+        .map_err(|e| format!("读取详情文件失败: {}", e))?;
+    let detail: Value =
+        serde_json::from_str(&content).map_err(|e| format!("解析详情文件失败: {}", e))?;
+    let cover_url = detail
+        .get("cover")
+        .and_then(|v| v.as_str())
+        .filter(|url| !url.is_empty())
+        .ok_or("详情中没有封面地址")?;
+
+    let cover_path = owner_path.join(format!(
+        "cover.{}",
+        get_extension_from_filename(&get_filename_from_url(cover_url))
+    ));
+    let client = http_client(app_handle);
+    download_image(&client, cover_url, &cover_path).await?;
+
+    Ok(format!("已重新下载封面: {}", cover_path.display()))
+}
+
+// 将任务状态设置为暂停，以便前端恢复下载
+async fn set_task_paused(app_handle: &AppHandle, issue: &LibraryIssue) -> Result<(), String> {
+    let (cartoon_uuid, chapter_uuid) = issue_task_key(issue)?;
+    let mut tasks = read_all_tasks(app_handle).await?;
+
+    let task = tasks
+        .iter_mut()
+        .find(|t| t.cartoon_uuid == cartoon_uuid && t.chapter_uuid == chapter_uuid)
+        .ok_or_else(|| format!("未找到任务: {}|{}", cartoon_uuid, chapter_uuid))?;
+    task.status = "paused".to_string();
+    task.updated_at = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    save_all_tasks(app_handle, &tasks).await
+}
+
+// 删除任务记录
+async fn remove_task_entry(app_handle: &AppHandle, issue: &LibraryIssue) -> Result<(), String> {
+    let (cartoon_uuid, chapter_uuid) = issue_task_key(issue)?;
+    let mut tasks = read_all_tasks(app_handle).await?;
+    tasks.retain(|t| !(t.cartoon_uuid == cartoon_uuid && t.chapter_uuid == chapter_uuid));
+    save_all_tasks(app_handle, &tasks).await
+}
+
+fn issue_task_key(issue: &LibraryIssue) -> Result<(&str, &str), String> {
+    match (&issue.owner_uuid, &issue.chapter_uuid) {
+        (Some(owner), Some(chapter)) => Ok((owner.as_str(), chapter.as_str())),
+        _ => Err("问题缺少任务信息".to_string()),
+    }
+}
+
+fn find_task<'a>(
+    tasks: &'a [DownloadTask],
+    cartoon_uuid: &str,
+    chapter_uuid: &str,
+) -> Option<&'a DownloadTask> {
+    tasks
+        .iter()
+        .find(|t| t.cartoon_uuid == cartoon_uuid && t.chapter_uuid == chapter_uuid)
+}
+
+fn new_issue(
+    kind: &str,
+    media_type: &str,
+    path: &Path,
+    message: &str,
+    fixes: &[&str],
+    owner_uuid: Option<&str>,
+    chapter_uuid: Option<&str>,
+) -> LibraryIssue {
+    let path = path.to_string_lossy().to_string();
+    LibraryIssue {
+        id: format!("{}:{}", kind, path),
+        kind: kind.to_string(),
+        media_type: media_type.to_string(),
+        path,
+        message: message.to_string(),
+        fixes: fixes.iter().map(|fix| fix.to_string()).collect(),
+        owner_uuid: owner_uuid.map(|s| s.to_string()),
+        chapter_uuid: chapter_uuid.map(|s| s.to_string()),
+    }
+}
+
+pub(crate) fn file_name_of(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+fn find_cover_file(owner_path: &Path) -> Option<PathBuf> {
+    IMAGE_EXTENSIONS
+        .iter()
+        .map(|ext| owner_path.join(format!("cover.{}", ext)))
+        .find(|path| path.exists())
+}
+
+async fn is_dir_empty(path: &Path) -> bool {
+    match fs::read_dir(path).await {
+        Ok(mut entries) => matches!(entries.next_entry().await, Ok(None)),
+        Err(_) => false,
+    }
+}
+
+// 临时分片目录中只有下载过程产生的文件：分片、初始化段、续传信息和本地播放列表
+async fn holds_only_temp_segments(dir: &Path) -> bool {
+    let Ok(mut entries) = fs::read_dir(dir).await else {
+        return false;
+    };
+    loop {
+        let entry = match entries.next_entry().await {
+            Ok(Some(entry)) => entry,
+            Ok(None) => return true,
+            Err(_) => return false,
+        };
+        let is_file = entry.file_type().await.is_ok_and(|kind| kind.is_file());
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !is_file || !is_temp_segment_file(name.strip_suffix(".part").unwrap_or(&name)) {
+            return false;
+        }
+    }
+}
+
+fn is_temp_segment_file(name: &str) -> bool {
+    matches!(
+        name,
+        "manifest.json" | "resume.json" | "parts.json" | "local.m3u8"
+    ) || ["segment_", "init_", "part_"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+// 列出目录中指定扩展名的文件名
+pub(crate) async fn list_files_with_extensions(dir: &Path, extensions: &[&str]) -> Vec<String> {
+    let mut files = Vec::new();
+    if let Ok(mut entries) = fs::read_dir(dir).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let matches_ext = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| extensions.contains(&ext.to_lowercase().as_str()));
+            if path.is_file() && matches_ext {
+                files.push(file_name_of(&path));
+            }
+        }
+    }
+    files
+}
+
+async fn read_detail_name(detail_file: &Path) -> Option<String> {
+    let content = fs::read_to_string(detail_file).await.ok()?;
+    let detail: Value = serde_json::from_str(&content).ok()?;
+    detail.get("name")?.as_str().map(|s| s.to_string())
+}
+
+// 在章节 info.json 中查找漫画/动画名称，depth 为章节目录相对于根目录的层级
+async fn find_chapter_owner_name(owner_path: &Path, field: &str, depth: usize) -> Option<String> {
+    let mut dirs = vec![(owner_path.to_path_buf(), 0)];
+    while let Some((dir, level)) = dirs.pop() {
+        if level == depth {
+            let content = fs::read_to_string(dir.join("info.json")).await.ok();
+            let info = content.and_then(|c| serde_json::from_str::<Value>(&c).ok());
+            if let Some(name) = info
+                .as_ref()
+                .and_then(|v| v.get(field))
+                .and_then(|v| v.as_str())
+                .filter(|name| !name.is_empty())
+            {
+                return Some(name.to_string());
+            }
+            continue;
+        }
+        if let Ok(mut entries) = fs::read_dir(&dir).await {
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                if entry.path().is_dir() {
+                    dirs.push((entry.path(), level + 1));
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temp_segment_files_are_recognized() {
+        for name in [
+            "segment_0001.ts",
+            "init_00.mp4",
+            "part_03.bin",
+            "manifest.json",
+            "resume.json",
+            "parts.json",
+            "local.m3u8",
+        ] {
+            assert!(is_temp_segment_file(name), "{}", name);
+        }
+        for name in ["第01集.mp4", "info.json", "cover.jpg", "notes.txt"] {
+            assert!(!is_temp_segment_file(name), "{}", name);
+        }
+    }
+}
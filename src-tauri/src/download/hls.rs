@@ -0,0 +1,1013 @@
+use crate::download::http_client::HttpClient;
+use crate::download::settings::VideoQualityPreference;
+use crate::download::types::VideoQuality;
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use reqwest::header::RANGE;
+use reqwest::{StatusCode, Url};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::Mutex;
+
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+// 主播放列表最多允许嵌套的层数，防止循环引用
+const MAX_PLAYLIST_DEPTH: usize = 3;
+
+/// 主播放列表（master playlist）中的一路码流
+#[derive(Debug, Clone)]
+pub struct VariantStream {
+    pub url: String,
+    pub bandwidth: Option<u64>,
+    pub resolution: Option<(u32, u32)>,
+    pub codecs: Option<String>,
+    pub external_audio: Option<String>, // 音频为单独的 #EXT-X-MEDIA 音轨时，记录其 GROUP-ID
+}
+
+impl VariantStream {
+    // 用于比较清晰度的排序键：先比较带宽，再比较像素数
+    fn quality_key(&self) -> (u64, u64) {
+        let pixels = self
+            .resolution
+            .map(|(width, height)| width as u64 * height as u64)
+            .unwrap_or(0);
+        (self.bandwidth.unwrap_or(0), pixels)
+    }
+
+    /// 转换为记录在章节信息中的清晰度
+    pub fn to_quality(&self) -> VideoQuality {
+        let label = match (self.resolution, self.bandwidth) {
+            (Some((_, height)), _) => format!("{}p", height),
+            (None, Some(bandwidth)) => format!("{}kbps", bandwidth / 1000),
+            (None, None) => "未知".to_string(),
+        };
+
+        VideoQuality {
+            label,
+            bandwidth: self.bandwidth,
+            resolution: self
+                .resolution
+                .map(|(width, height)| format!("{}x{}", width, height)),
+            codecs: self.codecs.clone(),
+        }
+    }
+}
+
+/// 获取HLS媒体播放列表，遇到主播放列表时按偏好选择码流并继续获取
+///
+/// 返回媒体播放列表内容、其最终地址（跟随重定向后，用于解析相对地址），
+/// 以及选中的码流（不是主播放列表时为 None）。
+pub async fn fetch_media_playlist(
+    client: &HttpClient,
+    url: &str,
+    preference: &VideoQualityPreference,
+) -> Result<(String, Url, Option<VariantStream>), String> {
+    let mut playlist_url = url.to_string();
+    let mut selected = None;
+
+    for _ in 0..MAX_PLAYLIST_DEPTH {
+        let (content, final_url) = fetch_playlist(client, &playlist_url).await?;
+        if !is_master_playlist(&content) {
+            return Ok((content, final_url, selected));
+        }
+
+        let variants = parse_master_playlist(&content, &final_url)?;
+        let variant = select_variant(&variants, preference)
+            .ok_or_else(|| "主播放列表中没有可用的码流".to_string())?
+            .clone();
+        // 码流本身不含音频，只下载视频会得到无声文件
+        if let Some(group) = &variant.external_audio {
+            return Err(format!(
+                "所选码流的音频为单独的音轨（AUDIO=\"{}\"），暂不支持下载音视频分离的HLS流",
+                group
+            ));
+        }
+        eprintln!(
+            "从 {} 路码流中选择: {} ({})",
+            variants.len(),
+            variant.to_quality().label,
+            variant.url
+        );
+
+        playlist_url = variant.url.clone();
+        selected = Some(variant);
+    }
+
+    Err("播放列表嵌套层数过多".to_string())
+}
+
+async fn fetch_playlist(client: &HttpClient, url: &str) -> Result<(String, Url), String> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("请求m3u8文件失败: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("HTTP状态错误: {}", response.status()));
+    }
+
+    let final_url = response.url().clone();
+    let content = response
+        .text()
+        .await
+        .map_err(|e| format!("读取m3u8内容失败: {}", e))?;
+    Ok((content, final_url))
+}
+
+/// 根据地址判断是否为HLS播放列表，忽略查询参数
+pub fn is_hls_url(url: &str) -> bool {
+    let path = match Url::parse(url) {
+        Ok(parsed) => parsed.path().to_lowercase(),
+        Err(_) => url.split(['?', '#']).next().unwrap_or("").to_lowercase(),
+    };
+    path.ends_with(".m3u8") || path.ends_with(".m3u")
+}
+
+/// 根据响应的 Content-Type 判断是否为HLS播放列表
+pub fn is_hls_content_type(content_type: &str) -> bool {
+    content_type.to_lowercase().contains("mpegurl")
+}
+
+/// 是否为主播放列表（包含 `#EXT-X-STREAM-INF`）
+pub fn is_master_playlist(content: &str) -> bool {
+    content
+        .lines()
+        .any(|line| line.trim_start().starts_with("#EXT-X-STREAM-INF"))
+}
+
+/// 解析主播放列表中的码流，`#EXT-X-STREAM-INF` 的下一个非注释行是该码流的地址
+///
+/// `AUDIO` 属性引用的音频组中有带 URI 的 `#EXT-X-MEDIA:TYPE=AUDIO` 时，
+/// 音频不在码流内，记录到 `external_audio`。
+pub fn parse_master_playlist(content: &str, base_url: &Url) -> Result<Vec<VariantStream>, String> {
+    let mut variants = Vec::new();
+    let mut audio_groups: Vec<Option<String>> = Vec::new();
+    let mut external_audio_groups = HashSet::new();
+    let mut pending: Option<HashMap<String, String>> = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            pending = Some(parse_attribute_list(attributes));
+            continue;
+        }
+        if let Some(attributes) = line.strip_prefix("#EXT-X-MEDIA:") {
+            let attributes = parse_attribute_list(attributes);
+            if attributes.get("TYPE").map(String::as_str) == Some("AUDIO")
+                && attributes.contains_key("URI")
+            {
+                if let Some(group) = attributes.get("GROUP-ID") {
+                    external_audio_groups.insert(group.clone());
+                }
+            }
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        // 没有 STREAM-INF 的地址行不是码流
+        let Some(attributes) = pending.take() else {
+            continue;
+        };
+        audio_groups.push(attributes.get("AUDIO").cloned());
+        variants.push(VariantStream {
+            url: resolve_url(base_url, line)?,
+            bandwidth: attributes
+                .get("AVERAGE-BANDWIDTH")
+                .or_else(|| attributes.get("BANDWIDTH"))
+                .and_then(|v| v.parse().ok()),
+            resolution: attributes.get("RESOLUTION").and_then(|v| {
+                let (width, height) = v.split_once('x')?;
+                Some((width.parse().ok()?, height.parse().ok()?))
+            }),
+            codecs: attributes.get("CODECS").cloned(),
+            external_audio: None,
+        });
+    }
+
+    // #EXT-X-MEDIA 可以出现在 #EXT-X-STREAM-INF 之后，全部读完再关联
+    for (variant, group) in variants.iter_mut().zip(audio_groups) {
+        variant.external_audio = group.filter(|group| external_audio_groups.contains(group));
+    }
+
+    Ok(variants)
+}
+
+/// 按偏好选择码流
+///
+/// 先排除超过分辨率/带宽上限的码流（未标注的视为满足），再按策略取最高或最低；
+/// 全部超过上限时取最低的一路。
+pub fn select_variant<'a>(
+    variants: &'a [VariantStream],
+    preference: &VideoQualityPreference,
+) -> Option<&'a VariantStream> {
+    let within_limits: Vec<&VariantStream> = variants
+        .iter()
+        .filter(|variant| {
+            let height_ok = match (preference.max_height, variant.resolution) {
+                (Some(max_height), Some((_, height))) => height <= max_height,
+                _ => true,
+            };
+            let bandwidth_ok = match (preference.max_bandwidth, variant.bandwidth) {
+                (Some(max_bandwidth), Some(bandwidth)) => bandwidth <= max_bandwidth,
+                _ => true,
+            };
+            height_ok && bandwidth_ok
+        })
+        .collect();
+
+    if within_limits.is_empty() {
+        return variants.iter().min_by_key(|variant| variant.quality_key());
+    }
+
+    match preference.strategy.as_str() {
+        "lowest" => within_limits
+            .into_iter()
+            .min_by_key(|variant| variant.quality_key()),
+        _ => within_limits
+            .into_iter()
+            .max_by_key(|variant| variant.quality_key()),
+    }
+}
+
+/// 解析 `KEY=VALUE,KEY="VALUE"` 形式的属性列表，引号内的逗号不作为分隔符
+pub fn parse_attribute_list(input: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = input.trim();
+
+    while !rest.is_empty() {
+        let Some((key, after_key)) = rest.split_once('=') else {
+            break;
+        };
+
+        let (value, after_value) = if let Some(quoted) = after_key.strip_prefix('"') {
+            match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            }
+        } else {
+            match after_key.find(',') {
+                Some(end) => (&after_key[..end], &after_key[end..]),
+                None => (after_key, ""),
+            }
+        };
+
+        attributes.insert(key.trim().to_uppercase(), value.to_string());
+        rest = after_value.trim_start_matches(',').trim_start();
+    }
+
+    attributes
+}
+
+// 按 RFC 3986 解析相对地址（支持 ../、/ 开头和带查询参数的地址）
+fn resolve_url(base_url: &Url, url: &str) -> Result<String, String> {
+    base_url
+        .join(url)
+        .map(|resolved| resolved.to_string())
+        .map_err(|e| format!("无效的地址 {}: {}", url, e))
+}
+
+/// 解析后的媒体播放列表
+#[derive(Debug, Clone, Default)]
+pub struct HlsPlaylist {
+    pub segments: Vec<HlsSegment>,
+    pub init_sections: Vec<InitSection>,
+}
+
+/// 媒体播放列表中的一个视频片段
+#[derive(Debug, Clone)]
+pub struct HlsSegment {
+    pub url: String,
+    pub sequence: u64, // 媒体序列号，未指定IV时用于计算IV
+    pub key: Option<SegmentKey>,
+    pub byte_range: Option<ByteRange>,
+    pub init_section: Option<usize>, // fMP4 片段对应的初始化片段，为 HlsPlaylist::init_sections 的下标
+    pub discontinuity: bool,         // 片段前有 #EXT-X-DISCONTINUITY（编码参数或时间戳可能变化）
+    pub duration: f64,               // #EXTINF 中的时长（秒）
+}
+
+/// fMP4/CMAF 流的初始化片段（#EXT-X-MAP）
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitSection {
+    pub url: String,
+    pub byte_range: Option<ByteRange>,
+    pub key: Option<SegmentKey>,
+}
+
+/// 资源中的字节范围
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl ByteRange {
+    // 范围结束位置（不含），长度为 0 或超出 u64 时返回 None
+    fn end(&self) -> Option<u64> {
+        self.offset
+            .checked_add(self.length)
+            .filter(|_| self.length > 0)
+    }
+
+    fn header_value(&self) -> Result<String, String> {
+        let end = self
+            .end()
+            .ok_or_else(|| format!("无效的字节范围: {}@{}", self.length, self.offset))?;
+        Ok(format!("bytes={}-{}", self.offset, end - 1))
+    }
+}
+
+/// 片段的 AES-128 加密信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentKey {
+    pub uri: String,
+    pub iv: Option<[u8; 16]>,
+}
+
+impl SegmentKey {
+    /// 未指定IV时使用媒体序列号（128位大端）作为IV
+    pub fn iv_for(&self, sequence: u64) -> [u8; 16] {
+        self.iv.unwrap_or_else(|| (sequence as u128).to_be_bytes())
+    }
+}
+
+/// 解密密钥缓存，同一播放列表中密钥轮换时按URI分别获取
+///
+/// 并行下载的分片共享同一个缓存，获取密钥时持有锁，避免重复请求同一个密钥。
+#[derive(Default)]
+pub struct KeyCache {
+    keys: Mutex<HashMap<String, [u8; 16]>>,
+}
+
+impl KeyCache {
+    pub async fn get(&self, client: &HttpClient, uri: &str) -> Result<[u8; 16], String> {
+        let mut keys = self.keys.lock().await;
+        if let Some(key) = keys.get(uri) {
+            return Ok(*key);
+        }
+
+        let response = client
+            .get(uri)
+            .send()
+            .await
+            .map_err(|e| format!("请求解密密钥失败: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("获取解密密钥失败，HTTP状态: {}", response.status()));
+        }
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| format!("读取解密密钥失败: {}", e))?;
+
+        let key: [u8; 16] = bytes
+            .as_ref()
+            .try_into()
+            .map_err(|_| format!("解密密钥长度应为16字节，实际为{}字节", bytes.len()))?;
+        keys.insert(uri.to_string(), key);
+        Ok(key)
+    }
+}
+
+/// 请求片段（支持字节范围），返回流式读取器，加密时边读取边解密
+pub async fn open_segment(
+    client: &HttpClient,
+    segment: &HlsSegment,
+    key_cache: &KeyCache,
+) -> Result<SegmentReader, String> {
+    let decryption = match &segment.key {
+        Some(key) => Some((
+            key_cache.get(client, &key.uri).await?,
+            key.iv_for(segment.sequence),
+        )),
+        None => None,
+    };
+    SegmentReader::open(client, &segment.url, segment.byte_range, decryption).await
+}
+
+/// 下载初始化片段，加密时使用 #EXT-X-KEY 中指定的IV解密
+pub async fn fetch_init_section(
+    client: &HttpClient,
+    init_section: &InitSection,
+    key_cache: &KeyCache,
+) -> Result<Vec<u8>, String> {
+    // 解析时已确认加密的初始化片段带有IV
+    let decryption = match &init_section.key {
+        Some(key) => Some((key_cache.get(client, &key.uri).await?, key.iv_for(0))),
+        None => None,
+    };
+    let mut reader = SegmentReader::open(
+        client,
+        &init_section.url,
+        init_section.byte_range,
+        decryption,
+    )
+    .await?;
+
+    // 初始化片段只有几KB，直接读入内存
+    let mut data = Vec::new();
+    while let Some(chunk) = reader.next_chunk().await? {
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+/// 片段内容的流式读取器，每次只在内存中保留一个网络数据块
+///
+/// 服务器不支持范围请求时从完整响应中跳过/截取所需部分；
+/// AES-128-CBC 解密时保留最后一个密文块，读完后再去除 PKCS7 填充。
+pub struct SegmentReader {
+    response: reqwest::Response,
+    skip: u64,              // 服务器忽略 Range 时需要跳过的字节数
+    remaining: Option<u64>, // 字节范围中还需读取的字节数
+    decryptor: Option<Aes128CbcDec>,
+    pending: Vec<u8>, // 尚未解密的密文
+    finished: bool,
+}
+
+impl SegmentReader {
+    async fn open(
+        client: &HttpClient,
+        url: &str,
+        byte_range: Option<ByteRange>,
+        decryption: Option<([u8; 16], [u8; 16])>,
+    ) -> Result<Self, String> {
+        let mut request = client.get(url);
+        if let Some(range) = byte_range {
+            request = request.header(RANGE, range.header_value()?);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("请求失败: {}", e))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("HTTP状态: {}", status));
+        }
+
+        let skip = match byte_range {
+            Some(range) if status != StatusCode::PARTIAL_CONTENT => range.offset,
+            _ => 0,
+        };
+        Ok(SegmentReader {
+            response,
+            skip,
+            remaining: byte_range.map(|range| range.length),
+            decryptor: decryption.map(|(key, iv)| Aes128CbcDec::new((&key).into(), (&iv).into())),
+            pending: Vec::new(),
+            finished: false,
+        })
+    }
+
+    /// 读取下一块（已解密的）数据，读完时返回 None
+    pub async fn next_chunk(&mut self) -> Result<Option<Vec<u8>>, String> {
+        while !self.finished {
+            let chunk = if self.remaining == Some(0) {
+                None
+            } else {
+                self.response
+                    .chunk()
+                    .await
+                    .map_err(|e| format!("读取数据失败: {}", e))?
+            };
+            let Some(chunk) = chunk else {
+                self.finished = true;
+                if self.remaining.is_some_and(|remaining| remaining > 0) {
+                    return Err("响应长度不足，无法读取完整的字节范围".to_string());
+                }
+                return self.finish_decryption().map(Some);
+            };
+
+            let mut data = &chunk[..];
+            let skipped = self.skip.min(data.len() as u64);
+            data = &data[skipped as usize..];
+            self.skip -= skipped;
+            if let Some(remaining) = &mut self.remaining {
+                let taken = (*remaining).min(data.len() as u64);
+                data = &data[..taken as usize];
+                *remaining -= taken;
+            }
+            if data.is_empty() {
+                continue;
+            }
+
+            let Some(decryptor) = &mut self.decryptor else {
+                return Ok(Some(data.to_vec()));
+            };
+            // 至少保留一个完整的密文块，最后一块需要去除填充
+            self.pending.extend_from_slice(data);
+            let keep = match self.pending.len() % 16 {
+                0 => 16,
+                partial => partial,
+            };
+            let ready = self.pending.len().saturating_sub(keep);
+            if ready == 0 {
+                continue;
+            }
+            let mut plain: Vec<u8> = self.pending.drain(..ready).collect();
+            for block in plain.chunks_exact_mut(16) {
+                decryptor.decrypt_block_mut(block.into());
+            }
+            return Ok(Some(plain));
+        }
+        Ok(None)
+    }
+
+    // 解密最后一个密文块并去除 PKCS7 填充
+    fn finish_decryption(&mut self) -> Result<Vec<u8>, String> {
+        let Some(decryptor) = self.decryptor.take() else {
+            return Ok(Vec::new());
+        };
+        if self.pending.len() != 16 {
+            return Err("加密片段长度不是16字节的整数倍".to_string());
+        }
+        let mut last_block = std::mem::take(&mut self.pending);
+        let plain_length = decryptor
+            .decrypt_padded_mut::<Pkcs7>(&mut last_block)
+            .map_err(|_| "解密片段失败，密钥或IV可能不正确".to_string())?
+            .len();
+        last_block.truncate(plain_length);
+        Ok(last_block)
+    }
+}
+
+// 解析 #EXT-X-KEY，METHOD=NONE 时返回 None
+fn parse_key(attributes: &str, base_url: &Url) -> Result<Option<SegmentKey>, String> {
+    let attributes = parse_attribute_list(attributes);
+    let method = attributes
+        .get("METHOD")
+        .map(String::as_str)
+        .unwrap_or("NONE");
+
+    match method {
+        "NONE" => Ok(None),
+        "AES-128" => {
+            if let Some(key_format) = attributes.get("KEYFORMAT") {
+                if key_format != "identity" {
+                    return Err(format!("不支持的密钥格式（DRM加密）: {}", key_format));
+                }
+            }
+            let uri = attributes
+                .get("URI")
+                .ok_or_else(|| "加密信息缺少密钥地址(URI)".to_string())?;
+            let iv = attributes.get("IV").map(|iv| parse_iv(iv)).transpose()?;
+            Ok(Some(SegmentKey {
+                uri: resolve_url(base_url, uri)?,
+                iv,
+            }))
+        }
+        // SAMPLE-AES 只加密部分音视频数据，需要解析封装格式才能解密
+        _ => Err(format!("不支持的加密方式: {}", method)),
+    }
+}
+
+// 解析十六进制IV（0x前缀），不足16字节时左侧补零
+fn parse_iv(value: &str) -> Result<[u8; 16], String> {
+    let hex = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .ok_or_else(|| format!("无效的IV: {}", value))?;
+    if hex.is_empty() || hex.len() > 32 {
+        return Err(format!("无效的IV: {}", value));
+    }
+
+    let number = u128::from_str_radix(hex, 16).map_err(|_| format!("无效的IV: {}", value))?;
+    Ok(number.to_be_bytes())
+}
+
+// 解析 `长度[@偏移]` 形式的字节范围，省略偏移时使用 default_offset
+fn parse_byte_range(value: &str, default_offset: Option<u64>) -> Result<ByteRange, String> {
+    let invalid = || format!("无效的字节范围: {}", value);
+    let (length, offset) = match value.trim().split_once('@') {
+        Some((length, offset)) => (length, Some(offset.parse().map_err(|_| invalid())?)),
+        None => (value.trim(), None),
+    };
+
+    let length: u64 = length.parse().map_err(|_| invalid())?;
+    let offset = offset
+        .or(default_offset)
+        .ok_or_else(|| format!("字节范围缺少偏移量: {}", value))?;
+    let range = ByteRange { offset, length };
+    // 长度为 0 或结束位置超出 u64 的范围无效
+    if range.end().is_none() {
+        return Err(invalid());
+    }
+    Ok(range)
+}
+
+// 解析媒体播放列表，提取视频片段及其加密、字节范围和初始化片段信息
+pub fn parse_media_playlist(content: &str, base_url: &Url) -> Result<HlsPlaylist, String> {
+    let mut playlist = HlsPlaylist::default();
+    let mut sequence = 0u64;
+    let mut key: Option<SegmentKey> = None;
+    let mut init_section = None;
+    let mut pending_byte_range: Option<String> = None;
+    let mut pending_discontinuity = false;
+    let mut pending_duration = 0.0;
+    // 上一个字节范围片段的地址和结束位置，省略偏移时从这里继续
+    let mut previous_range: Option<(String, u64)> = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+
+        if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            sequence = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("无效的媒体序列号: {}", value))?;
+            continue;
+        }
+        // 密钥对之后的片段生效，直到下一个 #EXT-X-KEY（密钥轮换）
+        if let Some(attributes) = line.strip_prefix("#EXT-X-KEY:") {
+            key = parse_key(attributes, base_url)?;
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("#EXT-X-BYTERANGE:") {
+            pending_byte_range = Some(value.to_string());
+            continue;
+        }
+        // "#EXTINF:<时长>,[标题]"，时长无法解析时记为 0
+        if let Some(value) = line.strip_prefix("#EXTINF:") {
+            let duration = value.split(',').next().unwrap_or_default();
+            pending_duration = duration.trim().parse().unwrap_or(0.0);
+            continue;
+        }
+        if line == "#EXT-X-DISCONTINUITY" {
+            pending_discontinuity = true;
+            continue;
+        }
+        if let Some(attributes) = line.strip_prefix("#EXT-X-MAP:") {
+            let attributes = parse_attribute_list(attributes);
+            let uri = attributes
+                .get("URI")
+                .ok_or_else(|| "初始化片段缺少地址(URI)".to_string())?;
+            // 加密的初始化片段必须指定IV
+            if key.as_ref().is_some_and(|key| key.iv.is_none()) {
+                return Err("加密的初始化片段未指定IV".to_string());
+            }
+            let section = InitSection {
+                url: resolve_url(base_url, uri)?,
+                byte_range: attributes
+                    .get("BYTERANGE")
+                    .map(|range| parse_byte_range(range, None))
+                    .transpose()?,
+                key: key.clone(),
+            };
+
+            // 相同的初始化片段只保存一份
+            let index = match playlist
+                .init_sections
+                .iter()
+                .position(|existing| existing == &section)
+            {
+                Some(index) => index,
+                None => {
+                    playlist.init_sections.push(section);
+                    playlist.init_sections.len() - 1
+                }
+            };
+            init_section = Some(index);
+            continue;
+        }
+
+        // 跳过其他注释行和空行
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+
+        let url = resolve_url(base_url, line)?;
+        let byte_range = match pending_byte_range.take() {
+            Some(value) => {
+                let default_offset = previous_range
+                    .as_ref()
+                    .filter(|(previous_url, _)| previous_url == &url)
+                    .map(|(_, end)| *end);
+                let range = parse_byte_range(&value, default_offset)?;
+                let end = range
+                    .end()
+                    .ok_or_else(|| format!("无效的字节范围: {}", value))?;
+                previous_range = Some((url.clone(), end));
+                Some(range)
+            }
+            None => None,
+        };
+
+        playlist.segments.push(HlsSegment {
+            url,
+            sequence,
+            key: key.clone(),
+            byte_range,
+            init_section,
+            discontinuity: std::mem::take(&mut pending_discontinuity),
+            duration: std::mem::take(&mut pending_duration),
+        });
+        sequence += 1;
+    }
+    Ok(playlist)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base_url() -> Url {
+        Url::parse("https://cdn.example.com/video/index.m3u8").unwrap()
+    }
+
+    fn preference(strategy: &str, max_height: Option<u32>) -> VideoQualityPreference {
+        VideoQualityPreference {
+            strategy: strategy.to_string(),
+            max_height,
+            max_bandwidth: None,
+        }
+    }
+
+    const MASTER: &str = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS=\"avc1.4d401e,mp4a.40.2\"
+360p/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,AVERAGE-BANDWIDTH=4000000,RESOLUTION=1920x1080
+https://other.example.com/1080p.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2500000,RESOLUTION=1280x720
+../720p/index.m3u8?token=abc
+";
+
+    #[test]
+    fn parses_master_playlist_variants() {
+        assert!(is_master_playlist(MASTER));
+        let variants = parse_master_playlist(MASTER, &base_url()).unwrap();
+        assert_eq!(variants.len(), 3);
+
+        assert_eq!(
+            variants[0].url,
+            "https://cdn.example.com/video/360p/index.m3u8"
+        );
+        assert_eq!(variants[0].bandwidth, Some(800000));
+        assert_eq!(variants[0].resolution, Some((640, 360)));
+        assert_eq!(variants[0].codecs.as_deref(), Some("avc1.4d401e,mp4a.40.2"));
+        // 有 AVERAGE-BANDWIDTH 时优先使用
+        assert_eq!(variants[1].bandwidth, Some(4000000));
+        assert_eq!(variants[1].url, "https://other.example.com/1080p.m3u8");
+        assert_eq!(
+            variants[2].url,
+            "https://cdn.example.com/720p/index.m3u8?token=abc"
+        );
+        assert!(variants
+            .iter()
+            .all(|variant| variant.external_audio.is_none()));
+    }
+
+    #[test]
+    fn selects_variant_by_preference() {
+        let variants = parse_master_playlist(MASTER, &base_url()).unwrap();
+        let height = |variant: Option<&VariantStream>| variant.unwrap().resolution.unwrap().1;
+
+        assert_eq!(
+            height(select_variant(&variants, &preference("highest", None))),
+            1080
+        );
+        assert_eq!(
+            height(select_variant(&variants, &preference("lowest", None))),
+            360
+        );
+        assert_eq!(
+            height(select_variant(&variants, &preference("highest", Some(720)))),
+            720
+        );
+        // 全部超过上限时取最低的一路
+        assert_eq!(
+            height(select_variant(&variants, &preference("highest", Some(240)))),
+            360
+        );
+
+        let bandwidth_limited = VideoQualityPreference {
+            max_bandwidth: Some(3000000),
+            ..preference("highest", None)
+        };
+        assert_eq!(height(select_variant(&variants, &bandwidth_limited)), 720);
+        assert!(select_variant(&[], &preference("highest", None)).is_none());
+    }
+
+    #[test]
+    fn detects_external_audio_renditions() {
+        let content = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=2000000,AUDIO=\"aac\"
+video.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,AUDIO=\"muxed\"
+muxed.m3u8
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",DEFAULT=YES,URI=\"audio/en.m3u8\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"muxed\",NAME=\"Main\",DEFAULT=YES
+";
+        let variants = parse_master_playlist(content, &base_url()).unwrap();
+        assert_eq!(variants[0].external_audio.as_deref(), Some("aac"));
+        // 没有 URI 的音轨表示音频在码流内
+        assert_eq!(variants[1].external_audio, None);
+    }
+
+    #[test]
+    fn parses_keys_and_rejects_unsupported_methods() {
+        let key = parse_key("METHOD=AES-128,URI=\"../keys/k1.bin\",IV=0x1F", &base_url())
+            .unwrap()
+            .unwrap();
+        assert_eq!(key.uri, "https://cdn.example.com/keys/k1.bin");
+        let mut iv = [0u8; 16];
+        iv[15] = 0x1F;
+        assert_eq!(key.iv, Some(iv));
+
+        assert_eq!(parse_key("METHOD=NONE", &base_url()).unwrap(), None);
+        assert!(parse_key("METHOD=AES-128", &base_url()).is_err());
+        assert!(parse_key("METHOD=SAMPLE-AES,URI=\"k\"", &base_url()).is_err());
+        assert!(parse_key(
+            "METHOD=AES-128,URI=\"k\",KEYFORMAT=\"com.apple.streamingkeydelivery\"",
+            &base_url()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parses_iv_values() {
+        let iv = parse_iv("0x000102030405060708090A0B0C0D0E0F").unwrap();
+        assert_eq!(iv, core::array::from_fn(|i| i as u8));
+        assert!(parse_iv("000102").is_err());
+        assert!(parse_iv("0x").is_err());
+        assert!(parse_iv("0xZZ").is_err());
+        assert!(parse_iv(&format!("0x{}", "0".repeat(33))).is_err());
+    }
+
+    #[test]
+    fn derives_iv_from_media_sequence() {
+        let content = "#EXTM3U
+#EXT-X-MEDIA-SEQUENCE:7
+#EXT-X-KEY:METHOD=AES-128,URI=\"key1\"
+#EXTINF:4.0,
+a.ts
+#EXTINF:4.0,
+b.ts
+#EXT-X-KEY:METHOD=AES-128,URI=\"key2\",IV=0x01
+#EXTINF:4.0,
+c.ts
+#EXT-X-KEY:METHOD=NONE
+#EXTINF:4.0,
+d.ts
+";
+        let playlist = parse_media_playlist(content, &base_url()).unwrap();
+        let segments = &playlist.segments;
+        assert_eq!(segments.len(), 4);
+
+        let first = segments[0].key.as_ref().unwrap();
+        assert_eq!(first.uri, "https://cdn.example.com/video/key1");
+        assert_eq!(segments[0].sequence, 7);
+        assert_eq!(first.iv_for(segments[0].sequence), 7u128.to_be_bytes());
+        assert_eq!(
+            segments[1]
+                .key
+                .as_ref()
+                .unwrap()
+                .iv_for(segments[1].sequence),
+            8u128.to_be_bytes()
+        );
+        // 显式指定的IV不随序列号变化
+        let rotated = segments[2].key.as_ref().unwrap();
+        assert_eq!(rotated.uri, "https://cdn.example.com/video/key2");
+        assert_eq!(rotated.iv_for(segments[2].sequence), 1u128.to_be_bytes());
+        assert_eq!(segments[3].key, None);
+    }
+
+    #[test]
+    fn parses_byte_ranges() {
+        assert_eq!(
+            parse_byte_range("1000@200", None),
+            Ok(ByteRange {
+                offset: 200,
+                length: 1000
+            })
+        );
+        assert_eq!(
+            parse_byte_range("500", Some(1200)),
+            Ok(ByteRange {
+                offset: 1200,
+                length: 500
+            })
+        );
+        assert!(parse_byte_range("500", None).is_err());
+        assert!(parse_byte_range("0@10", None).is_err());
+        assert!(parse_byte_range("abc@0", None).is_err());
+        // 结束位置溢出时返回错误而不是 panic
+        assert!(parse_byte_range(&format!("2@{}", u64::MAX), None).is_err());
+        assert!(parse_byte_range("1", Some(u64::MAX)).is_err());
+
+        let range = ByteRange {
+            offset: 100,
+            length: 50,
+        };
+        assert_eq!(range.header_value(), Ok("bytes=100-149".to_string()));
+        let invalid = ByteRange {
+            offset: 0,
+            length: 0,
+        };
+        assert!(invalid.header_value().is_err());
+    }
+
+    #[test]
+    fn byte_range_offset_follows_previous_range_of_same_resource() {
+        let content = "#EXTM3U
+#EXTINF:4.0,
+#EXT-X-BYTERANGE:1000@0
+video.ts
+#EXTINF:4.0,
+#EXT-X-BYTERANGE:800
+video.ts
+#EXTINF:4.0,
+#EXT-X-BYTERANGE:300
+other.ts
+";
+        // 省略偏移的范围只能接在同一资源的上一个范围之后
+        assert!(parse_media_playlist(content, &base_url()).is_err());
+
+        let content = content.replace("#EXT-X-BYTERANGE:300", "#EXT-X-BYTERANGE:300@50");
+        let playlist = parse_media_playlist(&content, &base_url()).unwrap();
+        let ranges: Vec<_> = playlist
+            .segments
+            .iter()
+            .map(|segment| segment.byte_range.unwrap())
+            .map(|range| (range.offset, range.length))
+            .collect();
+        assert_eq!(ranges, vec![(0, 1000), (1000, 800), (50, 300)]);
+    }
+
+    #[test]
+    fn parses_init_sections_and_discontinuities() {
+        let content = "#EXTM3U
+#EXT-X-MAP:URI=\"init.mp4\",BYTERANGE=\"720@0\"
+#EXTINF:6.006,title
+seg1.m4s
+#EXTINF:6.006,
+seg2.m4s
+#EXT-X-DISCONTINUITY
+#EXT-X-MAP:URI=\"init2.mp4\"
+#EXTINF:3.5,
+seg3.m4s
+#EXT-X-DISCONTINUITY
+#EXT-X-MAP:URI=\"init.mp4\",BYTERANGE=\"720@0\"
+#EXTINF:bad,
+seg4.m4s
+";
+        let playlist = parse_media_playlist(content, &base_url()).unwrap();
+        assert_eq!(playlist.init_sections.len(), 2);
+        assert_eq!(
+            playlist.init_sections[0].url,
+            "https://cdn.example.com/video/init.mp4"
+        );
+        assert_eq!(
+            playlist.init_sections[0].byte_range,
+            Some(ByteRange {
+                offset: 0,
+                length: 720
+            })
+        );
+        assert_eq!(playlist.init_sections[1].byte_range, None);
+
+        let segments = &playlist.segments;
+        let init: Vec<_> = segments.iter().map(|s| s.init_section).collect();
+        assert_eq!(init, vec![Some(0), Some(0), Some(1), Some(0)]);
+        let discontinuity: Vec<_> = segments.iter().map(|s| s.discontinuity).collect();
+        assert_eq!(discontinuity, vec![false, false, true, true]);
+        let duration: Vec<_> = segments.iter().map(|s| s.duration).collect();
+        assert_eq!(duration, vec![6.006, 6.006, 3.5, 0.0]);
+    }
+
+    #[test]
+    fn rejects_encrypted_init_section_without_iv() {
+        let content = "#EXTM3U
+#EXT-X-KEY:METHOD=AES-128,URI=\"key\"
+#EXT-X-MAP:URI=\"init.mp4\"
+#EXTINF:4.0,
+seg1.m4s
+";
+        assert!(parse_media_playlist(content, &base_url()).is_err());
+    }
+
+    #[test]
+    fn attribute_list_keeps_commas_in_quotes() {
+        let attributes = parse_attribute_list(
+            "BANDWIDTH=1280000,CODECS=\"avc1.640028,mp4a.40.2\",resolution=1920x1080,URI=\"a,b.m3u8\"",
+        );
+        assert_eq!(
+            attributes.get("BANDWIDTH").map(String::as_str),
+            Some("1280000")
+        );
+        assert_eq!(
+            attributes.get("CODECS").map(String::as_str),
+            Some("avc1.640028,mp4a.40.2")
+        );
+        assert_eq!(
+            attributes.get("RESOLUTION").map(String::as_str),
+            Some("1920x1080")
+        );
+        assert_eq!(attributes.get("URI").map(String::as_str), Some("a,b.m3u8"));
+        assert_eq!(attributes.len(), 4);
+    }
+}
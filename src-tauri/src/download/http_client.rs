@@ -0,0 +1,372 @@
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Method, RequestBuilder, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::fs;
+
+const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36";
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 15;
+const DEFAULT_READ_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_POOL_MAX_IDLE_PER_HOST: usize = 16;
+const MAX_TIMEOUT_SECS: u64 = 600;
+// 服务器配置中由 HTTP 客户端自行决定的请求头，不从配置中带上
+const SERVER_ONLY_HEADERS: [&str; 3] = ["host", "content-length", "content-type"];
+// 默认附加服务器请求头的图片 CDN，API 源的主机总是包含在内
+const DEFAULT_SERVER_HEADER_HOSTS: [&str; 1] = ["mangafuna.xyz"];
+
+/// 共享 HTTP 客户端的配置
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct HttpClientConfig {
+    pub user_agent: String, // 为空时使用请求头中的 user-agent
+    pub default_headers: BTreeMap<String, String>, // 附加到每个请求的请求头
+    pub use_server_headers: bool, // 向 API 源和 server_header_hosts 中的主机带上服务器设置（server.json）中的请求头
+    pub server_header_hosts: Vec<String>, // 除 API 源外附加服务器请求头的主机，同时匹配其子域名
+    pub connect_timeout_secs: u64,
+    pub read_timeout_secs: u64, // 两次收到数据之间的最长等待，大文件下载不受总时长限制
+    pub pool_max_idle_per_host: usize, // 每个主机保留的空闲连接数
+    pub http2: bool,            // 允许与服务器协商 HTTP/2，关闭时只用 HTTP/1.1
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        HttpClientConfig {
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            default_headers: BTreeMap::new(),
+            use_server_headers: true,
+            server_header_hosts: DEFAULT_SERVER_HEADER_HOSTS
+                .iter()
+                .map(|host| host.to_string())
+                .collect(),
+            connect_timeout_secs: DEFAULT_CONNECT_TIMEOUT_SECS,
+            read_timeout_secs: DEFAULT_READ_TIMEOUT_SECS,
+            pool_max_idle_per_host: DEFAULT_POOL_MAX_IDLE_PER_HOST,
+            http2: true,
+        }
+    }
+}
+
+/// 共享 HTTP 客户端，只向允许的主机附加服务器设置中的请求头
+#[derive(Clone, Default)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    server_headers: Arc<ServerHeaders>,
+}
+
+#[derive(Default)]
+struct ServerHeaders {
+    hosts: Vec<String>,
+    headers: HeaderMap,
+}
+
+impl HttpClient {
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.request(Method::GET, url)
+    }
+
+    pub fn head(&self, url: &str) -> RequestBuilder {
+        self.request(Method::HEAD, url)
+    }
+
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+        match host {
+            Some(host) if host_matches(&self.server_headers.hosts, &host) => {
+                request.headers(self.server_headers.headers.clone())
+            }
+            _ => request,
+        }
+    }
+}
+
+/// 由 Tauri 管理的共享 HTTP 客户端，修改配置后整体替换
+pub struct HttpClientState(RwLock<HttpClient>);
+
+/// 获取共享 HTTP 客户端配置
+#[tauri::command]
+pub async fn get_http_client_config(app_handle: AppHandle) -> Result<HttpClientConfig, String> {
+    load_http_client_config(&app_handle).await
+}
+
+/// 保存共享 HTTP 客户端配置并立即生效
+#[tauri::command]
+pub async fn set_http_client_config(
+    app_handle: AppHandle,
+    config: HttpClientConfig,
+) -> Result<(), String> {
+    validate_http_client_config(&config)?;
+    // 先构建客户端，请求头无效时不保存配置
+    let client = build_http_client(&app_handle, &config).await?;
+
+    let config_path = get_http_client_config_path(&app_handle)?;
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("创建配置目录失败: {}", e))?;
+    }
+
+    let content =
+        serde_json::to_string_pretty(&config).map_err(|e| format!("序列化网络设置失败: {}", e))?;
+    fs::write(&config_path, content)
+        .await
+        .map_err(|e| format!("写入网络设置失败: {}", e))?;
+
+    replace_http_client(&app_handle, client);
+    Ok(())
+}
+
+/// 按当前配置重新创建共享 HTTP 客户端，服务器设置中的请求头变化后调用
+#[tauri::command]
+pub async fn reload_http_client(app_handle: AppHandle) -> Result<(), String> {
+    let config = load_http_client_config(&app_handle).await?;
+    let client = build_http_client(&app_handle, &config).await?;
+    replace_http_client(&app_handle, client);
+    Ok(())
+}
+
+/// 创建共享 HTTP 客户端并交给 Tauri 管理，应在处理前端请求之前调用
+pub async fn init_http_client(app_handle: &AppHandle) {
+    let config = load_http_client_config(app_handle)
+        .await
+        .unwrap_or_default();
+    let client = match build_http_client(app_handle, &config).await {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("创建HTTP客户端失败，使用默认设置: {}", e);
+            build_http_client(app_handle, &HttpClientConfig::default())
+                .await
+                .unwrap_or_default()
+        }
+    };
+    app_handle.manage(HttpClientState(RwLock::new(client)));
+}
+
+/// 共享 HTTP 客户端，所有下载复用同一个连接池
+pub fn http_client(app_handle: &AppHandle) -> HttpClient {
+    app_handle
+        .state::<HttpClientState>()
+        .0
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+fn replace_http_client(app_handle: &AppHandle, client: HttpClient) {
+    *app_handle
+        .state::<HttpClientState>()
+        .0
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = client;
+}
+
+/// 读取共享 HTTP 客户端配置，不存在或无效时使用默认值
+pub async fn load_http_client_config(app_handle: &AppHandle) -> Result<HttpClientConfig, String> {
+    let config_path = get_http_client_config_path(app_handle)?;
+
+    let config = match fs::read_to_string(&config_path).await {
+        Ok(content) => serde_json::from_str::<HttpClientConfig>(&content).ok(),
+        Err(_) => None,
+    };
+
+    let config = config.unwrap_or_default();
+    if let Err(e) = validate_http_client_config(&config) {
+        eprintln!("网络设置无效，使用默认设置: {}", e);
+        return Ok(HttpClientConfig::default());
+    }
+
+    Ok(config)
+}
+
+fn validate_http_client_config(config: &HttpClientConfig) -> Result<(), String> {
+    if !(1..=MAX_TIMEOUT_SECS).contains(&config.connect_timeout_secs)
+        || !(1..=MAX_TIMEOUT_SECS).contains(&config.read_timeout_secs)
+    {
+        return Err(format!("超时时间应在 1 到 {} 秒之间", MAX_TIMEOUT_SECS));
+    }
+    for host in &config.server_header_hosts {
+        let valid = !host.is_empty()
+            && host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+        if !valid {
+            return Err(format!("无效的主机名: {}", host));
+        }
+    }
+    Ok(())
+}
+
+// 主机与列表中的某一项相同，或是其子域名
+fn host_matches(hosts: &[String], host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    hosts.iter().any(|allowed| {
+        let allowed = allowed.to_ascii_lowercase();
+        host == allowed
+            || host
+                .strip_suffix(&allowed)
+                .is_some_and(|prefix| prefix.ends_with('.'))
+    })
+}
+
+async fn build_http_client(
+    app_handle: &AppHandle,
+    config: &HttpClientConfig,
+) -> Result<HttpClient, String> {
+    // 服务器设置中的请求头由前端维护，个别无效时跳过；只发给 API 源和配置的 CDN 主机
+    let mut server_headers = ServerHeaders::default();
+    if config.use_server_headers {
+        for (name, value) in load_server_headers(app_handle).await {
+            if SERVER_ONLY_HEADERS.contains(&name.to_lowercase().as_str()) {
+                continue;
+            }
+            if let Err(e) = insert_header(&mut server_headers.headers, &name, &value) {
+                eprintln!("跳过服务器设置中的请求头: {}", e);
+            }
+        }
+        server_headers.hosts = load_api_hosts(app_handle).await;
+        server_headers
+            .hosts
+            .extend(config.server_header_hosts.iter().cloned());
+    }
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in &config.default_headers {
+        insert_header(&mut headers, name, value)?;
+    }
+
+    let mut builder = reqwest::Client::builder()
+        .default_headers(headers)
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .read_timeout(Duration::from_secs(config.read_timeout_secs))
+        .pool_max_idle_per_host(config.pool_max_idle_per_host);
+    // 在默认请求头之后设置，覆盖其中的 user-agent
+    let user_agent = config.user_agent.trim();
+    if !user_agent.is_empty() {
+        builder = builder.user_agent(user_agent);
+    }
+    if !config.http2 {
+        builder = builder.http1_only();
+    }
+    let client = builder
+        .build()
+        .map_err(|e| format!("创建HTTP客户端失败: {}", e))?;
+    Ok(HttpClient {
+        client,
+        server_headers: Arc::new(server_headers),
+    })
+}
+
+fn insert_header(headers: &mut HeaderMap, name: &str, value: &str) -> Result<(), String> {
+    let header_name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| format!("无效的请求头名称: {}", name))?;
+    let header_value =
+        HeaderValue::from_str(value.trim()).map_err(|_| format!("请求头 {} 的值无效", name))?;
+    headers.insert(header_name, header_value);
+    Ok(())
+}
+
+// 读取前端服务器设置中用户配置的请求头
+async fn load_server_headers(app_handle: &AppHandle) -> Vec<(String, String)> {
+    let Ok(app_data_dir) = app_handle.path().app_data_dir() else {
+        return Vec::new();
+    };
+    let Ok(content) = fs::read_to_string(app_data_dir.join("config").join("server.json")).await
+    else {
+        return Vec::new();
+    };
+    let Ok(config) = serde_json::from_str::<Value>(&content) else {
+        return Vec::new();
+    };
+
+    config
+        .get("requestHeaders")
+        .and_then(|headers| headers.as_object())
+        .map(|headers| {
+            headers
+                .iter()
+                .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// 读取前端应用配置中 API 源（含轻小说 API 源）的主机名
+async fn load_api_hosts(app_handle: &AppHandle) -> Vec<String> {
+    let Ok(app_data_dir) = app_handle.path().app_data_dir() else {
+        return Vec::new();
+    };
+    let Ok(content) = fs::read_to_string(app_data_dir.join("config").join("copymanga.json")).await
+    else {
+        return Vec::new();
+    };
+    let Ok(config) = serde_json::from_str::<Value>(&content) else {
+        return Vec::new();
+    };
+
+    ["apiSources", "bookApiSources"]
+        .iter()
+        .filter_map(|key| config.get(key)?.as_array())
+        .flatten()
+        .filter_map(|source| Url::parse(source.as_str()?).ok())
+        .filter_map(|url| url.host_str().map(str::to_string))
+        .collect()
+}
+
+fn get_http_client_config_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("无法获取应用数据目录: {}", e))?;
+
+    Ok(app_data_dir.join("config").join("http_client.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_headers_only_match_listed_hosts_and_subdomains() {
+        let hosts = vec![
+            "api.copy2000.online".to_string(),
+            "mangafuna.xyz".to_string(),
+        ];
+        assert!(host_matches(&hosts, "api.copy2000.online"));
+        assert!(host_matches(&hosts, "s3.mangafuna.xyz"));
+        assert!(host_matches(&hosts, "S3.MangaFuna.xyz"));
+        assert!(!host_matches(&hosts, "copy2000.online"));
+        assert!(!host_matches(&hosts, "evilmangafuna.xyz"));
+        assert!(!host_matches(&hosts, "mangafuna.xyz.example.com"));
+    }
+
+    #[test]
+    fn requests_carry_server_headers_by_host() {
+        let mut headers = HeaderMap::new();
+        headers.insert("platform", HeaderValue::from_static("3"));
+        let client = HttpClient {
+            client: reqwest::Client::new(),
+            server_headers: Arc::new(ServerHeaders {
+                hosts: vec!["mangafuna.xyz".to_string()],
+                headers,
+            }),
+        };
+
+        let request = client
+            .get("https://hi77.mangafuna.xyz/a.jpg")
+            .build()
+            .unwrap();
+        assert_eq!(request.headers().get("platform").unwrap(), "3");
+        let request = client
+            .head("https://video.example.com/a.m3u8")
+            .build()
+            .unwrap();
+        assert!(request.headers().get("platform").is_none());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::download::hls::HlsSegment;
+use crate::download::manifest::segment_file_path;
+use crate::download::utils::get_downloads_path;
+use crate::path_guard::SafeSegment;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use tokio::fs;
+
+// 边下载边播放的本地播放列表，位于章节临时目录中，合并完成后随临时目录删除
+const LOCAL_PLAYLIST_FILE: &str = "local.m3u8";
+
+/// 本地播放列表的状态
+#[derive(Debug, Serialize, Default)]
+pub struct LocalPlaylistStatus {
+    pub available: bool,       // 已有可播放的分片
+    pub playlist_path: String, // 前端通过 convertFileSrc 转换后交给播放器
+    pub ready_segments: usize, // 从头开始连续下载完成的分片数
+    pub ready_duration: f64,   // 可播放的时长（秒）
+    pub finished: bool,        // 全部分片已下载，播放列表不再增长
+}
+
+/// 获取正在下载的章节的本地播放列表
+#[tauri::command]
+pub async fn get_cartoon_local_playlist(
+    cartoon_uuid: SafeSegment,
+    chapter_uuid: SafeSegment,
+    app_handle: AppHandle,
+) -> Result<LocalPlaylistStatus, String> {
+    let downloads_path = get_downloads_path(&app_handle).await?;
+    let playlist_path = downloads_path
+        .join("cartoons")
+        .join(&cartoon_uuid)
+        .join(&chapter_uuid)
+        .join("temp_segments")
+        .join(LOCAL_PLAYLIST_FILE);
+
+    let Ok(content) = fs::read_to_string(&playlist_path).await else {
+        return Ok(LocalPlaylistStatus::default());
+    };
+    let durations: Vec<f64> = content
+        .lines()
+        .filter_map(|line| line.strip_prefix("#EXTINF:"))
+        .map(|value| value.trim_end_matches(',').parse().unwrap_or(0.0))
+        .collect();
+
+    Ok(LocalPlaylistStatus {
+        available: !durations.is_empty(),
+        playlist_path: playlist_path.to_string_lossy().to_string(),
+        ready_segments: durations.len(),
+        ready_duration: durations.iter().sum(),
+        finished: content.lines().any(|line| line == "#EXT-X-ENDLIST"),
+    })
+}
+
+/// 用前 `ready` 个已下载的分片生成本地播放列表
+///
+/// 分片已解密保存，播放列表中不再包含密钥；全部分片就绪时写入 #EXT-X-ENDLIST。
+/// 先写入临时文件再重命名，播放器不会读到写了一半的列表。
+pub async fn write_local_playlist(
+    temp_dir: &Path,
+    segments: &[HlsSegment],
+    init_files: &[PathBuf],
+    ready: usize,
+) -> Result<(), String> {
+    let ready_segments = &segments[..ready.min(segments.len())];
+    let target_duration = segments
+        .iter()
+        .map(|segment| segment.duration.ceil() as u64)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    // EXT-X-MAP 需要版本 6 及以上
+    let version = if init_files.is_empty() { 3 } else { 7 };
+
+    let mut content = String::new();
+    let _ = writeln!(content, "#EXTM3U");
+    let _ = writeln!(content, "#EXT-X-VERSION:{}", version);
+    let _ = writeln!(content, "#EXT-X-TARGETDURATION:{}", target_duration);
+    let _ = writeln!(content, "#EXT-X-PLAYLIST-TYPE:EVENT");
+    let _ = writeln!(content, "#EXT-X-MEDIA-SEQUENCE:0");
+
+    let mut written_init = None;
+    for (index, segment) in ready_segments.iter().enumerate() {
+        if index > 0 && segment.discontinuity {
+            let _ = writeln!(content, "#EXT-X-DISCONTINUITY");
+        }
+        if let Some(init_index) = segment
+            .init_section
+            .filter(|_| segment.init_section != written_init)
+        {
+            let _ = writeln!(
+                content,
+                "#EXT-X-MAP:URI=\"{}\"",
+                asset_url(&init_files[init_index])
+            );
+            written_init = segment.init_section;
+        }
+        let _ = writeln!(content, "#EXTINF:{:.3},", segment.duration);
+        let _ = writeln!(
+            content,
+            "{}",
+            asset_url(&segment_file_path(temp_dir, index))
+        );
+    }
+    if ready_segments.len() == segments.len() {
+        let _ = writeln!(content, "#EXT-X-ENDLIST");
+    }
+
+    let playlist_path = temp_dir.join(LOCAL_PLAYLIST_FILE);
+    let part_path = temp_dir.join(format!("{}.part", LOCAL_PLAYLIST_FILE));
+    fs::write(&part_path, content)
+        .await
+        .map_err(|e| format!("写入本地播放列表失败: {}", e))?;
+    fs::rename(&part_path, &playlist_path)
+        .await
+        .map_err(|e| format!("写入本地播放列表失败: {}", e))
+}
+
+/// 删除本地播放列表
+pub async fn remove_local_playlist(temp_dir: &Path) {
+    let _ = fs::remove_file(temp_dir.join(LOCAL_PLAYLIST_FILE)).await;
+}
+
+// 与前端 convertFileSrc 相同的 asset 协议地址；convertFileSrc 会编码整个路径，
+// 播放列表中的相对地址无法正确解析，因此每个分片都写成完整地址
+fn asset_url(path: &Path) -> String {
+    let encoded = urlencoding::encode(&path.to_string_lossy()).into_owned();
+    if cfg!(any(windows, target_os = "android")) {
+        format!("http://asset.localhost/{}", encoded)
+    } else {
+        format!("asset://localhost/{}", encoded)
+    }
+}
@@ -1,8 +1,16 @@
+use crate::download::ffmpeg::part_path_of;
+use crate::download::http_client::{http_client, HttpClient};
+use crate::download::naming::{
+    clean_extension, load_naming_config, render_file_name, resolve_collision, NamingContext,
+    TemplateKind,
+};
+use crate::download::trash::{move_to_trash, read_display_name};
 use crate::download::types::*;
 use crate::download::utils::*;
+use crate::path_guard::SafeSegment;
 use serde_json::{json, Value};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use tauri::AppHandle;
@@ -16,21 +24,22 @@ lazy_static::lazy_static! {
 
 #[tauri::command]
 pub async fn download_chapter(
-    manga_uuid: String,
+    manga_uuid: SafeSegment,
     manga_name: String,
-    group_path_word: String,
-    chapter_uuid: String,
+    group_path_word: SafeSegment,
+    chapter_uuid: SafeSegment,
     chapter_name: String,
     total_images: usize, // 添加总图片数量参数
     images: Vec<ImageInfo>,
     manga_detail: Option<MangaDetail>,
+    chapter_index: Option<usize>, // 章节序号（从1开始）
     app_handle: AppHandle,
 ) -> Result<DownloadResult, String> {
     let download_info = DownloadInfo {
-        manga_uuid: manga_uuid.clone(),
+        manga_uuid: manga_uuid.to_string(),
         manga_name: manga_name.clone(),
-        group_path_word: group_path_word.clone(),
-        chapter_uuid: chapter_uuid.clone(),
+        group_path_word: group_path_word.to_string(),
+        chapter_uuid: chapter_uuid.to_string(),
         chapter_name: chapter_name.clone(),
         images,
         manga_detail: manga_detail.clone(),
@@ -49,7 +58,11 @@ pub async fn download_chapter(
     if let Some(ref detail) = manga_detail {
         // 保存漫画详情JSON文件
         let manga_detail_path = manga_path.join("manga_detail.json");
-        let detail_content = serde_json::to_string_pretty(detail)
+        let versioned_detail = MangaDetail {
+            schema_version: METADATA_SCHEMA_VERSION,
+            ..detail.clone()
+        };
+        let detail_content = serde_json::to_string_pretty(&versioned_detail)
             .map_err(|e| format!("序列化漫画详情失败: {}", e))?;
 
         if let Err(e) = fs::write(&manga_detail_path, detail_content).await {
@@ -66,11 +79,7 @@ pub async fn download_chapter(
 
             // 检查封面是否已存在
             if !cover_path.exists() {
-                let client = reqwest::Client::builder()
-                    .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-                    .timeout(std::time::Duration::from_secs(30))
-                    .build()
-                    .map_err(|e| format!("创建HTTP客户端失败: {}", e))?;
+                let client = http_client(&app_handle);
                 match download_image(&client, &detail.cover, &cover_path).await {
                     Ok(_) => {} // 封面下载成功，无需输出
                     Err(e) => eprintln!("封面下载失败: {} - {}", detail.cover, e),
@@ -89,19 +98,34 @@ pub async fn download_chapter(
     // 确保目录存在
     if let Err(e) = fs::create_dir_all(&chapter_path).await {
         return Err(format!("创建目录失败: {}", e));
-    } // 创建章节信息文件
+    }
+
+    // 续传时未传入序号则沿用已有章节信息中的序号
+    let info_path = chapter_path.join("info.json");
+    let chapter_index = match chapter_index {
+        Some(index) => Some(index),
+        None => match fs::read_to_string(&info_path).await {
+            Ok(content) => serde_json::from_str::<ChapterInfo>(&content)
+                .ok()
+                .and_then(|info| info.chapter_index),
+            Err(_) => None,
+        },
+    };
+
+    // 创建章节信息文件
     let chapter_info = ChapterInfo {
+        schema_version: METADATA_SCHEMA_VERSION,
         manga_uuid: download_info.manga_uuid.clone(),
         manga_name: download_info.manga_name.clone(),
         group_path_word: download_info.group_path_word.clone(),
         chapter_uuid: download_info.chapter_uuid.clone(),
         chapter_name: download_info.chapter_name.clone(),
+        chapter_index,
         total_images,       // 保存总图片数量
         images: Vec::new(), // 将在下载完成后填充
         download_time: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
     };
 
-    let info_path = chapter_path.join("info.json");
     let info_content = serde_json::to_string_pretty(&chapter_info)
         .map_err(|e| format!("序列化章节信息失败: {}", e))?;
 
@@ -110,28 +134,57 @@ pub async fn download_chapter(
         .map_err(|e| format!("保存章节信息失败: {}", e))?;
 
     // 下载图片
-    let client = reqwest::Client::builder()
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-        .timeout(std::time::Duration::from_secs(30))
-        .build()
-        .map_err(|e| format!("创建HTTP客户端失败: {}", e))?;
+    let client = http_client(&app_handle);
+    // 按命名模板生成图片文件名，文件名经过清理，不会逃逸出章节目录
+    let naming_config = load_naming_config(&app_handle).await?;
+    let mut used_names = HashSet::new();
+    let mut image_file_names = Vec::with_capacity(download_info.images.len());
+    for image_info in download_info.images.iter() {
+        // {original} 取图片地址中的文件名，地址中没有文件名时使用前端生成的文件名
+        let original = source_file_name(&image_info.url)
+            .unwrap_or_else(|| get_filename_from_url(&image_info.filename));
+        let original_stem = original
+            .rsplit_once('.')
+            .map_or(original.as_str(), |(stem, _)| stem);
+        let context = NamingContext {
+            series: &download_info.manga_name,
+            group: &download_info.group_path_word,
+            chapter: &download_info.chapter_name,
+            index: Some(image_info.index + 1),
+            original: original_stem,
+        };
+        let file_name = render_file_name(
+            &naming_config.manga_page_template,
+            TemplateKind::MangaPage,
+            &context,
+            &clean_extension(&original, &clean_extension(&image_info.filename, "jpg")),
+        )?;
+        // 章节目录中已有的文件都属于本章节（断点续传）
+        image_file_names.push(resolve_collision(
+            &chapter_path,
+            &file_name,
+            &mut used_names,
+            |_| true,
+        ));
+    }
+
     let mut downloaded_images = Vec::new();
     let chapter_key = format!("{}|{}|{}", manga_uuid, group_path_word, chapter_uuid);
 
     // 确保暂停标志初始状态是false
     set_pause_flag(&chapter_key, false);
 
-    for image_info in download_info.images.iter() {
+    for (image_info, file_name) in download_info.images.iter().zip(&image_file_names) {
         // 检查是否被暂停 - 在每张图片开始前检查
         if is_paused(&chapter_key) {
             break;
         }
 
-        let image_path = chapter_path.join(&image_info.filename);
+        let image_path = chapter_path.join(file_name);
 
         // 检查图片是否已存在
         if image_path.exists() {
-            downloaded_images.push(image_info.filename.clone());
+            downloaded_images.push(file_name.clone());
             continue;
         }
 
@@ -142,7 +195,7 @@ pub async fn download_chapter(
 
         match download_image(&client, &image_info.url, &image_path).await {
             Ok(_) => {
-                downloaded_images.push(image_info.filename.clone());
+                downloaded_images.push(file_name.clone());
 
                 // 在下载完成后立即检查暂停状态
                 if is_paused(&chapter_key) {
@@ -170,11 +223,13 @@ pub async fn download_chapter(
     // 清理暂停标志
     clear_pause_flag(&chapter_key); // 更新章节信息文件，包含已下载的图片列表
     let updated_chapter_info = ChapterInfo {
+        schema_version: METADATA_SCHEMA_VERSION,
         manga_uuid: download_info.manga_uuid.clone(),
         manga_name: download_info.manga_name.clone(),
         group_path_word: download_info.group_path_word.clone(),
         chapter_uuid: download_info.chapter_uuid.clone(),
         chapter_name: download_info.chapter_name.clone(),
+        chapter_index,
         total_images, // 保持总图片数量
         images: downloaded_images,
         download_time: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
@@ -197,9 +252,9 @@ pub async fn download_chapter(
 #[tauri::command]
 pub async fn get_local_chapter_images(
     app_handle: AppHandle,
-    manga_uuid: String,
-    group_path_word: String,
-    chapter_uuid: String,
+    manga_uuid: SafeSegment,
+    group_path_word: SafeSegment,
+    chapter_uuid: SafeSegment,
 ) -> Result<Vec<String>, String> {
     let manga_downloads_path = get_manga_downloads_path(&app_handle).await?;
     let chapter_path = manga_downloads_path
@@ -239,9 +294,9 @@ pub async fn get_local_chapter_images(
 #[tauri::command]
 pub async fn delete_downloaded_chapter(
     app_handle: AppHandle,
-    manga_uuid: String,
-    group_path_word: String,
-    chapter_uuid: String,
+    manga_uuid: SafeSegment,
+    group_path_word: SafeSegment,
+    chapter_uuid: SafeSegment,
 ) -> Result<Value, String> {
     let manga_downloads_path = get_manga_downloads_path(&app_handle).await?;
     let chapter_path = manga_downloads_path
@@ -253,21 +308,25 @@ pub async fn delete_downloaded_chapter(
         return Err("章节不存在".to_string());
     }
 
-    // 删除整个章节目录
-    fs::remove_dir_all(&chapter_path)
+    // 移动章节目录到回收站
+    let chapter_name = read_display_name(&chapter_path.join("info.json"), "chapter_name")
+        .await
+        .unwrap_or_else(|| chapter_uuid.to_string());
+    let trash_item = move_to_trash(&app_handle, &chapter_path, "manga_chapter", &chapter_name)
         .await
         .map_err(|e| format!("删除章节失败: {}", e))?;
 
     Ok(json!({
         "success": true,
-        "message": "章节删除成功"
+        "message": "章节已移至回收站",
+        "trashId": trash_item.id
     }))
 }
 
 #[tauri::command]
 pub async fn delete_local_manga(
     app_handle: AppHandle,
-    manga_uuid: String,
+    manga_uuid: SafeSegment,
 ) -> Result<Value, String> {
     let manga_downloads_path = get_manga_downloads_path(&app_handle).await?;
     let manga_path = manga_downloads_path.join(&manga_uuid);
@@ -276,14 +335,18 @@ pub async fn delete_local_manga(
         return Err("本地漫画不存在".to_string());
     }
 
-    // 删除整个漫画目录
-    fs::remove_dir_all(&manga_path)
+    // 移动整个漫画目录到回收站
+    let manga_name = read_display_name(&manga_path.join("manga_detail.json"), "name")
+        .await
+        .unwrap_or_else(|| manga_uuid.to_string());
+    let trash_item = move_to_trash(&app_handle, &manga_path, "manga", &manga_name)
         .await
         .map_err(|e| format!("删除漫画失败: {}", e))?;
 
     Ok(json!({
         "success": true,
-        "message": "漫画删除成功"
+        "message": "漫画已移至回收站",
+        "trashId": trash_item.id
     }))
 }
 
@@ -348,7 +411,7 @@ pub async fn get_downloaded_manga_list(app_handle: AppHandle) -> Result<Vec<Valu
 #[tauri::command]
 pub async fn get_local_manga_detail(
     app_handle: AppHandle,
-    manga_uuid: String,
+    manga_uuid: SafeSegment,
 ) -> Result<Value, String> {
     let manga_downloads_path = get_manga_downloads_path(&app_handle).await?;
     let manga_path = manga_downloads_path.join(&manga_uuid);
@@ -381,7 +444,7 @@ pub async fn get_local_manga_detail(
 #[tauri::command]
 pub async fn get_local_manga_chapters(
     app_handle: AppHandle,
-    manga_uuid: String,
+    manga_uuid: SafeSegment,
 ) -> Result<Vec<Value>, String> {
     let manga_downloads_path = get_manga_downloads_path(&app_handle).await?;
     let manga_path = manga_downloads_path.join(&manga_uuid);
@@ -432,11 +495,14 @@ pub async fn get_local_manga_chapters(
                 }
             }
         }
-    } // 按章节名排序
+    } // 按章节序号排序，旧版本下载的章节按名称排在后面
     chapters.sort_by(|a, b| {
-        let a_name = a["chapter_name"].as_str().unwrap_or("");
-        let b_name = b["chapter_name"].as_str().unwrap_or("");
-        a_name.cmp(b_name)
+        chapter_cmp(
+            json_chapter_index(a),
+            a["chapter_name"].as_str().unwrap_or(""),
+            json_chapter_index(b),
+            b["chapter_name"].as_str().unwrap_or(""),
+        )
     });
 
     Ok(chapters)
@@ -445,9 +511,9 @@ pub async fn get_local_manga_chapters(
 #[tauri::command]
 pub async fn get_download_progress(
     app_handle: AppHandle,
-    manga_uuid: String,
-    group_path_word: String,
-    chapter_uuid: String,
+    manga_uuid: SafeSegment,
+    group_path_word: SafeSegment,
+    chapter_uuid: SafeSegment,
     expected_image_count: usize,
 ) -> Result<DownloadProgress, String> {
     let manga_downloads_path = get_manga_downloads_path(&app_handle).await?;
@@ -509,12 +575,8 @@ pub async fn get_download_progress(
 }
 
 // 辅助函数
-pub async fn download_image(
-    client: &reqwest::Client,
-    url: &str,
-    path: &PathBuf,
-) -> Result<(), String> {
-    let response = client
+pub async fn download_image(client: &HttpClient, url: &str, path: &PathBuf) -> Result<(), String> {
+    let mut response = client
         .get(url)
         .send()
         .await
@@ -524,26 +586,53 @@ pub async fn download_image(
         return Err(format!("下载图片失败: HTTP {}", response.status()));
     }
 
-    let bytes = response
-        .bytes()
-        .await
-        .map_err(|e| format!("读取图片数据失败: {}", e))?;
+    // 先逐块写入 .part 文件，完整下载后再重命名；中断或出错时不会留下被当作已下载的残缺图片
+    let part_path = part_path_of(path);
+    let result = match write_image_body(&mut response, &part_path).await {
+        Ok(()) => fs::rename(&part_path, path)
+            .await
+            .map_err(|e| format!("保存图片失败: {}", e)),
+        Err(e) => Err(e),
+    };
+    if result.is_err() {
+        let _ = fs::remove_file(&part_path).await;
+    }
+    result
+}
 
+// 逐块写入文件，不把整张图片读入内存
+async fn write_image_body(response: &mut reqwest::Response, path: &Path) -> Result<(), String> {
     let mut file = fs::File::create(path)
         .await
         .map_err(|e| format!("创建文件失败: {}", e))?;
 
-    file.write_all(&bytes)
+    while let Some(chunk) = response
+        .chunk()
         .await
-        .map_err(|e| format!("写入文件失败: {}", e))?;
+        .map_err(|e| format!("读取图片数据失败: {}", e))?
+    {
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| format!("写入文件失败: {}", e))?;
+    }
 
-    Ok(())
+    file.flush()
+        .await
+        .map_err(|e| format!("写入文件失败: {}", e))
 }
 
 pub fn get_filename_from_url(url: &str) -> String {
     url.split('/').last().unwrap_or("image").to_string()
 }
 
+// 图片地址路径的最后一段（已解码），忽略查询参数
+fn source_file_name(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let name = url.path_segments()?.next_back()?;
+    let name = urlencoding::decode(name).ok()?;
+    (!name.trim().is_empty()).then(|| name.into_owned())
+}
+
 pub fn get_extension_from_filename(filename: &str) -> String {
     filename.split('.').last().unwrap_or("jpg").to_string()
 }
@@ -690,9 +779,9 @@ pub async fn resume_chapter_download(
 
 #[tauri::command]
 pub async fn check_incomplete_download(
-    manga_uuid: String,
-    group_path_word: String,
-    chapter_uuid: String,
+    manga_uuid: SafeSegment,
+    group_path_word: SafeSegment,
+    chapter_uuid: SafeSegment,
     app_handle: AppHandle,
 ) -> Result<IncompleteDownloadResult, String> {
     let manga_downloads_path = get_manga_downloads_path(&app_handle).await?;
@@ -749,9 +838,9 @@ pub async fn check_incomplete_download(
 #[tauri::command]
 pub async fn check_chapter_download_detail(
     app_handle: AppHandle,
-    manga_uuid: String,
-    group_path_word: String,
-    chapter_uuid: String,
+    manga_uuid: SafeSegment,
+    group_path_word: SafeSegment,
+    chapter_uuid: SafeSegment,
 ) -> Result<ChapterDownloadDetail, String> {
     let manga_downloads_path = get_manga_downloads_path(&app_handle).await?;
     let chapter_path = manga_downloads_path
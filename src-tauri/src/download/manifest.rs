@@ -0,0 +1,553 @@
+use crate::download::hls::{ByteRange, HlsPlaylist, HlsSegment, VariantStream};
+use crate::download::types::METADATA_SCHEMA_VERSION;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+// 清单文件名，位于分片临时目录中
+const MANIFEST_FILE: &str = "manifest.json";
+const VERIFY_BUFFER_SIZE: usize = 64 * 1024;
+// 不同线路的播放列表时长精度可能不同（如 10 与 10.000）
+const DURATION_TOLERANCE: f64 = 0.01;
+
+/// HLS 分片下载清单，记录每个分片的完成状态，用于断点续传
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct SegmentManifest {
+    pub schema_version: u32,
+    pub playlist_url: String,
+    pub playlist_hash: String, // 分片列表的哈希，播放列表变化时逐个分片重新校验
+    pub variant: String,       // 选中码流的分辨率、编码和带宽，不是主播放列表时为空
+    pub segments: Vec<SegmentRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct SegmentRecord {
+    pub index: usize,
+    pub url: String,
+    pub byte_range: Option<(u64, u64)>, // (偏移, 长度)
+    pub duration: f64,                  // 播放列表中的片段时长，切换线路时用于判断分片是否对应
+    pub size: u64,
+    pub checksum: String, // 解密后分片内容的 SHA-256
+    pub completed: bool,
+}
+
+impl SegmentRecord {
+    fn new(index: usize, segment: &HlsSegment) -> Self {
+        SegmentRecord {
+            index,
+            url: segment.url.clone(),
+            byte_range: segment
+                .byte_range
+                .map(|ByteRange { offset, length }| (offset, length)),
+            duration: segment.duration,
+            ..Default::default()
+        }
+    }
+
+    // 是否与播放列表中的分片是同一个资源，忽略查询参数（签名、过期时间等会变化）
+    fn matches(&self, other: &SegmentRecord) -> bool {
+        strip_query(&self.url) == strip_query(&other.url) && self.byte_range == other.byte_range
+    }
+}
+
+// 码流标识，记录在清单中用于判断切换线路前后是否为同一路编码
+fn variant_identity(variant: Option<&VariantStream>) -> String {
+    let Some(variant) = variant else {
+        return String::new();
+    };
+    let resolution = variant
+        .resolution
+        .map(|(width, height)| format!("{}x{}", width, height))
+        .unwrap_or_default();
+    format!(
+        "resolution={};codecs={};bandwidth={}",
+        resolution,
+        variant.codecs.as_deref().unwrap_or(""),
+        variant.bandwidth.map(|b| b.to_string()).unwrap_or_default()
+    )
+}
+
+// 切换线路后分片地址全部变化，只有两条线路选中的码流标识相同、分片数量和每个分片的时长
+// 都一致时才认为是同一视频的相同切分，已下载的分片可以按索引继续使用。
+// 没有码流标识（不是主播放列表）时无法确认编码一致，不复用。
+fn same_segment_layout(previous: &SegmentManifest, current: &SegmentManifest) -> bool {
+    let (previous_variant, current_variant) = (&previous.variant, &current.variant);
+    let (previous, current) = (&previous.segments, &current.segments);
+    !previous_variant.is_empty()
+        && previous_variant == current_variant
+        && previous.len() == current.len()
+        && previous.iter().zip(current).all(|(old, new)| {
+            old.duration > 0.0 && (old.duration - new.duration).abs() < DURATION_TOLERANCE
+        })
+}
+
+impl SegmentManifest {
+    /// 读取临时目录中的清单并与当前播放列表校验
+    ///
+    /// 播放列表中对应位置的分片地址变化、文件缺失或大小/校验和不一致的分片都会重新下载；
+    /// 没有清单时（旧版本下载或首次下载）已有的分片无法校验，全部重新下载；
+    /// 切换线路后码流标识、分片数量和时长都一致时，已下载的分片按索引继续使用。
+    pub async fn load_or_create(
+        temp_dir: &Path,
+        playlist_url: &str,
+        playlist: &HlsPlaylist,
+        variant: Option<&VariantStream>,
+    ) -> Self {
+        let expected: Vec<SegmentRecord> = playlist
+            .segments
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| SegmentRecord::new(index, segment))
+            .collect();
+        let playlist_hash = hash_segment_list(&expected);
+
+        let previous = match fs::read_to_string(temp_dir.join(MANIFEST_FILE)).await {
+            Ok(content) => serde_json::from_str::<SegmentManifest>(&content).ok(),
+            Err(_) => None,
+        };
+        if previous.is_none() {
+            remove_unlisted_segments(temp_dir, &[]).await;
+        }
+
+        let mut manifest = SegmentManifest {
+            schema_version: METADATA_SCHEMA_VERSION,
+            playlist_url: playlist_url.to_string(),
+            playlist_hash,
+            variant: variant_identity(variant),
+            segments: expected,
+        };
+
+        let Some(previous) = previous else {
+            return manifest;
+        };
+        if previous.playlist_hash != manifest.playlist_hash {
+            eprintln!("播放列表已变化，逐个校验已下载的分片");
+        }
+        let line_switched = strip_query(&previous.playlist_url) != strip_query(playlist_url)
+            && same_segment_layout(&previous, &manifest);
+        if line_switched {
+            eprintln!("线路已切换且码流和分片一致，从已下载的分片继续");
+        } else if previous.variant != manifest.variant {
+            eprintln!(
+                "码流已变化（{} -> {}），丢弃已下载的分片",
+                previous.variant, manifest.variant
+            );
+        }
+
+        let mut reused = 0;
+        for record in &mut manifest.segments {
+            let Some(old) = previous.segments.get(record.index) else {
+                continue;
+            };
+            let same_source = line_switched || old.matches(record);
+            if !old.completed || previous.variant != manifest.variant || !same_source {
+                continue;
+            }
+            if verify_segment_file(&segment_file_path(temp_dir, record.index), old).await {
+                record.size = old.size;
+                record.checksum = old.checksum.clone();
+                record.completed = true;
+                reused += 1;
+            }
+        }
+
+        // 删除未通过校验的分片，避免合并时误用
+        let valid: Vec<usize> = manifest
+            .segments
+            .iter()
+            .filter(|record| record.completed)
+            .map(|record| record.index)
+            .collect();
+        remove_unlisted_segments(temp_dir, &valid).await;
+
+        eprintln!(
+            "分片清单校验完成: {}/{} 个分片可复用",
+            reused,
+            manifest.segments.len()
+        );
+        manifest
+    }
+
+    /// 尚未完成的分片索引
+    pub fn pending_indices(&self) -> Vec<usize> {
+        self.segments
+            .iter()
+            .filter(|record| !record.completed)
+            .map(|record| record.index)
+            .collect()
+    }
+
+    /// 从第一个分片开始连续完成的分片数
+    pub fn completed_prefix(&self) -> usize {
+        self.segments
+            .iter()
+            .take_while(|record| record.completed)
+            .count()
+    }
+
+    pub fn completed_count(&self) -> usize {
+        self.segments
+            .iter()
+            .filter(|record| record.completed)
+            .count()
+    }
+
+    pub fn completed_bytes(&self) -> u64 {
+        self.segments
+            .iter()
+            .filter(|record| record.completed)
+            .map(|record| record.size)
+            .sum()
+    }
+
+    pub fn mark_completed(&mut self, index: usize, size: u64, checksum: String) {
+        if let Some(record) = self.segments.get_mut(index) {
+            record.size = size;
+            record.checksum = checksum;
+            record.completed = true;
+        }
+    }
+
+    /// 保存清单，先写临时文件再重命名，避免中断时留下损坏的清单
+    pub async fn save(&self, temp_dir: &Path) -> Result<(), String> {
+        let content =
+            serde_json::to_string(self).map_err(|e| format!("序列化分片清单失败: {}", e))?;
+        let manifest_path = temp_dir.join(MANIFEST_FILE);
+        let temp_path = manifest_path.with_extension("json.part");
+
+        fs::write(&temp_path, content)
+            .await
+            .map_err(|e| format!("写入分片清单失败: {}", e))?;
+        fs::rename(&temp_path, &manifest_path)
+            .await
+            .map_err(|e| format!("写入分片清单失败: {}", e))
+    }
+}
+
+/// 分片在临时目录中的文件路径
+pub fn segment_file_path(temp_dir: &Path, index: usize) -> PathBuf {
+    temp_dir.join(format!("segment_{:04}.ts", index))
+}
+
+/// 分片内容的增量校验和，边下载边计算
+#[derive(Default)]
+pub struct Checksum(Sha256);
+
+impl Checksum {
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    pub fn finish(self) -> String {
+        format!("{:x}", self.0.finalize())
+    }
+}
+
+fn hash_segment_list(records: &[SegmentRecord]) -> String {
+    let mut hasher = Sha256::new();
+    for record in records {
+        hasher.update(strip_query(&record.url).as_bytes());
+        if let Some((offset, length)) = record.byte_range {
+            hasher.update(format!("@{}:{}", offset, length).as_bytes());
+        }
+        hasher.update(b"\n");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+pub(crate) fn strip_query(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap_or(url)
+}
+
+// 校验已下载分片的大小和校验和，按固定大小的缓冲区读取
+async fn verify_segment_file(path: &Path, record: &SegmentRecord) -> bool {
+    let Ok(file) = fs::File::open(path).await else {
+        return false;
+    };
+    let mut reader = BufReader::with_capacity(VERIFY_BUFFER_SIZE, file);
+    let mut checksum = Checksum::default();
+    let mut size = 0u64;
+    loop {
+        let Ok(buffer) = reader.fill_buf().await else {
+            return false;
+        };
+        if buffer.is_empty() {
+            break;
+        }
+        checksum.update(buffer);
+        let length = buffer.len();
+        size += length as u64;
+        reader.consume(length);
+    }
+    size == record.size && checksum.finish() == record.checksum
+}
+
+// 删除不在有效列表中的分片文件和未写完的 .part 文件
+async fn remove_unlisted_segments(temp_dir: &Path, valid: &[usize]) {
+    let Ok(mut entries) = fs::read_dir(temp_dir).await else {
+        return;
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let file_name = entry.file_name();
+        let file_name_str = file_name.to_string_lossy();
+
+        let index = file_name_str
+            .strip_prefix("segment_")
+            .and_then(|s| s.strip_suffix(".ts"))
+            .and_then(|s| s.parse::<usize>().ok());
+        let stale = match index {
+            Some(index) => !valid.contains(&index),
+            None => file_name_str.ends_with(".part"),
+        };
+        if stale {
+            let _ = fs::remove_file(entry.path()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist(urls: &[&str], duration: f64) -> HlsPlaylist {
+        HlsPlaylist {
+            segments: urls
+                .iter()
+                .enumerate()
+                .map(|(index, url)| HlsSegment {
+                    url: url.to_string(),
+                    sequence: index as u64,
+                    key: None,
+                    byte_range: None,
+                    init_section: None,
+                    discontinuity: false,
+                    duration,
+                })
+                .collect(),
+            init_sections: Vec::new(),
+        }
+    }
+
+    fn variant(height: u32) -> VariantStream {
+        VariantStream {
+            url: "https://cdn.example.com/index.m3u8".to_string(),
+            bandwidth: Some(2_000_000),
+            resolution: Some((height * 16 / 9, height)),
+            codecs: Some("avc1.64001f,mp4a.40.2".to_string()),
+            external_audio: None,
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("manifest_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn manifest(completed: &[bool]) -> SegmentManifest {
+        SegmentManifest {
+            segments: completed
+                .iter()
+                .enumerate()
+                .map(|(index, completed)| SegmentRecord {
+                    index,
+                    completed: *completed,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    // 写入分片文件并在清单中标记完成，然后保存清单
+    async fn download_segments(dir: &Path, manifest: &mut SegmentManifest, indices: &[usize]) {
+        for index in indices {
+            let content = format!("segment {}", index).into_bytes();
+            std::fs::write(segment_file_path(dir, *index), &content).unwrap();
+            let mut checksum = Checksum::default();
+            checksum.update(&content);
+            manifest.mark_completed(*index, content.len() as u64, checksum.finish());
+        }
+        manifest.save(dir).await.unwrap();
+    }
+
+    const URLS: [&str; 3] = [
+        "https://cdn.example.com/seg0.ts?token=a",
+        "https://cdn.example.com/seg1.ts?token=a",
+        "https://cdn.example.com/seg2.ts?token=a",
+    ];
+
+    #[test]
+    fn pending_indices_and_completed_prefix() {
+        let gaps = manifest(&[true, true, false, true, false]);
+        assert_eq!(gaps.pending_indices(), vec![2, 4]);
+        assert_eq!(gaps.completed_prefix(), 2);
+        assert_eq!(gaps.completed_count(), 3);
+
+        let first_pending = manifest(&[false, true, true]);
+        assert_eq!(first_pending.completed_prefix(), 0);
+        assert_eq!(first_pending.pending_indices(), vec![0]);
+        assert!(manifest(&[true, true]).pending_indices().is_empty());
+    }
+
+    #[test]
+    fn same_segment_layout_requires_variant_count_and_durations() {
+        let build = |variant: Option<&VariantStream>, durations: &[f64]| SegmentManifest {
+            variant: variant_identity(variant),
+            segments: durations
+                .iter()
+                .enumerate()
+                .map(|(index, duration)| SegmentRecord {
+                    index,
+                    duration: *duration,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let hd = variant(720);
+        let previous = build(Some(&hd), &[10.0, 10.0, 4.5]);
+
+        // 时长精度不同但在误差范围内
+        assert!(same_segment_layout(
+            &previous,
+            &build(Some(&hd), &[10.0, 10.001, 4.5])
+        ));
+        assert!(!same_segment_layout(
+            &previous,
+            &build(Some(&hd), &[10.0, 9.0, 4.5])
+        ));
+        assert!(!same_segment_layout(
+            &previous,
+            &build(Some(&hd), &[10.0, 10.0])
+        ));
+        assert!(!same_segment_layout(
+            &previous,
+            &build(Some(&variant(1080)), &[10.0, 10.0, 4.5])
+        ));
+        // 没有码流标识或时长未知时不复用
+        assert!(!same_segment_layout(
+            &build(None, &[10.0, 10.0, 4.5]),
+            &build(None, &[10.0, 10.0, 4.5])
+        ));
+        assert!(!same_segment_layout(
+            &build(Some(&hd), &[0.0, 0.0]),
+            &build(Some(&hd), &[0.0, 0.0])
+        ));
+    }
+
+    #[tokio::test]
+    async fn load_or_create_keeps_verified_segments() {
+        let dir = temp_dir("keep");
+        let playlist_url = "https://cdn.example.com/index.m3u8?token=a";
+        let mut manifest =
+            SegmentManifest::load_or_create(&dir, playlist_url, &playlist(&URLS, 10.0), None).await;
+        download_segments(&dir, &mut manifest, &[0, 1]).await;
+
+        // 只有查询参数（签名）变化时视为同一播放列表
+        let refreshed: Vec<&str> = URLS.iter().map(|url| url.trim_end_matches('a')).collect();
+        let reloaded = SegmentManifest::load_or_create(
+            &dir,
+            "https://cdn.example.com/index.m3u8?token=b",
+            &playlist(&refreshed, 10.0),
+            None,
+        )
+        .await;
+        assert_eq!(reloaded.pending_indices(), vec![2]);
+        assert_eq!(reloaded.completed_bytes(), manifest.completed_bytes());
+        assert!(segment_file_path(&dir, 1).exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn load_or_create_discards_changed_or_corrupted_segments() {
+        let dir = temp_dir("discard");
+        let playlist_url = "https://cdn.example.com/index.m3u8";
+        let mut manifest =
+            SegmentManifest::load_or_create(&dir, playlist_url, &playlist(&URLS, 10.0), None).await;
+        download_segments(&dir, &mut manifest, &[0, 1, 2]).await;
+
+        // 分片 1 的地址变化，分片 2 的内容被改动
+        std::fs::write(segment_file_path(&dir, 2), b"corrupted").unwrap();
+        let changed = [URLS[0], "https://cdn.example.com/other1.ts", URLS[2]];
+        let reloaded =
+            SegmentManifest::load_or_create(&dir, playlist_url, &playlist(&changed, 10.0), None)
+                .await;
+        assert_eq!(reloaded.pending_indices(), vec![1, 2]);
+        assert!(segment_file_path(&dir, 0).exists());
+        assert!(!segment_file_path(&dir, 1).exists());
+        assert!(!segment_file_path(&dir, 2).exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn load_or_create_handles_variant_and_line_switches() {
+        let dir = temp_dir("switch");
+        let hd = variant(720);
+        let mut manifest = SegmentManifest::load_or_create(
+            &dir,
+            "https://line1.example.com/index.m3u8",
+            &playlist(&URLS, 10.0),
+            Some(&hd),
+        )
+        .await;
+        download_segments(&dir, &mut manifest, &[0, 1]).await;
+
+        // 切换线路后分片地址全部变化，码流和切分一致时按索引复用
+        let line2 = [
+            "https://line2.example.com/a.ts",
+            "https://line2.example.com/b.ts",
+            "https://line2.example.com/c.ts",
+        ];
+        let switched = SegmentManifest::load_or_create(
+            &dir,
+            "https://line2.example.com/index.m3u8",
+            &playlist(&line2, 10.0),
+            Some(&hd),
+        )
+        .await;
+        assert_eq!(switched.pending_indices(), vec![2]);
+        switched.save(&dir).await.unwrap();
+
+        // 码流变化时丢弃全部分片
+        let other = SegmentManifest::load_or_create(
+            &dir,
+            "https://line2.example.com/index.m3u8",
+            &playlist(&line2, 10.0),
+            Some(&variant(1080)),
+        )
+        .await;
+        assert_eq!(other.pending_indices(), vec![0, 1, 2]);
+        assert!(!segment_file_path(&dir, 0).exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn load_or_create_without_manifest_removes_unverifiable_segments() {
+        let dir = temp_dir("missing");
+        std::fs::write(segment_file_path(&dir, 0), b"old download").unwrap();
+        std::fs::write(dir.join("segment_0001.ts.part"), b"partial").unwrap();
+
+        let manifest = SegmentManifest::load_or_create(
+            &dir,
+            "https://cdn.example.com/index.m3u8",
+            &playlist(&URLS, 10.0),
+            None,
+        )
+        .await;
+        assert_eq!(manifest.pending_indices(), vec![0, 1, 2]);
+        assert!(!segment_file_path(&dir, 0).exists());
+        assert!(!dir.join("segment_0001.ts.part").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
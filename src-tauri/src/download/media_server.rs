@@ -0,0 +1,573 @@
+use crate::download::cartoon::{list_completed_chapters, read_completed_chapter};
+use crate::download::trash::read_display_name;
+use crate::download::utils::{get_downloads_path, natural_cmp};
+use crate::path_guard::SafeSegment;
+use axum::body::Body;
+use axum::extract::{Path as RoutePath, Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::io::SeekFrom;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use tauri::{AppHandle, Manager};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::{oneshot, Mutex};
+use tokio_util::io::ReaderStream;
+
+const DEFAULT_BIND_ADDRESS: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 17380;
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+// 监听非本机地址时访问令牌的最小长度，避免局域网内被轻易猜出
+const MIN_ACCESS_TOKEN_LENGTH: usize = 16;
+
+/// 本地视频服务配置，默认关闭且只监听本机
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MediaServerConfig {
+    pub enabled: bool,
+    pub bind_address: String, // 如 "127.0.0.1"，局域网访问使用 "0.0.0.0"
+    pub port: u16,            // 0 表示由系统分配
+    pub access_token: Option<String>, // 访问令牌，监听非本机地址时必须设置
+}
+
+impl Default for MediaServerConfig {
+    fn default() -> Self {
+        MediaServerConfig {
+            enabled: false,
+            bind_address: DEFAULT_BIND_ADDRESS.to_string(),
+            port: DEFAULT_PORT,
+            access_token: None,
+        }
+    }
+}
+
+/// 本地视频服务运行状态
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct MediaServerStatus {
+    pub running: bool,
+    pub address: Option<String>, // 实际监听的地址，如 "127.0.0.1:17380"
+}
+
+// 正在运行的服务，发送关闭信号后停止接受新连接
+struct RunningServer {
+    address: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+}
+
+static MEDIA_SERVER: OnceLock<Mutex<Option<RunningServer>>> = OnceLock::new();
+
+fn get_running_server() -> &'static Mutex<Option<RunningServer>> {
+    MEDIA_SERVER.get_or_init(|| Mutex::new(None))
+}
+
+#[derive(Clone)]
+struct ServerState {
+    library_dir: PathBuf, // downloads/cartoons
+    access_token: Option<Arc<str>>,
+}
+
+/// 动画库索引
+#[derive(Debug, Serialize)]
+struct LibraryIndex {
+    cartoons: Vec<IndexCartoon>,
+}
+
+#[derive(Debug, Serialize)]
+struct IndexCartoon {
+    uuid: String,
+    name: String,
+    chapters: Vec<IndexChapter>,
+}
+
+#[derive(Debug, Serialize)]
+struct IndexChapter {
+    uuid: String,
+    name: String,
+    file_size: u64,
+    mime_type: &'static str,
+    url: String, // 可直接交给播放器的地址，设置了令牌时包含令牌
+}
+
+/// 获取本地视频服务配置
+#[tauri::command]
+pub async fn get_media_server_config(app_handle: AppHandle) -> Result<MediaServerConfig, String> {
+    load_media_server_config(&app_handle).await
+}
+
+/// 保存本地视频服务配置并按新配置重启服务
+#[tauri::command]
+pub async fn set_media_server_config(
+    app_handle: AppHandle,
+    config: MediaServerConfig,
+) -> Result<MediaServerStatus, String> {
+    validate_media_server_config(&config)?;
+
+    let config_path = get_media_server_config_path(&app_handle)?;
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("创建配置目录失败: {}", e))?;
+    }
+
+    let content = serde_json::to_string_pretty(&config)
+        .map_err(|e| format!("序列化视频服务配置失败: {}", e))?;
+    fs::write(&config_path, content)
+        .await
+        .map_err(|e| format!("写入视频服务配置失败: {}", e))?;
+
+    apply_media_server_config(&app_handle).await
+}
+
+/// 获取本地视频服务运行状态
+#[tauri::command]
+pub async fn get_media_server_status() -> Result<MediaServerStatus, String> {
+    Ok(current_status(&*get_running_server().lock().await))
+}
+
+/// 按配置启动或停止本地视频服务，启动时和修改配置后调用
+pub async fn apply_media_server_config(
+    app_handle: &AppHandle,
+) -> Result<MediaServerStatus, String> {
+    let config = load_media_server_config(app_handle).await?;
+    let mut running = get_running_server().lock().await;
+
+    // 先停止旧的服务，正在传输的连接会继续到结束
+    if let Some(server) = running.take() {
+        let _ = server.shutdown.send(());
+        eprintln!("本地视频服务已停止: {}", server.address);
+    }
+    if !config.enabled {
+        return Ok(current_status(&running));
+    }
+
+    let bind_address: IpAddr = config
+        .bind_address
+        .trim()
+        .parse()
+        .map_err(|_| format!("无效的监听地址: {}", config.bind_address))?;
+    let listener = tokio::net::TcpListener::bind((bind_address, config.port))
+        .await
+        .map_err(|e| format!("启动本地视频服务失败: {}", e))?;
+    let address = listener
+        .local_addr()
+        .map_err(|e| format!("启动本地视频服务失败: {}", e))?;
+
+    let downloads_path = get_downloads_path(app_handle).await?;
+    let state = ServerState {
+        library_dir: downloads_path.join("cartoons"),
+        access_token: config
+            .access_token
+            .as_deref()
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+            .map(Arc::from),
+    };
+    let router = Router::new()
+        .route("/api/cartoons", get(library_index))
+        .route("/cartoons/:cartoon_uuid/:chapter_uuid", get(chapter_video))
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state);
+
+    let (shutdown, shutdown_signal) = oneshot::channel::<()>();
+    tauri::async_runtime::spawn(async move {
+        let result = axum::serve(listener, router)
+            .with_graceful_shutdown(async move {
+                let _ = shutdown_signal.await;
+            })
+            .await;
+        if let Err(e) = result {
+            eprintln!("本地视频服务异常退出: {}", e);
+        }
+    });
+
+    eprintln!("本地视频服务已启动: http://{}", address);
+    *running = Some(RunningServer { address, shutdown });
+    Ok(current_status(&running))
+}
+
+/// 读取本地视频服务配置，不存在或无效时使用默认配置
+pub async fn load_media_server_config(app_handle: &AppHandle) -> Result<MediaServerConfig, String> {
+    let config_path = get_media_server_config_path(app_handle)?;
+
+    let config = match fs::read_to_string(&config_path).await {
+        Ok(content) => serde_json::from_str::<MediaServerConfig>(&content).ok(),
+        Err(_) => None,
+    };
+
+    let config = config.unwrap_or_default();
+    if let Err(e) = validate_media_server_config(&config) {
+        eprintln!("视频服务配置无效，使用默认配置: {}", e);
+        return Ok(MediaServerConfig::default());
+    }
+
+    Ok(config)
+}
+
+fn validate_media_server_config(config: &MediaServerConfig) -> Result<(), String> {
+    let bind_address: IpAddr = config
+        .bind_address
+        .trim()
+        .parse()
+        .map_err(|_| format!("无效的监听地址: {}", config.bind_address))?;
+    let token_length = config
+        .access_token
+        .as_deref()
+        .map(|token| token.trim().chars().count())
+        .unwrap_or(0);
+    // 局域网中的其他设备可以访问，必须用足够长的令牌限制
+    if !bind_address.is_loopback() {
+        if token_length == 0 {
+            return Err("监听本机以外的地址时必须设置访问令牌".to_string());
+        }
+        if token_length < MIN_ACCESS_TOKEN_LENGTH {
+            return Err(format!(
+                "监听本机以外的地址时访问令牌至少需要 {} 个字符",
+                MIN_ACCESS_TOKEN_LENGTH
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn get_media_server_config_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("无法获取应用数据目录: {}", e))?;
+
+    Ok(app_data_dir.join("config").join("media_server.json"))
+}
+
+fn current_status(running: &Option<RunningServer>) -> MediaServerStatus {
+    MediaServerStatus {
+        running: running.is_some(),
+        address: running.as_ref().map(|server| server.address.to_string()),
+    }
+}
+
+// 校验访问令牌，支持 ?token= 参数（播放器只能使用地址）和 Authorization: Bearer 请求头
+async fn require_token(State(state): State<ServerState>, request: Request, next: Next) -> Response {
+    let Some(expected) = state.access_token.as_deref() else {
+        return next.run(request).await;
+    };
+
+    let query_token = request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(name, _)| *name == "token")
+            .and_then(|(_, value)| urlencoding::decode(value).ok())
+            .map(|value| value.into_owned())
+    });
+    let header_token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|value| value.trim().to_string());
+
+    let authorized = [query_token, header_token]
+        .into_iter()
+        .flatten()
+        .any(|token| constant_time_eq(token.as_bytes(), expected.as_bytes()));
+    if authorized {
+        next.run(request).await
+    } else {
+        (StatusCode::UNAUTHORIZED, "访问令牌无效").into_response()
+    }
+}
+
+// 比较耗时与令牌内容无关
+fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    left.len() == right.len()
+        && left
+            .iter()
+            .zip(right)
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// 已下载完成的动画和剧集列表
+async fn library_index(State(state): State<ServerState>, headers: HeaderMap) -> Response {
+    // 播放地址使用请求中的主机名，局域网设备拿到的是它能访问的地址
+    let base_url = headers
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .map(|host| format!("http://{}", host))
+        .unwrap_or_default();
+    let token_query = state
+        .access_token
+        .as_deref()
+        .map(|token| format!("?token={}", urlencoding::encode(token)))
+        .unwrap_or_default();
+
+    let mut cartoons = Vec::new();
+    let mut cartoon_entries = match fs::read_dir(&state.library_dir).await {
+        Ok(entries) => entries,
+        Err(_) => return Json(LibraryIndex { cartoons }).into_response(),
+    };
+    while let Ok(Some(cartoon_entry)) = cartoon_entries.next_entry().await {
+        let cartoon_path = cartoon_entry.path();
+        if !cartoon_path.is_dir() {
+            continue;
+        }
+        let cartoon_uuid = cartoon_entry.file_name().to_string_lossy().to_string();
+
+        let mut chapters = Vec::new();
+        for (info, video_path) in list_completed_chapters(&cartoon_path).await {
+            chapters.push(IndexChapter {
+                url: format!(
+                    "{}/cartoons/{}/{}{}",
+                    base_url,
+                    urlencoding::encode(&cartoon_uuid),
+                    urlencoding::encode(&info.chapter_uuid),
+                    token_query
+                ),
+                uuid: info.chapter_uuid,
+                name: info.chapter_name,
+                file_size: info.file_size,
+                mime_type: mime_type(&video_path),
+            });
+        }
+        if chapters.is_empty() {
+            continue;
+        }
+
+        let name = read_display_name(&cartoon_path.join("cartoon_detail.json"), "name")
+            .await
+            .unwrap_or_else(|| cartoon_uuid.clone());
+        cartoons.push(IndexCartoon {
+            uuid: cartoon_uuid,
+            name,
+            chapters,
+        });
+    }
+    cartoons.sort_by(|a, b| natural_cmp(&a.name, &b.name));
+
+    Json(LibraryIndex { cartoons }).into_response()
+}
+
+// 输出剧集视频，支持单个 Range 请求
+async fn chapter_video(
+    State(state): State<ServerState>,
+    RoutePath((cartoon_uuid, chapter_uuid)): RoutePath<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    let (Ok(cartoon_uuid), Ok(chapter_uuid)) = (
+        SafeSegment::parse(&cartoon_uuid),
+        SafeSegment::parse(&chapter_uuid),
+    ) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let chapter_path = state.library_dir.join(&cartoon_uuid).join(&chapter_uuid);
+    let Some((_, video_path)) = read_completed_chapter(&chapter_path).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let mut file = match fs::File::open(&video_path).await {
+        Ok(file) => file,
+        Err(_) => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let file_size = match file.metadata().await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| parse_range(value, file_size));
+    let (status, start, length) = match range {
+        None | Some(RangeRequest::Ignored) => (StatusCode::OK, 0, file_size),
+        Some(RangeRequest::Satisfiable(start, end)) => {
+            (StatusCode::PARTIAL_CONTENT, start, end - start + 1)
+        }
+        Some(RangeRequest::Unsatisfiable) => {
+            return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", file_size))],
+            )
+                .into_response();
+        }
+    };
+
+    if start > 0 && file.seek(SeekFrom::Start(start)).await.is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    let body = Body::from_stream(ReaderStream::with_capacity(
+        file.take(length),
+        STREAM_BUFFER_SIZE,
+    ));
+
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    let response_headers = response.headers_mut();
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(mime_type(&video_path)),
+    );
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+    if status == StatusCode::PARTIAL_CONTENT {
+        let content_range = format!("bytes {}-{}/{}", start, start + length - 1, file_size);
+        if let Ok(value) = HeaderValue::from_str(&content_range) {
+            response_headers.insert(header::CONTENT_RANGE, value);
+        }
+    }
+    response
+}
+
+#[derive(Debug, PartialEq)]
+enum RangeRequest {
+    Satisfiable(u64, u64), // 闭区间
+    Unsatisfiable,
+    Ignored, // 格式无法识别或包含多个范围，返回完整文件
+}
+
+// 解析 "bytes=start-end"、"bytes=start-" 和 "bytes=-suffix"
+fn parse_range(value: &str, file_size: u64) -> RangeRequest {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Ignored;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Ignored;
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return RangeRequest::Ignored;
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    let range = match (start.is_empty(), end.is_empty()) {
+        // 最后 suffix 个字节
+        (true, false) => match end.parse::<u64>() {
+            Ok(0) => return RangeRequest::Unsatisfiable,
+            Ok(suffix) => (
+                file_size.saturating_sub(suffix),
+                file_size.saturating_sub(1),
+            ),
+            Err(_) => return RangeRequest::Ignored,
+        },
+        (false, _) => {
+            let Ok(start) = start.parse::<u64>() else {
+                return RangeRequest::Ignored;
+            };
+            let end = match end.parse::<u64>() {
+                Ok(end) if end >= start => end.min(file_size.saturating_sub(1)),
+                Ok(_) => return RangeRequest::Ignored,
+                Err(_) if end.is_empty() => file_size.saturating_sub(1),
+                Err(_) => return RangeRequest::Ignored,
+            };
+            (start, end)
+        }
+        (true, true) => return RangeRequest::Ignored,
+    };
+
+    if file_size == 0 || range.0 >= file_size {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Satisfiable(range.0, range.1)
+    }
+}
+
+// 按扩展名判断视频类型
+fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "mp4" | "m4v" => "video/mp4",
+        "ts" => "video/mp2t",
+        "mkv" => "video/x-matroska",
+        "webm" => "video/webm",
+        "mov" => "video/quicktime",
+        "flv" => "video/x-flv",
+        "avi" => "video/x-msvideo",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_ranges() {
+        assert_eq!(
+            parse_range("bytes=0-99", 1000),
+            RangeRequest::Satisfiable(0, 99)
+        );
+        // 结束位置超过文件大小时截断到末尾
+        assert_eq!(
+            parse_range("bytes=900-2000", 1000),
+            RangeRequest::Satisfiable(900, 999)
+        );
+        assert_eq!(
+            parse_range("bytes=500-", 1000),
+            RangeRequest::Satisfiable(500, 999)
+        );
+        assert_eq!(
+            parse_range("bytes=-100", 1000),
+            RangeRequest::Satisfiable(900, 999)
+        );
+        // 后缀长度超过文件大小时返回整个文件
+        assert_eq!(
+            parse_range("bytes=-5000", 1000),
+            RangeRequest::Satisfiable(0, 999)
+        );
+    }
+
+    #[test]
+    fn rejects_unsatisfiable_ranges() {
+        assert_eq!(
+            parse_range("bytes=1000-", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(
+            parse_range("bytes=2000-3000", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+        // 空文件没有可返回的范围
+        assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-10", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn ignores_unsupported_ranges() {
+        // 多个范围和无法识别的格式返回完整文件
+        assert_eq!(
+            parse_range("bytes=0-99,200-299", 1000),
+            RangeRequest::Ignored
+        );
+        assert_eq!(parse_range("items=0-99", 1000), RangeRequest::Ignored);
+        assert_eq!(parse_range("bytes=-", 1000), RangeRequest::Ignored);
+        assert_eq!(parse_range("bytes=99-0", 1000), RangeRequest::Ignored);
+        assert_eq!(parse_range("bytes=abc-", 1000), RangeRequest::Ignored);
+    }
+
+    #[test]
+    fn non_loopback_binding_requires_long_token() {
+        let config = |bind_address: &str, token: Option<&str>| MediaServerConfig {
+            bind_address: bind_address.to_string(),
+            access_token: token.map(str::to_string),
+            ..Default::default()
+        };
+
+        assert!(validate_media_server_config(&config("127.0.0.1", None)).is_ok());
+        assert!(validate_media_server_config(&config("::1", Some("short"))).is_ok());
+        assert!(validate_media_server_config(&config("0.0.0.0", None)).is_err());
+        assert!(validate_media_server_config(&config("0.0.0.0", Some("   "))).is_err());
+        assert!(validate_media_server_config(&config("0.0.0.0", Some("short"))).is_err());
+        assert!(
+            validate_media_server_config(&config("192.168.1.10", Some("0123456789abcdef"))).is_ok()
+        );
+        assert!(validate_media_server_config(&config("localhost", None)).is_err());
+    }
+}
@@ -0,0 +1,518 @@
+use crate::download::fsck::{
+    file_name_of, list_files_with_extensions, IMAGE_EXTENSIONS, VIDEO_EXTENSIONS,
+};
+use crate::download::task_manager::DownloadTask;
+use crate::download::types::*;
+use crate::download::utils::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+use tokio::fs;
+use tokio::sync::watch;
+
+// 记录资源库版本的文件，位于下载根目录
+const LIBRARY_META_FILE: &str = "library_meta.json";
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
+struct LibraryMeta {
+    schema_version: u32,
+    migrated_time: String,
+}
+
+// 迁移过程中的状态
+struct Migrator {
+    downloads_path: PathBuf,
+    backup_dir: PathBuf,
+    report: LibraryMigrationReport,
+}
+
+/// 资源库迁移状态，值为 true 表示迁移已结束
+pub struct LibraryMigrationState(watch::Sender<bool>);
+
+/// 在后台升级资源库，不阻塞窗口显示
+///
+/// 迁移结束前，通过 `get_downloads_path` 访问资源库的命令都会等待，避免读写迁移中的目录。
+pub fn start_library_migration(app_handle: &AppHandle) {
+    let (sender, _) = watch::channel(false);
+    app_handle.manage(LibraryMigrationState(sender));
+
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        // 迁移放在单独的任务中，即使出错崩溃也会解除等待
+        let migration_handle = app_handle.clone();
+        let result =
+            tauri::async_runtime::spawn(async move { migrate_library(&migration_handle).await })
+                .await;
+        match result {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => eprintln!("资源库迁移失败: {}", e),
+            Err(e) => eprintln!("资源库迁移异常退出: {}", e),
+        }
+        app_handle
+            .state::<LibraryMigrationState>()
+            .0
+            .send_replace(true);
+    });
+}
+
+/// 等待启动时的资源库迁移结束，未启动迁移时直接返回
+pub async fn wait_for_library_migration(app_handle: &AppHandle) {
+    let Some(state) = app_handle.try_state::<LibraryMigrationState>() else {
+        return;
+    };
+    let mut receiver = state.0.subscribe();
+    let _ = receiver.wait_for(|finished| *finished).await;
+}
+
+/// 升级旧版本的资源库
+///
+/// 1. 将旧的 `anime` 目录合并到 `cartoons`
+/// 2. 为缺少 `schema_version` 的元数据文件补全字段并写入当前版本
+///
+/// 改写前的文件备份到 `downloads/backups/migration_<时间>/`，目录移动记录在同目录的报告中。
+/// 资源库版本高于当前程序（由新版本写入）时不做任何修改。
+pub async fn migrate_library(app_handle: &AppHandle) -> Result<LibraryMigrationReport, String> {
+    let downloads_path = library_root(app_handle)?;
+    let meta_path = downloads_path.join(LIBRARY_META_FILE);
+    let meta = match fs::read_to_string(&meta_path).await {
+        Ok(content) => serde_json::from_str::<LibraryMeta>(&content).unwrap_or_default(),
+        Err(_) => LibraryMeta::default(),
+    };
+
+    let report = LibraryMigrationReport {
+        from_version: meta.schema_version,
+        to_version: METADATA_SCHEMA_VERSION,
+        ..Default::default()
+    };
+
+    if !downloads_path.exists() {
+        return Ok(report);
+    }
+    if meta.schema_version > METADATA_SCHEMA_VERSION {
+        eprintln!(
+            "资源库版本 {} 高于当前程序支持的版本 {}，跳过迁移",
+            meta.schema_version, METADATA_SCHEMA_VERSION
+        );
+        return Ok(report);
+    }
+    if meta.schema_version == METADATA_SCHEMA_VERSION && !downloads_path.join("anime").exists() {
+        return Ok(report);
+    }
+
+    let backup_dir = downloads_path.join("backups").join(format!(
+        "migration_{}",
+        chrono::Utc::now().format("%Y%m%d%H%M%S")
+    ));
+    let mut migrator = Migrator {
+        downloads_path,
+        backup_dir,
+        report,
+    };
+
+    migrator.migrate_legacy_layout().await;
+    migrator.upgrade_manga_library().await;
+    migrator.upgrade_cartoon_library().await;
+    migrator.upgrade_tasks().await;
+    migrator.upgrade_trash().await;
+
+    let Migrator {
+        backup_dir, report, ..
+    } = migrator;
+
+    // 有移动或改写时在备份目录中保存报告，便于手动回退
+    if report.backup_path.is_some() || !report.moved_paths.is_empty() {
+        let _ = fs::create_dir_all(&backup_dir).await;
+        if let Ok(content) = serde_json::to_string_pretty(&report) {
+            let _ = fs::write(backup_dir.join("migration_report.json"), content).await;
+        }
+    }
+
+    // 出错时不写入版本标记，下次启动重试
+    if report.errors.is_empty() {
+        let meta = LibraryMeta {
+            schema_version: METADATA_SCHEMA_VERSION,
+            migrated_time: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        };
+        let content = serde_json::to_string_pretty(&meta)
+            .map_err(|e| format!("序列化资源库版本失败: {}", e))?;
+        fs::write(&meta_path, content)
+            .await
+            .map_err(|e| format!("写入资源库版本失败: {}", e))?;
+    } else {
+        for error in &report.errors {
+            eprintln!("资源库迁移错误: {}", error);
+        }
+    }
+
+    println!(
+        "资源库迁移完成: 版本 {} -> {}，移动 {} 个目录，升级 {} 个文件",
+        report.from_version,
+        report.to_version,
+        report.moved_paths.len(),
+        report.upgraded_files
+    );
+    Ok(report)
+}
+
+impl Migrator {
+    // 将旧的 anime 目录合并到 cartoons，同一部动画的章节逐个移动
+    async fn migrate_legacy_layout(&mut self) {
+        let legacy_root = self.downloads_path.join("anime");
+        if !legacy_root.is_dir() {
+            return;
+        }
+
+        let cartoon_root = self.downloads_path.join("cartoons");
+        if let Err(e) = fs::create_dir_all(&cartoon_root).await {
+            self.report.errors.push(format!("创建动画目录失败: {}", e));
+            return;
+        }
+
+        for legacy_path in list_entries(&legacy_root).await {
+            let target_path = cartoon_root.join(file_name_of(&legacy_path));
+            if !target_path.exists() {
+                self.move_path(&legacy_path, &target_path).await;
+                continue;
+            }
+            if !legacy_path.is_dir() {
+                self.report.errors.push(format!(
+                    "目标已存在，保留在旧目录: {}",
+                    legacy_path.display()
+                ));
+                continue;
+            }
+
+            for child_path in list_entries(&legacy_path).await {
+                let target_child = target_path.join(file_name_of(&child_path));
+                if target_child.exists() {
+                    self.report.errors.push(format!(
+                        "目标已存在，保留在旧目录: {}",
+                        child_path.display()
+                    ));
+                } else {
+                    self.move_path(&child_path, &target_child).await;
+                }
+            }
+            // 全部移走后删除空目录，非空时保留
+            let _ = fs::remove_dir(&legacy_path).await;
+        }
+        let _ = fs::remove_dir(&legacy_root).await;
+    }
+
+    async fn upgrade_manga_library(&mut self) {
+        let manga_root = self.downloads_path.join("manga");
+
+        for manga_path in list_dirs(&manga_root).await {
+            let manga_uuid = file_name_of(&manga_path);
+            self.upgrade_file(
+                &manga_path.join("manga_detail.json"),
+                |detail: &mut MangaDetail, _| {
+                    if detail.uuid.is_empty() {
+                        detail.uuid = manga_uuid.clone();
+                    }
+                },
+            )
+            .await;
+
+            for group_path in list_dirs(&manga_path).await {
+                let group_path_word = file_name_of(&group_path);
+                for chapter_path in list_dirs(&group_path).await {
+                    let info_path = chapter_path.join("info.json");
+                    let chapter_uuid = file_name_of(&chapter_path);
+                    let download_time = modified_time(&info_path).await;
+                    let mut images =
+                        list_files_with_extensions(&chapter_path, &IMAGE_EXTENSIONS).await;
+                    images.sort_by(|a, b| natural_cmp(a, b));
+
+                    self.upgrade_file(&info_path, |info: &mut ChapterInfo, original| {
+                        if info.manga_uuid.is_empty() {
+                            info.manga_uuid = manga_uuid.clone();
+                        }
+                        if info.group_path_word.is_empty() {
+                            info.group_path_word = group_path_word.clone();
+                        }
+                        if info.chapter_uuid.is_empty() {
+                            info.chapter_uuid = chapter_uuid;
+                        }
+                        if info.images.is_empty() {
+                            info.images = images;
+                        }
+                        // 旧版本没有 total_images，只记录了已下载的图片
+                        if original.get("total_images").is_none() {
+                            info.total_images = info.images.len();
+                        }
+                        if info.download_time.is_empty() {
+                            info.download_time = download_time;
+                        }
+                    })
+                    .await;
+                }
+            }
+        }
+    }
+
+    async fn upgrade_cartoon_library(&mut self) {
+        let cartoon_root = self.downloads_path.join("cartoons");
+
+        for cartoon_path in list_dirs(&cartoon_root).await {
+            let cartoon_uuid = file_name_of(&cartoon_path);
+            self.upgrade_file(
+                &cartoon_path.join("cartoon_detail.json"),
+                |detail: &mut CartoonDetail, _| {
+                    if detail.uuid.is_empty() {
+                        detail.uuid = cartoon_uuid.clone();
+                    }
+                },
+            )
+            .await;
+
+            for chapter_path in list_dirs(&cartoon_path).await {
+                let info_path = chapter_path.join("info.json");
+                let chapter_uuid = file_name_of(&chapter_path);
+                let download_time = modified_time(&info_path).await;
+                let videos = list_files_with_extensions(&chapter_path, &VIDEO_EXTENSIONS).await;
+                let has_temp_segments = chapter_path.join("temp_segments").exists();
+
+                self.upgrade_file(&info_path, |info: &mut CartoonChapterInfo, original| {
+                    if info.cartoon_uuid.is_empty() {
+                        info.cartoon_uuid = cartoon_uuid.clone();
+                    }
+                    if info.chapter_uuid.is_empty() {
+                        info.chapter_uuid = chapter_uuid;
+                    }
+                    if info.video_file.is_empty() {
+                        info.video_file = videos.into_iter().next().unwrap_or_default();
+                    }
+
+                    let video_size = std::fs::metadata(chapter_path.join(&info.video_file))
+                        .map(|m| m.len())
+                        .unwrap_or(0);
+                    // 没有完成标记时，根据视频文件和临时分片推断
+                    if original.get("is_completed").is_none() {
+                        info.is_completed = video_size > 0 && !has_temp_segments;
+                    }
+                    if info.is_completed && info.file_size == 0 {
+                        info.file_size = video_size;
+                    }
+                    if info.download_time.is_empty() {
+                        info.download_time = download_time;
+                    }
+                })
+                .await;
+            }
+        }
+    }
+
+    // 任务文件是任务数组，逐个升级，无法解析的任务原样保留
+    async fn upgrade_tasks(&mut self) {
+        let tasks_path = self.downloads_path.join("tasks").join("cartoon_tasks.json");
+        let tasks = match read_json(&tasks_path).await {
+            Ok(Some(Value::Array(tasks))) => tasks,
+            Ok(_) => return,
+            Err(e) => {
+                self.report.errors.push(e);
+                return;
+            }
+        };
+        if tasks
+            .iter()
+            .all(|task| schema_version_of(task) >= METADATA_SCHEMA_VERSION)
+        {
+            return;
+        }
+
+        let mut upgraded = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            if schema_version_of(&task) >= METADATA_SCHEMA_VERSION {
+                upgraded.push(task);
+                continue;
+            }
+            let result = upgrade_value(task.clone(), |task: &mut DownloadTask, _| {
+                if task.updated_at.is_empty() {
+                    task.updated_at = task.start_time.clone();
+                }
+            });
+            match result {
+                Ok(value) => upgraded.push(value),
+                Err(e) => {
+                    eprintln!("无法升级下载任务: {}", e);
+                    upgraded.push(task);
+                }
+            }
+        }
+
+        self.write_upgraded(&tasks_path, &Value::Array(upgraded))
+            .await;
+    }
+
+    async fn upgrade_trash(&mut self) {
+        let trash_root = self.downloads_path.join("trash");
+
+        for item_dir in list_dirs(&trash_root).await {
+            let trash_id = file_name_of(&item_dir);
+            self.upgrade_file(
+                &item_dir.join("trash_info.json"),
+                |item: &mut TrashItem, _| {
+                    if item.id.is_empty() {
+                        item.id = trash_id;
+                    }
+                },
+            )
+            .await;
+        }
+    }
+
+    // 升级单个元数据文件，不存在或已是当前版本时跳过；
+    // 无法读取、解析或升级的文件记入错误，保留原文件，其余文件继续迁移
+    async fn upgrade_file<T>(&mut self, path: &Path, fill: impl FnOnce(&mut T, &Value))
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let original = match read_json(path).await {
+            Ok(Some(original)) => original,
+            Ok(None) => return,
+            Err(e) => {
+                self.report.errors.push(e);
+                return;
+            }
+        };
+        if schema_version_of(&original) >= METADATA_SCHEMA_VERSION {
+            return;
+        }
+
+        match upgrade_value(original, fill) {
+            Ok(upgraded) => self.write_upgraded(path, &upgraded).await,
+            Err(e) => self
+                .report
+                .errors
+                .push(format!("无法升级 {}: {}", path.display(), e)),
+        }
+    }
+
+    // 备份原文件后写入升级后的内容
+    async fn write_upgraded(&mut self, path: &Path, value: &Value) {
+        if let Err(e) = self.backup(path).await {
+            self.report.errors.push(e);
+            return;
+        }
+
+        let result = match serde_json::to_string_pretty(value) {
+            Ok(content) => fs::write(path, content).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match result {
+            Ok(_) => self.report.upgraded_files += 1,
+            Err(e) => self
+                .report
+                .errors
+                .push(format!("写入 {} 失败: {}", path.display(), e)),
+        }
+    }
+
+    // 按相对下载目录的路径复制到备份目录
+    async fn backup(&mut self, path: &Path) -> Result<(), String> {
+        let relative = path
+            .strip_prefix(&self.downloads_path)
+            .map_err(|_| format!("文件不在下载目录内: {}", path.display()))?;
+        let backup_path = self.backup_dir.join(relative);
+
+        if let Some(parent) = backup_path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("创建备份目录失败: {}", e))?;
+        }
+        fs::copy(path, &backup_path)
+            .await
+            .map_err(|e| format!("备份 {} 失败: {}", path.display(), e))?;
+
+        self.report.backup_path = Some(self.backup_dir.to_string_lossy().to_string());
+        Ok(())
+    }
+
+    async fn move_path(&mut self, from: &Path, to: &Path) {
+        match fs::rename(from, to).await {
+            Ok(_) => {
+                self.report
+                    .moved_paths
+                    .push(format!("{} -> {}", from.display(), to.display()))
+            }
+            Err(e) => self
+                .report
+                .errors
+                .push(format!("移动 {} 失败: {}", from.display(), e)),
+        }
+    }
+}
+
+// 按类型宽松解析后补全字段，类型中没有的字段原样保留
+fn upgrade_value<T>(original: Value, fill: impl FnOnce(&mut T, &Value)) -> Result<Value, String>
+where
+    T: DeserializeOwned + Serialize,
+{
+    let mut typed: T =
+        serde_json::from_value(original.clone()).map_err(|e| format!("解析失败: {}", e))?;
+    fill(&mut typed, &original);
+
+    let Value::Object(fields) =
+        serde_json::to_value(&typed).map_err(|e| format!("序列化失败: {}", e))?
+    else {
+        return Err("元数据不是对象".to_string());
+    };
+
+    let mut merged = match original {
+        Value::Object(map) => map,
+        _ => Default::default(),
+    };
+    merged.extend(fields);
+    merged.insert("schema_version".to_string(), json!(METADATA_SCHEMA_VERSION));
+    Ok(Value::Object(merged))
+}
+
+fn schema_version_of(value: &Value) -> u32 {
+    value
+        .get("schema_version")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0) as u32
+}
+
+// 读取 JSON 文件，文件不存在时返回 None
+async fn read_json(path: &Path) -> Result<Option<Value>, String> {
+    let content = match fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("读取 {} 失败: {}", path.display(), e)),
+    };
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| format!("无法解析 {}: {}", path.display(), e))
+}
+
+// 旧文件缺少下载时间时使用文件修改时间
+async fn modified_time(path: &Path) -> String {
+    let modified = match fs::metadata(path).await.and_then(|m| m.modified()) {
+        Ok(time) => chrono::DateTime::<chrono::Utc>::from(time),
+        Err(_) => chrono::Utc::now(),
+    };
+    modified.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+async fn list_entries(dir: &Path) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Ok(mut entries) = fs::read_dir(dir).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            paths.push(entry.path());
+        }
+    }
+    paths
+}
+
+async fn list_dirs(dir: &Path) -> Vec<PathBuf> {
+    list_entries(dir)
+        .await
+        .into_iter()
+        .filter(|path| path.is_dir())
+        .collect()
+}
@@ -1,12 +1,44 @@
 #![allow(unused_imports)]
 // 导出所有下载相关的函数
 pub mod cartoon;
+pub mod estimate;
+pub mod export;
+pub mod ffmpeg;
+pub mod fsck;
+pub mod hls;
+pub mod http_client;
+pub mod local_playlist;
 pub mod manga;
+pub mod manifest;
+pub mod media_server;
+pub mod migration;
+pub mod naming;
+pub mod player;
+pub mod probe;
+pub mod remux;
+pub mod resume;
+pub mod segmented;
+pub mod settings;
 pub mod task_manager;
+pub mod thumbnail;
+pub mod trash;
 pub mod types;
 pub mod utils;
+pub mod verify;
 
 pub use cartoon::*;
+pub use estimate::*;
+pub use export::*;
+pub use ffmpeg::*;
+pub use fsck::*;
+pub use http_client::*;
+pub use local_playlist::*;
 pub use manga::*;
+pub use media_server::*;
+pub use migration::*;
+pub use naming::*;
+pub use player::*;
+pub use settings::*;
 pub use task_manager::*;
+pub use trash::*;
 pub use types::*;
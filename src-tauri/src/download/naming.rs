@@ -0,0 +1,511 @@
+use crate::download::utils::sanitize_filename;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+use tokio::fs;
+
+// 漫画图片可用的占位符
+const MANGA_PAGE_PLACEHOLDERS: [&str; 5] = ["series", "group", "chapter", "index", "original"];
+// 动画剧集可用的占位符
+const CARTOON_EPISODE_PLACEHOLDERS: [&str; 3] = ["series", "chapter", "index"];
+
+/// 下载文件命名模板
+///
+/// 占位符写作 `{name}` 或 `{name:03}`（数字补零到指定宽度），扩展名会自动追加。
+/// 动画模板中的 `/` 表示在章节目录下创建子目录。
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NamingConfig {
+    pub manga_page_template: String,
+    pub cartoon_episode_template: String,
+}
+
+impl Default for NamingConfig {
+    fn default() -> Self {
+        // 默认值与之前的命名方式保持一致
+        NamingConfig {
+            manga_page_template: "{index:03}".to_string(),
+            cartoon_episode_template: "{chapter}".to_string(),
+        }
+    }
+}
+
+/// 模板类型，决定可用的占位符
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateKind {
+    MangaPage,
+    CartoonEpisode,
+}
+
+impl TemplateKind {
+    fn placeholders(self) -> &'static [&'static str] {
+        match self {
+            TemplateKind::MangaPage => &MANGA_PAGE_PLACEHOLDERS,
+            TemplateKind::CartoonEpisode => &CARTOON_EPISODE_PLACEHOLDERS,
+        }
+    }
+}
+
+/// 渲染模板时使用的变量
+#[derive(Debug, Default)]
+pub struct NamingContext<'a> {
+    pub series: &'a str,
+    pub group: &'a str,
+    pub chapter: &'a str,
+    pub index: Option<usize>,
+    pub original: &'a str, // 图片地址中的原始文件名（不含扩展名）
+}
+
+// 模板解析后的片段
+enum TemplatePart {
+    Text(String),
+    Placeholder { name: String, width: usize },
+}
+
+/// 获取命名模板配置
+#[tauri::command]
+pub async fn get_naming_config(app_handle: AppHandle) -> Result<NamingConfig, String> {
+    load_naming_config(&app_handle).await
+}
+
+/// 保存命名模板配置，保存前校验模板
+#[tauri::command]
+pub async fn set_naming_config(app_handle: AppHandle, config: NamingConfig) -> Result<(), String> {
+    validate_manga_page_template(&config.manga_page_template)?;
+    validate_cartoon_episode_template(&config.cartoon_episode_template)?;
+
+    let config_path = get_naming_config_path(&app_handle)?;
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("创建配置目录失败: {}", e))?;
+    }
+
+    let content =
+        serde_json::to_string_pretty(&config).map_err(|e| format!("序列化命名配置失败: {}", e))?;
+    fs::write(&config_path, content)
+        .await
+        .map_err(|e| format!("写入命名配置失败: {}", e))
+}
+
+/// 预览模板效果，kind 为 "manga" 或 "cartoon"
+#[tauri::command]
+pub fn preview_naming_template(kind: String, template: String) -> Result<String, String> {
+    let context = NamingContext {
+        series: "示例作品",
+        group: "default",
+        chapter: "第1话",
+        index: Some(1),
+        original: "b3f1c2a4d5e6",
+    };
+
+    match kind.as_str() {
+        "manga" => {
+            validate_manga_page_template(&template)?;
+            render_file_name(&template, TemplateKind::MangaPage, &context, "jpg")
+        }
+        "cartoon" => {
+            validate_cartoon_episode_template(&template)?;
+            render_file_name(&template, TemplateKind::CartoonEpisode, &context, "mp4")
+        }
+        _ => Err(format!("未知的模板类型: {}", kind)),
+    }
+}
+
+/// 读取命名模板配置，不存在或无效时使用默认值
+pub async fn load_naming_config(app_handle: &AppHandle) -> Result<NamingConfig, String> {
+    let config_path = get_naming_config_path(app_handle)?;
+
+    let config = match fs::read_to_string(&config_path).await {
+        Ok(content) => serde_json::from_str::<NamingConfig>(&content).ok(),
+        Err(_) => None,
+    };
+
+    let config = config.unwrap_or_default();
+    if validate_manga_page_template(&config.manga_page_template).is_err()
+        || validate_cartoon_episode_template(&config.cartoon_episode_template).is_err()
+    {
+        eprintln!("命名配置无效，使用默认配置");
+        return Ok(NamingConfig::default());
+    }
+
+    Ok(config)
+}
+
+pub fn validate_manga_page_template(template: &str) -> Result<(), String> {
+    let parts = parse_template(template, TemplateKind::MangaPage.placeholders())?;
+    if template.contains('/') || template.contains('\\') {
+        return Err("漫画图片模板不能包含目录分隔符".to_string());
+    }
+    // 至少包含一个能区分页面的占位符
+    if !has_placeholder(&parts, &["index", "original"]) {
+        return Err("漫画图片模板必须包含 {index} 或 {original}".to_string());
+    }
+    Ok(())
+}
+
+pub fn validate_cartoon_episode_template(template: &str) -> Result<(), String> {
+    let parts = parse_template(template, TemplateKind::CartoonEpisode.placeholders())?;
+    if template.contains('\\') {
+        return Err("请使用 / 作为目录分隔符".to_string());
+    }
+    if !has_placeholder(&parts, &["chapter", "index"]) {
+        return Err("动画剧集模板必须包含 {chapter} 或 {index}".to_string());
+    }
+    Ok(())
+}
+
+/// 渲染模板并追加扩展名，返回使用 `/` 分隔的相对路径，每一级都经过清理
+///
+/// 只有模板文字中的 `/` 表示目录，占位符的值（如 "Fate/stay night"）清理后填入，不会产生新的目录层级。
+pub fn render_file_name(
+    template: &str,
+    kind: TemplateKind,
+    context: &NamingContext,
+    extension: &str,
+) -> Result<String, String> {
+    let parts = parse_template(template, kind.placeholders())?;
+
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    for part in parts {
+        match part {
+            TemplatePart::Text(text) => {
+                let mut pieces = text.split('/');
+                current.push_str(pieces.next().unwrap_or_default());
+                for piece in pieces {
+                    segments.push(std::mem::take(&mut current));
+                    current.push_str(piece);
+                }
+            }
+            TemplatePart::Placeholder { name, width } => {
+                let value = match name.as_str() {
+                    "series" => context.series.to_string(),
+                    "group" => context.group.to_string(),
+                    "chapter" => context.chapter.to_string(),
+                    "original" => context.original.to_string(),
+                    // 未提供序号时留空
+                    "index" => context
+                        .index
+                        .map(|index| format!("{:0width$}", index, width = width))
+                        .unwrap_or_default(),
+                    _ => String::new(),
+                };
+                if !value.is_empty() {
+                    current.push_str(&sanitize_filename(&value));
+                }
+            }
+        }
+    }
+    segments.push(current);
+
+    let mut segments: Vec<String> = segments
+        .iter()
+        .filter(|segment| !segment.trim().is_empty())
+        .map(|segment| sanitize_filename(segment))
+        .collect();
+
+    // 渲染结果为空时回退到章节名
+    if segments.is_empty() {
+        segments.push(sanitize_filename(if context.chapter.is_empty() {
+            context.original
+        } else {
+            context.chapter
+        }));
+    }
+
+    if !extension.is_empty() {
+        if let Some(last) = segments.last_mut() {
+            last.push('.');
+            last.push_str(extension);
+        }
+    }
+
+    Ok(segments.join("/"))
+}
+
+/// 从原始文件名中提取扩展名，只保留字母数字，无效时使用默认值
+pub fn clean_extension(file_name: &str, default: &str) -> String {
+    let extension = Path::new(file_name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let extension: String = extension
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect();
+
+    if extension.is_empty() || extension.len() > 5 {
+        default.to_string()
+    } else {
+        extension
+    }
+}
+
+/// 将 `/` 分隔的相对路径拼接到目录下
+pub fn join_relative(base: &Path, relative: &str) -> PathBuf {
+    relative
+        .split('/')
+        .fold(base.to_path_buf(), |path, segment| path.join(segment))
+}
+
+/// 处理重名：名称已被本批次使用或与不属于本次下载的文件冲突时追加 ` (2)`、` (3)` ...
+pub fn resolve_collision(
+    base: &Path,
+    relative: &str,
+    used: &mut HashSet<String>,
+    is_own_file: impl Fn(&str) -> bool,
+) -> String {
+    let (stem, extension) = match relative.rfind('.') {
+        Some(dot) if dot > relative.rfind('/').map_or(0, |slash| slash + 1) => {
+            (&relative[..dot], &relative[dot..])
+        }
+        _ => (relative, ""),
+    };
+    resolve_candidates(base, relative, stem, extension, used, is_own_file)
+}
+
+/// 目录名的重名处理，与 `resolve_collision` 相同，但不把名称中的 `.` 视为扩展名
+pub fn resolve_dir_collision(
+    base: &Path,
+    relative: &str,
+    used: &mut HashSet<String>,
+    is_own_dir: impl Fn(&str) -> bool,
+) -> String {
+    resolve_candidates(base, relative, relative, "", used, is_own_dir)
+}
+
+fn resolve_candidates(
+    base: &Path,
+    relative: &str,
+    stem: &str,
+    extension: &str,
+    used: &mut HashSet<String>,
+    is_own: impl Fn(&str) -> bool,
+) -> String {
+    let mut candidate = relative.to_string();
+    let mut counter = 2;
+    loop {
+        let taken = used.contains(&candidate.to_lowercase())
+            || (join_relative(base, &candidate).exists() && !is_own(&candidate));
+        if !taken {
+            used.insert(candidate.to_lowercase());
+            return candidate;
+        }
+        candidate = format!("{} ({}){}", stem, counter, extension);
+        counter += 1;
+    }
+}
+
+fn get_naming_config_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("无法获取应用数据目录: {}", e))?;
+
+    Ok(app_data_dir.join("config").join("naming.json"))
+}
+
+fn has_placeholder(parts: &[TemplatePart], names: &[&str]) -> bool {
+    parts.iter().any(|part| match part {
+        TemplatePart::Placeholder { name, .. } => names.contains(&name.as_str()),
+        TemplatePart::Text(_) => false,
+    })
+}
+
+// 解析模板，校验括号配对、占位符名称和格式
+fn parse_template(template: &str, allowed: &[&str]) -> Result<Vec<TemplatePart>, String> {
+    if template.trim().is_empty() {
+        return Err("模板不能为空".to_string());
+    }
+
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some('{') | None => {
+                            return Err(format!("模板中的 {{ 没有闭合: {}", template))
+                        }
+                        Some(c) => placeholder.push(c),
+                    }
+                }
+
+                let (name, width) = match placeholder.split_once(':') {
+                    Some((name, spec)) => {
+                        let width = spec
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|w| spec.chars().all(|c| c.is_ascii_digit()) && *w <= 10)
+                            .ok_or_else(|| format!("无效的占位符格式: {{{}}}", placeholder))?;
+                        if name != "index" {
+                            return Err(format!(
+                                "只有 {{index}} 支持补零格式: {{{}}}",
+                                placeholder
+                            ));
+                        }
+                        (name.to_string(), width)
+                    }
+                    None => (placeholder.clone(), 0),
+                };
+
+                if !allowed.contains(&name.as_str()) {
+                    return Err(format!(
+                        "未知的占位符 {{{}}}，可用: {}",
+                        name,
+                        allowed
+                            .iter()
+                            .map(|p| format!("{{{}}}", p))
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    ));
+                }
+
+                if !text.is_empty() {
+                    parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                }
+                parts.push(TemplatePart::Placeholder { name, width });
+            }
+            '}' => return Err(format!("模板中有多余的 }}: {}", template)),
+            c => text.push(c),
+        }
+    }
+
+    if !text.is_empty() {
+        parts.push(TemplatePart::Text(text));
+    }
+
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context<'a>(chapter: &'a str, index: Option<usize>) -> NamingContext<'a> {
+        NamingContext {
+            series: "Fate/stay night",
+            group: "default",
+            chapter,
+            index,
+            original: "abc",
+        }
+    }
+
+    #[test]
+    fn parse_template_splits_text_and_placeholders() {
+        let parts = parse_template(
+            "{index:03} - {chapter}",
+            TemplateKind::CartoonEpisode.placeholders(),
+        )
+        .unwrap();
+        assert_eq!(parts.len(), 3);
+        assert!(
+            matches!(&parts[0], TemplatePart::Placeholder { name, width: 3 } if name == "index")
+        );
+        assert!(matches!(&parts[1], TemplatePart::Text(text) if text == " - "));
+        assert!(
+            matches!(&parts[2], TemplatePart::Placeholder { name, width: 0 } if name == "chapter")
+        );
+    }
+
+    #[test]
+    fn parse_template_rejects_invalid_templates() {
+        let manga = TemplateKind::MangaPage.placeholders();
+        assert!(parse_template("", manga).is_err());
+        assert!(parse_template("{index", manga).is_err());
+        assert!(parse_template("index}", manga).is_err());
+        assert!(parse_template("{unknown}", manga).is_err());
+        assert!(parse_template("{chapter:03}", manga).is_err());
+        assert!(parse_template("{index:x}", manga).is_err());
+        assert!(parse_template("{index:99}", manga).is_err());
+    }
+
+    #[test]
+    fn placeholders_depend_on_template_kind() {
+        let cartoon = TemplateKind::CartoonEpisode;
+        assert!(parse_template("{original}", cartoon.placeholders()).is_err());
+        assert!(
+            render_file_name("{group} {chapter}", cartoon, &context("第1话", None), "mp4").is_err()
+        );
+        assert!(parse_template("{original}", TemplateKind::MangaPage.placeholders()).is_ok());
+    }
+
+    #[test]
+    fn placeholder_values_do_not_create_directories() {
+        let rendered = render_file_name(
+            "{series} {index:03}",
+            TemplateKind::MangaPage,
+            &context("第1话/上", Some(1)),
+            "jpg",
+        )
+        .unwrap();
+        assert_eq!(rendered, "Fate_stay night 001.jpg");
+
+        let rendered = render_file_name(
+            "{chapter}",
+            TemplateKind::CartoonEpisode,
+            &context("第1话/上", None),
+            "mp4",
+        )
+        .unwrap();
+        assert_eq!(rendered, "第1话_上.mp4");
+    }
+
+    #[test]
+    fn template_separators_create_directories() {
+        let rendered = render_file_name(
+            "{series}/{index:02} {chapter}",
+            TemplateKind::CartoonEpisode,
+            &context("OVA: 前篇?", Some(3)),
+            "mp4",
+        )
+        .unwrap();
+        assert_eq!(rendered, "Fate_stay night/03 OVA_ 前篇_.mp4");
+        assert_eq!(
+            join_relative(Path::new("base"), &rendered),
+            Path::new("base")
+                .join("Fate_stay night")
+                .join("03 OVA_ 前篇_.mp4")
+        );
+    }
+
+    #[test]
+    fn empty_render_falls_back_to_chapter_name() {
+        let rendered = render_file_name(
+            "{index}",
+            TemplateKind::CartoonEpisode,
+            &context("第2话", None),
+            "mp4",
+        )
+        .unwrap();
+        assert_eq!(rendered, "第2话.mp4");
+    }
+
+    #[test]
+    fn collisions_get_numbered_suffixes() {
+        let base = Path::new("/nonexistent-naming-test");
+        let mut used = HashSet::new();
+        let resolve =
+            |used: &mut HashSet<String>, name: &str| resolve_collision(base, name, used, |_| false);
+        assert_eq!(resolve(&mut used, "a/第1话.mp4"), "a/第1话.mp4");
+        assert_eq!(resolve(&mut used, "a/第1话.MP4"), "a/第1话 (2).MP4");
+        assert_eq!(resolve(&mut used, "a/第1话.mp4"), "a/第1话 (3).mp4");
+
+        // 目录名中的 . 不是扩展名
+        assert_eq!(
+            resolve_dir_collision(base, "a/第1.5话", &mut used, |_| false),
+            "a/第1.5话"
+        );
+        assert_eq!(
+            resolve_dir_collision(base, "a/第1.5话", &mut used, |_| false),
+            "a/第1.5话 (2)"
+        );
+    }
+}
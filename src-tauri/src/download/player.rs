@@ -0,0 +1,382 @@
+use crate::download::cartoon::{list_completed_chapters, read_completed_chapter};
+use crate::download::probe::probe_media_file;
+use crate::download::trash::read_display_name;
+use crate::download::utils::get_downloads_path;
+use crate::path_guard::SafeSegment;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+use tokio::fs;
+use tokio::process::Command;
+use tokio::sync::oneshot;
+
+// 自定义参数的数量和单个参数长度上限
+const MAX_PLAYER_ARGS: usize = 32;
+const MAX_PLAYER_ARG_LENGTH: usize = 1024;
+// 参数中的该占位符会被替换为视频或播放列表路径；没有占位符时路径作为最后一个参数
+const INPUT_PLACEHOLDER: &str = "{input}";
+
+/// 外部播放器配置
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct PlayerConfig {
+    pub player_path: Option<String>, // 播放器可执行文件的绝对路径，为空时用系统默认程序打开
+    pub player_args: Vec<String>,    // 传给播放器的参数，每项作为一个独立参数，不经过 shell
+}
+
+// 本次运行中已确认过的播放器路径和参数。配置文件可能被改写，
+// 新的路径或参数组合第一次启动前需要用户在原生对话框中确认
+type PlayerCommand = (String, Vec<String>);
+
+static CONFIRMED_PLAYERS: OnceLock<Mutex<HashSet<PlayerCommand>>> = OnceLock::new();
+
+fn confirmed_players() -> std::sync::MutexGuard<'static, HashSet<PlayerCommand>> {
+    CONFIRMED_PLAYERS
+        .get_or_init(|| Mutex::new(HashSet::new()))
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// 获取外部播放器配置
+#[tauri::command]
+pub async fn get_player_config(app_handle: AppHandle) -> Result<PlayerConfig, String> {
+    load_player_config(&app_handle).await
+}
+
+/// 用原生文件对话框选择播放器并保存，取消选择时保持原配置
+///
+/// 播放器路径只能通过这里设置，前端无法直接传入要运行的程序。
+#[tauri::command]
+pub async fn choose_player_executable(app_handle: AppHandle) -> Result<PlayerConfig, String> {
+    let (sender, receiver) = oneshot::channel();
+    app_handle
+        .dialog()
+        .file()
+        .set_title("选择外部播放器")
+        .pick_file(move |file_path| {
+            let _ = sender.send(file_path);
+        });
+    let Some(file_path) = receiver
+        .await
+        .map_err(|_| "文件对话框异常关闭".to_string())?
+    else {
+        return load_player_config(&app_handle).await;
+    };
+
+    let player_path = file_path
+        .into_path()
+        .map_err(|e| format!("无效的播放器路径: {}", e))?;
+    validate_player_path(&player_path)?;
+    let player_path = player_path.to_string_lossy().to_string();
+
+    // 参数通常只适用于原来的播放器，更换播放器时清空
+    let config = PlayerConfig {
+        player_path: Some(player_path.clone()),
+        player_args: Vec::new(),
+    };
+    save_player_config(&app_handle, &config).await?;
+    // 用户刚在对话框中选择，不需要再次确认
+    confirmed_players().insert((player_path, Vec::new()));
+    Ok(config)
+}
+
+/// 设置传给外部播放器的参数
+///
+/// 每项作为一个独立参数传给播放器，不经过 shell；参数中的 `{input}` 会被替换为视频或
+/// 播放列表路径，没有时路径作为最后一个参数。新的参数第一次启动前需要在原生对话框中确认。
+#[tauri::command]
+pub async fn set_player_arguments(
+    app_handle: AppHandle,
+    arguments: Vec<String>,
+) -> Result<PlayerConfig, String> {
+    validate_player_args(&arguments)?;
+    let mut config = load_player_config(&app_handle).await?;
+    config.player_args = arguments;
+    save_player_config(&app_handle, &config).await?;
+    Ok(config)
+}
+
+/// 清除外部播放器，改用系统默认程序打开视频
+#[tauri::command]
+pub async fn reset_player_config(app_handle: AppHandle) -> Result<(), String> {
+    save_player_config(&app_handle, &PlayerConfig::default()).await
+}
+
+/// 用外部播放器打开已下载的剧集
+#[tauri::command]
+pub async fn open_cartoon_chapter_in_player(
+    app_handle: AppHandle,
+    cartoon_uuid: SafeSegment,
+    chapter_uuid: SafeSegment,
+) -> Result<(), String> {
+    let cartoon_path = get_cartoon_path(&app_handle, &cartoon_uuid).await?;
+    let (_, video_path) = read_completed_chapter(&cartoon_path.join(&chapter_uuid))
+        .await
+        .ok_or_else(|| "剧集不存在或尚未下载完成".to_string())?;
+
+    launch_player(&app_handle, &video_path).await
+}
+
+/// 为整部动画生成 M3U 播放列表并用外部播放器打开
+///
+/// 只包含下载完成的剧集，按章节序号排序；指定 start_chapter_uuid 时从该集开始。
+/// 返回生成的播放列表路径。
+#[tauri::command]
+pub async fn open_cartoon_playlist_in_player(
+    app_handle: AppHandle,
+    cartoon_uuid: SafeSegment,
+    start_chapter_uuid: Option<SafeSegment>,
+) -> Result<String, String> {
+    let cartoon_path = get_cartoon_path(&app_handle, &cartoon_uuid).await?;
+    let mut chapters = list_completed_chapters(&cartoon_path).await;
+    if let Some(start) = start_chapter_uuid {
+        if let Some(position) = chapters
+            .iter()
+            .position(|(info, _)| info.chapter_uuid == start.as_str())
+        {
+            chapters.drain(..position);
+        }
+    }
+    if chapters.is_empty() {
+        return Err("没有已下载完成的剧集".to_string());
+    }
+
+    let cartoon_name = read_display_name(&cartoon_path.join("cartoon_detail.json"), "name")
+        .await
+        .unwrap_or_else(|| cartoon_uuid.to_string());
+
+    let mut content = String::new();
+    let _ = writeln!(content, "#EXTM3U");
+    let _ = writeln!(content, "#PLAYLIST:{}", cartoon_name);
+    for (info, video_path) in &chapters {
+        // 旧版本下载的剧集没有记录媒体信息，现场解析；仍无法确定时长时为 -1
+        let duration_secs = match &info.media {
+            Some(media) => media.duration_secs,
+            None => probe_media_file(video_path)
+                .await
+                .map_or(0.0, |media| media.duration_secs),
+        };
+        let duration = if duration_secs > 0.0 {
+            duration_secs.round() as i64
+        } else {
+            -1
+        };
+        let _ = writeln!(content, "#EXTINF:{},{}", duration, info.chapter_name);
+        let _ = writeln!(content, "{}", video_path.display());
+    }
+
+    // 播放列表放在应用数据目录，不写入下载库
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("无法获取应用数据目录: {}", e))?;
+    let playlist_dir = app_data_dir.join("playlists");
+    fs::create_dir_all(&playlist_dir)
+        .await
+        .map_err(|e| format!("创建播放列表目录失败: {}", e))?;
+    // .m3u8 扩展名表示 UTF-8 编码，剧集名称包含中文时播放器不会乱码
+    let playlist_path = playlist_dir.join(format!("{}.m3u8", cartoon_uuid));
+    fs::write(&playlist_path, content)
+        .await
+        .map_err(|e| format!("写入播放列表失败: {}", e))?;
+
+    launch_player(&app_handle, &playlist_path).await?;
+    Ok(playlist_path.to_string_lossy().to_string())
+}
+
+/// 读取外部播放器配置，不存在或无效时使用默认配置
+pub async fn load_player_config(app_handle: &AppHandle) -> Result<PlayerConfig, String> {
+    let config_path = get_player_config_path(app_handle)?;
+
+    let config = match fs::read_to_string(&config_path).await {
+        Ok(content) => serde_json::from_str::<PlayerConfig>(&content).ok(),
+        Err(_) => None,
+    };
+
+    Ok(config.unwrap_or_default())
+}
+
+async fn save_player_config(app_handle: &AppHandle, config: &PlayerConfig) -> Result<(), String> {
+    let config_path = get_player_config_path(app_handle)?;
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("创建配置目录失败: {}", e))?;
+    }
+
+    let content =
+        serde_json::to_string_pretty(config).map_err(|e| format!("序列化播放器配置失败: {}", e))?;
+    fs::write(&config_path, content)
+        .await
+        .map_err(|e| format!("写入播放器配置失败: {}", e))
+}
+
+// 播放器必须是存在的可执行文件的绝对路径，不接受命令名
+fn validate_player_path(path: &Path) -> Result<(), String> {
+    if !path.is_absolute() {
+        return Err("播放器路径必须是绝对路径".to_string());
+    }
+    if !path.is_file() {
+        return Err(format!("找不到播放器: {}", path.display()));
+    }
+    Ok(())
+}
+
+// 参数数量和长度有上限，不允许包含控制字符
+fn validate_player_args(arguments: &[String]) -> Result<(), String> {
+    if arguments.len() > MAX_PLAYER_ARGS {
+        return Err(format!("播放器参数不能超过 {} 个", MAX_PLAYER_ARGS));
+    }
+    for argument in arguments {
+        if argument.len() > MAX_PLAYER_ARG_LENGTH || argument.chars().any(char::is_control) {
+            return Err(format!("无效的播放器参数: {}", argument));
+        }
+    }
+    Ok(())
+}
+
+// 按配置的参数生成播放器的参数列表，`{input}` 替换为输入路径，没有时追加在最后
+fn player_command_args(arguments: &[String], input: &Path) -> Vec<OsString> {
+    let mut args: Vec<OsString> = arguments
+        .iter()
+        .map(|argument| match argument.as_str() {
+            INPUT_PLACEHOLDER => input.as_os_str().to_os_string(),
+            _ => OsString::from(argument),
+        })
+        .collect();
+    if !arguments
+        .iter()
+        .any(|argument| argument == INPUT_PLACEHOLDER)
+    {
+        args.push(input.as_os_str().to_os_string());
+    }
+    args
+}
+
+// 在原生对话框中确认运行新的播放器路径和参数
+async fn confirm_player(app_handle: &AppHandle, player: &str, arguments: &[String]) -> bool {
+    let arguments = if arguments.is_empty() {
+        "（无）".to_string()
+    } else {
+        arguments.join("\n")
+    };
+    let (sender, receiver) = oneshot::channel();
+    app_handle
+        .dialog()
+        .message(format!(
+            "将使用以下程序打开视频：\n{}\n\n参数：\n{}\n\n确定要运行它吗？",
+            player, arguments
+        ))
+        .title("确认外部播放器")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancel)
+        .show(move |confirmed| {
+            let _ = sender.send(confirmed);
+        });
+    receiver.await.unwrap_or(false)
+}
+
+// 启动外部播放器，不等待其退出；没有配置播放器时交给系统默认程序
+// 参数逐项传给播放器进程，不经过 shell
+async fn launch_player(app_handle: &AppHandle, input: &Path) -> Result<(), String> {
+    let config = load_player_config(app_handle).await?;
+    let Some(player) = config
+        .player_path
+        .as_deref()
+        .map(str::trim)
+        .filter(|player| !player.is_empty())
+    else {
+        return tauri_plugin_opener::open_path(input, None::<&str>)
+            .map_err(|e| format!("打开视频失败: {}", e));
+    };
+    validate_player_path(Path::new(player))?;
+    validate_player_args(&config.player_args)?;
+
+    let key = (player.to_string(), config.player_args.clone());
+    let confirmed = confirmed_players().contains(&key);
+    if !confirmed {
+        if !confirm_player(app_handle, player, &config.player_args).await {
+            return Err("已取消启动外部播放器".to_string());
+        }
+        confirmed_players().insert(key);
+    }
+
+    let mut child = Command::new(player)
+        .args(player_command_args(&config.player_args, input))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("启动播放器失败: {}", e))?;
+    eprintln!("已启动播放器: {} {}", player, input.display());
+
+    // 回收子进程，避免退出后残留
+    tauri::async_runtime::spawn(async move {
+        let _ = child.wait().await;
+    });
+    Ok(())
+}
+
+async fn get_cartoon_path(
+    app_handle: &AppHandle,
+    cartoon_uuid: &SafeSegment,
+) -> Result<PathBuf, String> {
+    let downloads_path = get_downloads_path(app_handle).await?;
+
+    Ok(downloads_path.join("cartoons").join(cartoon_uuid))
+}
+
+fn get_player_config_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("无法获取应用数据目录: {}", e))?;
+
+    Ok(app_data_dir.join("config").join("player.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_path_replaces_placeholder_or_is_appended() {
+        let input = Path::new("/library/第01集.mp4");
+        let arguments = vec!["--fs".to_string(), "--title=第 1 集; rm -rf".to_string()];
+        assert_eq!(
+            player_command_args(&arguments, input),
+            vec![
+                OsString::from("--fs"),
+                OsString::from("--title=第 1 集; rm -rf"),
+                OsString::from("/library/第01集.mp4"),
+            ]
+        );
+
+        let arguments = vec![
+            "--play".to_string(),
+            "{input}".to_string(),
+            "--fs".to_string(),
+        ];
+        assert_eq!(
+            player_command_args(&arguments, input),
+            vec![
+                OsString::from("--play"),
+                OsString::from("/library/第01集.mp4"),
+                OsString::from("--fs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_control_characters_and_too_many_arguments() {
+        assert!(validate_player_args(&["--fs".to_string()]).is_ok());
+        assert!(validate_player_args(&["--fs\n--other".to_string()]).is_err());
+        assert!(validate_player_args(&vec!["-v".to_string(); MAX_PLAYER_ARGS + 1]).is_err());
+    }
+}
@@ -0,0 +1,482 @@
+use crate::download::remux::{
+    parse_h264_sps, parse_h265_sps, parse_pes_header, psi_section, split_nal_units,
+    timestamp_delta, SpsInfo, AAC_SAMPLE_RATES, MPEG_TIMESCALE, STREAM_TYPE_AAC_ADTS,
+    STREAM_TYPE_H264, STREAM_TYPE_H265, TS_PACKET_SIZE, TS_SYNC_BYTE,
+};
+use crate::download::types::MediaInfo;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+// TS 文件只读取开头和结尾：开头解析节目表、参数集和起始时间戳，结尾取结束时间戳
+const TS_HEAD_PROBE_SIZE: u64 = 4 * 1024 * 1024;
+const TS_TAIL_PROBE_SIZE: u64 = 2 * 1024 * 1024;
+// moov 超过该大小视为文件损坏，不再读取
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+
+/// 解析视频文件的媒体信息，失败时返回 None
+///
+/// 只读取容器结构，不解码画面；在阻塞线程中执行，不占用异步运行时。
+pub async fn probe_media_file(path: &Path) -> Option<MediaInfo> {
+    let path = path.to_path_buf();
+    let result = tokio::task::spawn_blocking(move || probe_media(&path))
+        .await
+        .map_err(|e| format!("解析媒体信息任务异常退出: {}", e))
+        .and_then(|result| result);
+    match result {
+        Ok(info) => Some(info),
+        Err(e) => {
+            eprintln!("解析媒体信息失败: {}", e);
+            None
+        }
+    }
+}
+
+/// 解析 MP4 或 MPEG-TS 文件的时长、分辨率、编码、码率和声道数
+pub fn probe_media(path: &Path) -> Result<MediaInfo, String> {
+    let read_error = |e: std::io::Error| format!("读取视频文件 {} 失败: {}", path.display(), e);
+    let mut file = File::open(path).map_err(read_error)?;
+    let file_size = file.metadata().map_err(read_error)?.len();
+
+    let mut head = vec![0; (TS_PACKET_SIZE + 1).min(file_size as usize)];
+    file.read_exact(&mut head).map_err(read_error)?;
+    let mut info = if head.len() > TS_PACKET_SIZE
+        && head[0] == TS_SYNC_BYTE
+        && head[TS_PACKET_SIZE] == TS_SYNC_BYTE
+    {
+        probe_ts(&mut file, file_size).map_err(|e| format!("{}: {}", path.display(), e))?
+    } else if head.len() >= 8 && is_mp4_box_type(&head[4..8]) {
+        probe_mp4(&mut file, file_size).map_err(|e| format!("{}: {}", path.display(), e))?
+    } else {
+        return Err(format!("无法识别的视频格式: {}", path.display()));
+    };
+
+    if info.duration_secs > 0.0 {
+        info.bitrate = (file_size as f64 * 8.0 / info.duration_secs) as u64;
+    }
+    Ok(info)
+}
+
+pub(crate) fn read_range(file: &mut File, offset: u64, length: u64) -> Result<Vec<u8>, String> {
+    let mut data = vec![0; length as usize];
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.read_exact(&mut data))
+        .map_err(|e| format!("读取文件失败: {}", e))?;
+    Ok(data)
+}
+
+// ==================== MPEG-TS ====================
+
+#[derive(Default)]
+struct TsProbe {
+    pmt_pid: Option<u16>,
+    video: Option<(u16, u8)>, // (PID, 流类型)
+    audio: Option<(u16, u8)>,
+    first_pts: Option<i64>,
+    duration: i64, // 相对第一个时间戳的最大偏移，90kHz
+    video_pes: Vec<u8>,
+    sps: Option<SpsInfo>,
+    aac: Option<(u32, u32)>, // (采样率, 声道数)
+}
+
+impl TsProbe {
+    // 用视频流计时，没有视频时用音频流
+    fn timing_pid(&self) -> Option<u16> {
+        self.video.or(self.audio).map(|(pid, _)| pid)
+    }
+
+    fn scan(&mut self, data: &[u8]) {
+        let mut pos = 0;
+        while pos + TS_PACKET_SIZE <= data.len() {
+            let next_sync = data
+                .get(pos + TS_PACKET_SIZE)
+                .is_none_or(|b| *b == TS_SYNC_BYTE);
+            if data[pos] != TS_SYNC_BYTE || !next_sync {
+                pos += 1;
+                continue;
+            }
+            self.scan_packet(&data[pos..pos + TS_PACKET_SIZE]);
+            pos += TS_PACKET_SIZE;
+        }
+    }
+
+    fn scan_packet(&mut self, packet: &[u8]) {
+        if packet[1] & 0x80 != 0 {
+            return;
+        }
+        let unit_start = packet[1] & 0x40 != 0;
+        let pid = (u16::from(packet[1] & 0x1F) << 8) | u16::from(packet[2]);
+        let adaptation = (packet[3] >> 4) & 0x03;
+
+        let mut offset = 4;
+        if adaptation & 0x02 != 0 {
+            offset += 1 + packet[4] as usize;
+        }
+        if adaptation & 0x01 == 0 || offset >= TS_PACKET_SIZE {
+            return;
+        }
+        let payload = &packet[offset..];
+
+        if pid == 0 {
+            if unit_start && self.pmt_pid.is_none() {
+                self.parse_pat(payload);
+            }
+            return;
+        }
+        if Some(pid) == self.pmt_pid {
+            if unit_start && self.timing_pid().is_none() {
+                self.parse_pmt(payload);
+            }
+            return;
+        }
+
+        let is_video = self.video.is_some_and(|(video_pid, _)| video_pid == pid);
+        if !unit_start {
+            if is_video && self.sps.is_none() {
+                self.video_pes.extend_from_slice(payload);
+            }
+            return;
+        }
+        let Some(pes) = parse_pes_header(pid, payload) else {
+            return;
+        };
+
+        if Some(pid) == self.timing_pid() {
+            if let Some(pts) = pes.pts {
+                match self.first_pts {
+                    Some(first) => self.duration = self.duration.max(timestamp_delta(pts, first)),
+                    None => self.first_pts = Some(pts),
+                }
+            }
+        }
+        if is_video && self.sps.is_none() {
+            // 上一个 PES 已完整，先从中查找 SPS
+            self.find_sps();
+            if self.sps.is_none() {
+                self.video_pes = pes.data;
+            }
+        } else if self.audio.is_some_and(|(audio_pid, stream_type)| {
+            audio_pid == pid && stream_type == STREAM_TYPE_AAC_ADTS
+        }) && self.aac.is_none()
+        {
+            self.aac = parse_adts_header(&pes.data);
+        }
+    }
+
+    fn parse_pat(&mut self, payload: &[u8]) {
+        let Some(section) = psi_section(payload, 0x00) else {
+            return;
+        };
+        for entry in section.get(8..).unwrap_or_default().chunks_exact(4) {
+            let program_number = u16::from_be_bytes([entry[0], entry[1]]);
+            if program_number != 0 {
+                self.pmt_pid = Some((u16::from(entry[2] & 0x1F) << 8) | u16::from(entry[3]));
+                return;
+            }
+        }
+    }
+
+    fn parse_pmt(&mut self, payload: &[u8]) {
+        let Some(section) = psi_section(payload, 0x02) else {
+            return;
+        };
+        if section.len() < 12 {
+            return;
+        }
+        let program_info_length = (usize::from(section[10] & 0x0F) << 8) | usize::from(section[11]);
+
+        let mut pos = 12 + program_info_length;
+        while pos + 5 <= section.len() {
+            let stream_type = section[pos];
+            let pid = (u16::from(section[pos + 1] & 0x1F) << 8) | u16::from(section[pos + 2]);
+            let es_info_length =
+                (usize::from(section[pos + 3] & 0x0F) << 8) | usize::from(section[pos + 4]);
+            pos += 5 + es_info_length;
+
+            match ts_codec_name(stream_type) {
+                Some((true, _)) => {
+                    self.video.get_or_insert((pid, stream_type));
+                }
+                Some((false, _)) => {
+                    self.audio.get_or_insert((pid, stream_type));
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn find_sps(&mut self) {
+        let Some((_, stream_type)) = self.video else {
+            return;
+        };
+        for nal in split_nal_units(&self.video_pes) {
+            let sps = match stream_type {
+                STREAM_TYPE_H264 if nal[0] & 0x1F == 7 => parse_h264_sps(nal),
+                STREAM_TYPE_H265 if (nal[0] >> 1) & 0x3F == 33 => parse_h265_sps(nal),
+                _ => continue,
+            };
+            if let Ok(sps) = sps {
+                self.sps = Some(sps);
+                self.video_pes = Vec::new();
+                return;
+            }
+        }
+    }
+
+    fn into_media_info(mut self) -> MediaInfo {
+        if self.sps.is_none() {
+            self.find_sps();
+        }
+        let (width, height) = self
+            .sps
+            .as_ref()
+            .map_or((0, 0), |sps| (sps.width, sps.height));
+        MediaInfo {
+            container: "mpegts".to_string(),
+            duration_secs: self.duration as f64 / f64::from(MPEG_TIMESCALE),
+            width,
+            height,
+            video_codec: self
+                .video
+                .and_then(|(_, stream_type)| ts_codec_name(stream_type))
+                .map(|(_, name)| name.to_string()),
+            audio_codec: self
+                .audio
+                .and_then(|(_, stream_type)| ts_codec_name(stream_type))
+                .map(|(_, name)| name.to_string()),
+            audio_channels: self.aac.map(|(_, channels)| channels),
+            audio_sample_rate: self.aac.map(|(sample_rate, _)| sample_rate),
+            bitrate: 0,
+        }
+    }
+}
+
+// PMT 流类型对应的编码名称，返回 (是否视频, 名称)
+fn ts_codec_name(stream_type: u8) -> Option<(bool, &'static str)> {
+    match stream_type {
+        STREAM_TYPE_H264 => Some((true, "h264")),
+        STREAM_TYPE_H265 => Some((true, "hevc")),
+        0x01 | 0x02 => Some((true, "mpeg2video")),
+        STREAM_TYPE_AAC_ADTS => Some((false, "aac")),
+        0x03 | 0x04 => Some((false, "mp3")),
+        0x81 => Some((false, "ac3")),
+        0x87 => Some((false, "eac3")),
+        _ => None,
+    }
+}
+
+// 从第一个 ADTS 帧头读取采样率和声道数
+fn parse_adts_header(data: &[u8]) -> Option<(u32, u32)> {
+    let header = data
+        .windows(4)
+        .find(|header| header[0] == 0xFF && header[1] & 0xF6 == 0xF0)?;
+    let sample_rate = *AAC_SAMPLE_RATES.get(usize::from((header[2] >> 2) & 0x0F))?;
+    let channels = (u32::from(header[2] & 0x01) << 2) | u32::from(header[3] >> 6);
+    Some((sample_rate, channels))
+}
+
+fn probe_ts(file: &mut File, file_size: u64) -> Result<MediaInfo, String> {
+    let mut probe = TsProbe::default();
+    probe.scan(&read_range(file, 0, file_size.min(TS_HEAD_PROBE_SIZE))?);
+    if probe.timing_pid().is_none() {
+        return Err("未找到音视频流".to_string());
+    }
+
+    // 结尾部分与开头不重叠时单独读取，取最后的时间戳
+    let tail_start = file_size.saturating_sub(TS_TAIL_PROBE_SIZE);
+    if tail_start > TS_HEAD_PROBE_SIZE {
+        probe.scan(&read_range(file, tail_start, file_size - tail_start)?);
+    } else if file_size > TS_HEAD_PROBE_SIZE {
+        probe.scan(&read_range(
+            file,
+            TS_HEAD_PROBE_SIZE,
+            file_size - TS_HEAD_PROBE_SIZE,
+        )?);
+    }
+    Ok(probe.into_media_info())
+}
+
+// ==================== MP4 ====================
+
+pub(crate) fn is_mp4_box_type(kind: &[u8]) -> bool {
+    matches!(
+        kind,
+        b"ftyp" | b"styp" | b"moov" | b"mdat" | b"free" | b"skip" | b"wide"
+    )
+}
+
+// 按顺序拆分子 box，返回 (类型, 内容)
+pub(crate) fn child_boxes(mut data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut boxes = Vec::new();
+    while data.len() >= 8 {
+        let size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let (header_length, size) = match size {
+            0 => (8, data.len()),
+            1 if data.len() >= 16 => {
+                let large = u64::from_be_bytes(data[8..16].try_into().unwrap_or_default());
+                (16, large as usize)
+            }
+            _ => (8, size),
+        };
+        if size < header_length || size > data.len() {
+            break;
+        }
+        boxes.push((&data[4..8], &data[header_length..size]));
+        data = &data[size..];
+    }
+    boxes
+}
+
+pub(crate) fn find_box<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    child_boxes(data)
+        .into_iter()
+        .find(|(box_kind, _)| *box_kind == kind)
+        .map(|(_, body)| body)
+}
+
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u32::from(u16::from_be_bytes([bytes[0], bytes[1]])))
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u64::from(u32::from_be_bytes(bytes.try_into().ok()?)))
+}
+
+pub(crate) fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+// mvhd/mdhd 中的时间刻度和时长，按版本区分字段宽度
+fn header_duration(body: &[u8]) -> Option<f64> {
+    let (timescale, duration) = if *body.first()? == 1 {
+        (read_u32(body, 20)?, read_u64(body, 24)?)
+    } else {
+        (read_u32(body, 12)?, read_u32(body, 16)?)
+    };
+    (timescale > 0 && duration > 0 && duration != u64::from(u32::MAX))
+        .then(|| duration as f64 / timescale as f64)
+}
+
+// 在顶层 box 中查找 moov 并读取其内容，mdat 等大 box 直接跳过
+pub(crate) fn read_moov(file: &mut File, file_size: u64) -> Result<Vec<u8>, String> {
+    let mut offset = 0;
+    while offset + 8 <= file_size {
+        let header = read_range(file, offset, 8)?;
+        let mut size = read_u32(&header, 0).unwrap_or(0);
+        let mut header_length = 8;
+        if size == 1 {
+            size = read_u64(&read_range(file, offset + 8, 8)?, 0).unwrap_or(0);
+            header_length = 16;
+        } else if size == 0 {
+            size = file_size - offset;
+        }
+        if size < header_length || offset + size > file_size {
+            break;
+        }
+        if &header[4..8] == b"moov" {
+            if size > MAX_MOOV_SIZE {
+                return Err("moov 过大".to_string());
+            }
+            return read_range(file, offset + header_length, size - header_length);
+        }
+        offset += size;
+    }
+    Err("未找到 moov".to_string())
+}
+
+fn probe_mp4(file: &mut File, file_size: u64) -> Result<MediaInfo, String> {
+    let moov = read_moov(file, file_size)?;
+    let mut info = MediaInfo {
+        container: "mp4".to_string(),
+        ..Default::default()
+    };
+
+    let mut duration = find_box(&moov, b"mvhd")
+        .and_then(header_duration)
+        .unwrap_or(0.0);
+    // 分片 MP4 的 mvhd 时长通常为 0，总时长记录在 mehd 中
+    if duration == 0.0 {
+        let timescale = find_box(&moov, b"mvhd")
+            .and_then(|mvhd| read_u32(mvhd, if mvhd.first() == Some(&1) { 20 } else { 12 }))
+            .unwrap_or(0);
+        let fragment_duration = find_box(&moov, b"mvex")
+            .and_then(|mvex| find_box(mvex, b"mehd"))
+            .and_then(|mehd| {
+                if mehd.first() == Some(&1) {
+                    read_u64(mehd, 4)
+                } else {
+                    read_u32(mehd, 4)
+                }
+            })
+            .unwrap_or(0);
+        if timescale > 0 {
+            duration = fragment_duration as f64 / timescale as f64;
+        }
+    }
+
+    for (kind, trak) in child_boxes(&moov) {
+        if kind != b"trak" {
+            continue;
+        }
+        let Some(mdia) = find_box(trak, b"mdia") else {
+            continue;
+        };
+        let handler = find_box(mdia, b"hdlr").and_then(|hdlr| hdlr.get(8..12));
+        let Some((entry_kind, entry)) = find_box(mdia, b"minf")
+            .and_then(|minf| find_box(minf, b"stbl"))
+            .and_then(|stbl| find_box(stbl, b"stsd"))
+            .and_then(|stsd| child_boxes(stsd.get(8..)?).into_iter().next())
+        else {
+            continue;
+        };
+        if duration == 0.0 {
+            duration = find_box(mdia, b"mdhd")
+                .and_then(header_duration)
+                .unwrap_or(0.0);
+        }
+
+        match handler {
+            Some(b"vide") if info.video_codec.is_none() => {
+                info.video_codec = Some(mp4_codec_name(entry_kind));
+                info.width = read_u16(entry, 24).unwrap_or(0);
+                info.height = read_u16(entry, 26).unwrap_or(0);
+                // 样本描述中没有尺寸时使用 tkhd 中的显示尺寸（16.16 定点数）
+                if info.width == 0 || info.height == 0 {
+                    if let Some(tkhd) = find_box(trak, b"tkhd") {
+                        let end = tkhd.len().saturating_sub(8);
+                        info.width = read_u16(tkhd, end).unwrap_or(0);
+                        info.height = read_u16(tkhd, end + 4).unwrap_or(0);
+                    }
+                }
+            }
+            Some(b"soun") if info.audio_codec.is_none() => {
+                info.audio_codec = Some(mp4_codec_name(entry_kind));
+                info.audio_channels = read_u16(entry, 16);
+                info.audio_sample_rate = read_u16(entry, 24).filter(|rate| *rate > 0);
+            }
+            _ => {}
+        }
+    }
+
+    if info.video_codec.is_none() && info.audio_codec.is_none() {
+        return Err("未找到音视频轨道".to_string());
+    }
+    info.duration_secs = duration;
+    Ok(info)
+}
+
+// 样本描述类型对应的编码名称
+fn mp4_codec_name(kind: &[u8]) -> String {
+    match kind {
+        b"avc1" | b"avc3" => "h264".to_string(),
+        b"hvc1" | b"hev1" => "hevc".to_string(),
+        b"mp4a" => "aac".to_string(),
+        b"ac-3" => "ac3".to_string(),
+        b"ec-3" => "eac3".to_string(),
+        b"Opus" => "opus".to_string(),
+        _ => String::from_utf8_lossy(kind).trim().to_string(),
+    }
+}
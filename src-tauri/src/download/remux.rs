@@ -0,0 +1,1783 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+// MPEG-TS 固定包长与同步字节
+pub(crate) const TS_PACKET_SIZE: usize = 188;
+pub(crate) const TS_SYNC_BYTE: u8 = 0x47;
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+// PES 时间戳为 33 位，90kHz 时钟
+const TIMESTAMP_WRAP: i64 = 1 << 33;
+pub(crate) const MPEG_TIMESCALE: u32 = 90000;
+const MOVIE_TIMESCALE: u32 = 1000;
+// 无法推算帧间隔时按 30fps 处理
+const DEFAULT_FRAME_DURATION: i64 = 3000;
+// 时间戳与已写出的时间轴相差超过该值视为不连续（如插播广告），重新锚定时间轴
+const MAX_TIMESTAMP_GAP: i64 = 10 * MPEG_TIMESCALE as i64;
+
+const AAC_FRAME_SAMPLES: u32 = 1024;
+pub(crate) const AAC_SAMPLE_RATES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+// PMT 中的流类型
+pub(crate) const STREAM_TYPE_H264: u8 = 0x1B;
+pub(crate) const STREAM_TYPE_H265: u8 = 0x24;
+pub(crate) const STREAM_TYPE_AAC_ADTS: u8 = 0x0F;
+// 私有数据、ID3 元数据、SCTE-35 等非音视频流直接忽略
+const IGNORED_STREAM_TYPES: [u8; 4] = [0x05, 0x06, 0x15, 0x86];
+
+/// 转封装结果摘要
+#[derive(Debug, Clone)]
+pub struct RemuxSummary {
+    pub video_codec: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub has_audio: bool,
+    pub duration_secs: f64,
+}
+
+/// 待转封装的 TS 分片
+#[derive(Debug, Clone)]
+pub struct RemuxInput {
+    pub path: PathBuf,
+    pub discontinuity: bool, // 分片前有 #EXT-X-DISCONTINUITY，时间戳从此处重新锚定
+}
+
+/// 将按顺序排列的 MPEG-TS 分片转封装为带 moov 索引的 MP4 文件
+///
+/// 只做解复用和重新封装，不重新编码，支持 H.264/H.265 视频和 ADTS 封装的 AAC 音频。
+/// 先写入临时文件，成功后再重命名为目标文件，失败时不会留下不完整的输出。
+pub fn remux_ts_to_mp4(
+    inputs: &[RemuxInput],
+    output: &Path,
+    mut on_progress: impl FnMut(usize, usize),
+) -> Result<RemuxSummary, String> {
+    let mdat_path = sibling_path(output, ".mdat.tmp");
+    let part_path = sibling_path(output, ".part");
+
+    let result = (|| {
+        let mut muxer = Muxer::new(&mdat_path)?;
+        let mut demuxer = TsDemuxer::default();
+        let mut carry = Vec::new();
+
+        let mut buffer = vec![0; READ_BUFFER_SIZE];
+
+        // 按固定大小的缓冲区读取分片，不足一个 TS 包的数据留到下一次
+        for (index, input) in inputs.iter().enumerate() {
+            // 不连续点之前的数据先全部写出，之后的时间戳不再与前面的分片比较
+            if input.discontinuity && index > 0 {
+                demuxer.flush(&mut muxer)?;
+                muxer.mark_discontinuity();
+                carry.clear();
+            }
+
+            let read_error =
+                |e: io::Error| format!("读取分片 {} 失败: {}", input.path.display(), e);
+            let mut file = File::open(&input.path).map_err(read_error)?;
+            loop {
+                let length = file.read(&mut buffer).map_err(read_error)?;
+                if length == 0 {
+                    break;
+                }
+                carry.extend_from_slice(&buffer[..length]);
+
+                let consumed = demuxer.push(&carry, &mut muxer)?;
+                carry.drain(..consumed);
+            }
+            on_progress(index + 1, inputs.len());
+        }
+        demuxer.finish(&mut muxer)?;
+
+        muxer.finish(&part_path)
+    })();
+
+    let _ = fs::remove_file(&mdat_path);
+    match result {
+        Ok(summary) => {
+            fs::rename(&part_path, output).map_err(|e| format!("重命名输出文件失败: {}", e))?;
+            Ok(summary)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&part_path);
+            Err(e)
+        }
+    }
+}
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// 两个 33 位时间戳之差，处理回绕
+pub(crate) fn timestamp_delta(to: i64, from: i64) -> i64 {
+    let delta = (to - from).rem_euclid(TIMESTAMP_WRAP);
+    if delta > TIMESTAMP_WRAP / 2 {
+        delta - TIMESTAMP_WRAP
+    } else {
+        delta
+    }
+}
+
+// ==================== MPEG-TS 解复用 ====================
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum VideoCodec {
+    H264,
+    H265,
+}
+
+pub(crate) struct Pes {
+    pid: u16,
+    pub(crate) pts: Option<i64>,
+    dts: Option<i64>,
+    pub(crate) data: Vec<u8>,
+}
+
+#[derive(Default)]
+struct TsDemuxer {
+    pmt_pid: Option<u16>,
+    video: Option<(u16, VideoCodec)>,
+    audio_pid: Option<u16>,
+    pending: HashMap<u16, Pes>,
+}
+
+impl TsDemuxer {
+    // 处理缓冲区中完整的 TS 包，返回已消费的字节数，剩余不足一个包的数据留给下一个分片
+    fn push(&mut self, data: &[u8], muxer: &mut Muxer) -> Result<usize, String> {
+        let mut pos = 0;
+        while pos + TS_PACKET_SIZE <= data.len() {
+            // 同步字节不对时逐字节重新同步
+            let next_sync = data
+                .get(pos + TS_PACKET_SIZE)
+                .is_none_or(|b| *b == TS_SYNC_BYTE);
+            if data[pos] != TS_SYNC_BYTE || !next_sync {
+                pos += 1;
+                continue;
+            }
+            if let Some(pes) = self.push_packet(&data[pos..pos + TS_PACKET_SIZE])? {
+                muxer.push_pes(pes, self.video)?;
+            }
+            pos += TS_PACKET_SIZE;
+        }
+        Ok(pos)
+    }
+
+    // 写出尚未结束的 PES
+    fn flush(&mut self, muxer: &mut Muxer) -> Result<(), String> {
+        let codec = self.video;
+        let mut remaining: Vec<Pes> = self.pending.drain().map(|(_, pes)| pes).collect();
+        remaining.sort_by_key(|pes| pes.pid);
+        for pes in remaining {
+            muxer.push_pes(pes, codec)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, muxer: &mut Muxer) -> Result<(), String> {
+        self.flush(muxer)?;
+        muxer.flush_audio()
+    }
+
+    fn push_packet(&mut self, packet: &[u8]) -> Result<Option<Pes>, String> {
+        // 传输错误标志置位的包直接丢弃
+        if packet[1] & 0x80 != 0 {
+            return Ok(None);
+        }
+        let unit_start = packet[1] & 0x40 != 0;
+        let pid = (u16::from(packet[1] & 0x1F) << 8) | u16::from(packet[2]);
+        let adaptation = (packet[3] >> 4) & 0x03;
+
+        let mut offset = 4;
+        if adaptation & 0x02 != 0 {
+            offset += 1 + packet[4] as usize;
+        }
+        if adaptation & 0x01 == 0 || offset >= TS_PACKET_SIZE {
+            return Ok(None);
+        }
+        let payload = &packet[offset..];
+
+        if pid == 0 {
+            if unit_start {
+                self.parse_pat(payload);
+            }
+            return Ok(None);
+        }
+        if Some(pid) == self.pmt_pid {
+            if unit_start {
+                self.parse_pmt(payload)?;
+            }
+            return Ok(None);
+        }
+
+        let is_video = self.video.is_some_and(|(video_pid, _)| video_pid == pid);
+        if !is_video && self.audio_pid != Some(pid) {
+            return Ok(None);
+        }
+
+        if unit_start {
+            let finished = self.pending.remove(&pid);
+            if let Some(pes) = parse_pes_header(pid, payload) {
+                self.pending.insert(pid, pes);
+            }
+            return Ok(finished);
+        }
+        if let Some(pes) = self.pending.get_mut(&pid) {
+            pes.data.extend_from_slice(payload);
+        }
+        Ok(None)
+    }
+
+    fn parse_pat(&mut self, payload: &[u8]) {
+        let Some(section) = psi_section(payload, 0x00) else {
+            return;
+        };
+        // 取第一个节目（节目号 0 为网络信息表）
+        for entry in section.get(8..).unwrap_or_default().chunks_exact(4) {
+            let program_number = u16::from_be_bytes([entry[0], entry[1]]);
+            if program_number != 0 {
+                self.pmt_pid = Some((u16::from(entry[2] & 0x1F) << 8) | u16::from(entry[3]));
+                return;
+            }
+        }
+    }
+
+    fn parse_pmt(&mut self, payload: &[u8]) -> Result<(), String> {
+        let Some(section) = psi_section(payload, 0x02) else {
+            return Ok(());
+        };
+        if section.len() < 12 {
+            return Ok(());
+        }
+        let program_info_length = (usize::from(section[10] & 0x0F) << 8) | usize::from(section[11]);
+
+        let mut pos = 12 + program_info_length;
+        while pos + 5 <= section.len() {
+            let stream_type = section[pos];
+            let pid = (u16::from(section[pos + 1] & 0x1F) << 8) | u16::from(section[pos + 2]);
+            let es_info_length =
+                (usize::from(section[pos + 3] & 0x0F) << 8) | usize::from(section[pos + 4]);
+            pos += 5 + es_info_length;
+
+            let codec = match stream_type {
+                STREAM_TYPE_H264 => VideoCodec::H264,
+                STREAM_TYPE_H265 => VideoCodec::H265,
+                STREAM_TYPE_AAC_ADTS => {
+                    self.audio_pid.get_or_insert(pid);
+                    continue;
+                }
+                t if IGNORED_STREAM_TYPES.contains(&t) => continue,
+                t => return Err(format!("不支持的流类型: 0x{:02X}", t)),
+            };
+            match self.video {
+                Some((_, current)) if current != codec => {
+                    return Err("视频编码在流中发生变化".to_string());
+                }
+                Some(_) => {}
+                None => self.video = Some((pid, codec)),
+            }
+        }
+        Ok(())
+    }
+}
+
+// 提取 PSI 表的段数据（去掉指针字段和 CRC）
+pub(crate) fn psi_section(payload: &[u8], table_id: u8) -> Option<&[u8]> {
+    let pointer = *payload.first()? as usize;
+    let section = payload.get(1 + pointer..)?;
+    if section.len() < 3 || section[0] != table_id {
+        return None;
+    }
+    let section_length = (usize::from(section[1] & 0x0F) << 8) | usize::from(section[2]);
+    let end = (3 + section_length).min(section.len()).checked_sub(4)?;
+    section.get(..end)
+}
+
+pub(crate) fn parse_pes_header(pid: u16, payload: &[u8]) -> Option<Pes> {
+    if payload.len() < 9 || payload[..3] != [0, 0, 1] {
+        return None;
+    }
+    let flags = payload[7] >> 6;
+    let header_length = payload[8] as usize;
+    let data = payload.get(9 + header_length..)?;
+
+    let pts = (flags & 0x02 != 0 && header_length >= 5).then(|| read_timestamp(&payload[9..14]));
+    let dts = if flags == 0x03 && header_length >= 10 {
+        Some(read_timestamp(&payload[14..19]))
+    } else {
+        pts
+    };
+
+    Some(Pes {
+        pid,
+        pts,
+        dts,
+        data: data.to_vec(),
+    })
+}
+
+fn read_timestamp(bytes: &[u8]) -> i64 {
+    (i64::from(bytes[0] >> 1) & 0x07) << 30
+        | i64::from(bytes[1]) << 22
+        | i64::from(bytes[2] >> 1) << 15
+        | i64::from(bytes[3]) << 7
+        | i64::from(bytes[4] >> 1)
+}
+
+// ==================== 码流解析 ====================
+
+// 按 Annex B 起始码拆分 NAL 单元
+pub(crate) fn split_nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut units = Vec::new();
+    let mut start = None;
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            if let Some(s) = start {
+                units.push(trim_trailing_zeros(&data[s..i]));
+            }
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(s) = start {
+        units.push(trim_trailing_zeros(&data[s..]));
+    }
+    units.retain(|unit| !unit.is_empty());
+    units
+}
+
+fn trim_trailing_zeros(data: &[u8]) -> &[u8] {
+    let end = data.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    &data[..end]
+}
+
+// 去掉防竞争字节（00 00 03 中的 03）
+fn nal_to_rbsp(nal: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &byte in nal {
+        if zeros >= 2 && byte == 0x03 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+    rbsp
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader { data, pos: 0 }
+    }
+
+    fn bit(&mut self) -> Result<u32, String> {
+        let byte = self
+            .data
+            .get(self.pos / 8)
+            .ok_or_else(|| "参数集数据不完整".to_string())?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Ok(u32::from(bit))
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, String> {
+        let mut value = 0;
+        for _ in 0..count {
+            value = (value << 1) | self.bit()?;
+        }
+        Ok(value)
+    }
+
+    fn skip(&mut self, count: usize) {
+        self.pos += count;
+    }
+
+    // 无符号指数哥伦布编码
+    fn ue(&mut self) -> Result<u32, String> {
+        let mut leading_zeros = 0;
+        while self.bit()? == 0 {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return Err("参数集数据无效".to_string());
+            }
+        }
+        Ok((1u32 << leading_zeros) - 1 + self.bits(leading_zeros)?)
+    }
+
+    fn se(&mut self) -> Result<i32, String> {
+        let value = self.ue()? as i64;
+        Ok(if value % 2 == 0 {
+            -(value / 2) as i32
+        } else {
+            (value / 2 + 1) as i32
+        })
+    }
+}
+
+// 从 SPS 中解析出的封装所需信息
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SpsInfo {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    chroma_format_idc: u32,
+    bit_depth_luma_minus8: u32,
+    bit_depth_chroma_minus8: u32,
+    // 仅 H.265：general_profile_tier_level 的 12 个字节、时间层数和嵌套标志
+    profile_tier_level: Vec<u8>,
+    temporal_layers: u32,
+    temporal_id_nested: bool,
+}
+
+pub(crate) fn parse_h264_sps(nal: &[u8]) -> Result<SpsInfo, String> {
+    let rbsp = nal_to_rbsp(nal.get(1..).unwrap_or_default());
+    if rbsp.len() < 4 {
+        return Err("H.264 SPS 数据不完整".to_string());
+    }
+    let profile_idc = rbsp[0];
+    let mut reader = BitReader::new(&rbsp[3..]);
+    reader.ue()?; // seq_parameter_set_id
+
+    let mut info = SpsInfo {
+        chroma_format_idc: 1,
+        ..Default::default()
+    };
+    let mut separate_colour_plane = false;
+    if matches!(
+        profile_idc,
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+    ) {
+        info.chroma_format_idc = reader.ue()?;
+        if info.chroma_format_idc == 3 {
+            separate_colour_plane = reader.bit()? == 1;
+        }
+        info.bit_depth_luma_minus8 = reader.ue()?;
+        info.bit_depth_chroma_minus8 = reader.ue()?;
+        reader.skip(1); // qpprime_y_zero_transform_bypass_flag
+        if reader.bit()? == 1 {
+            let list_count = if info.chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..list_count {
+                if reader.bit()? == 1 {
+                    skip_scaling_list(&mut reader, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+
+    reader.ue()?; // log2_max_frame_num_minus4
+    match reader.ue()? {
+        0 => {
+            reader.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            reader.skip(1); // delta_pic_order_always_zero_flag
+            reader.se()?;
+            reader.se()?;
+            for _ in 0..reader.ue()? {
+                reader.se()?;
+            }
+        }
+        _ => {}
+    }
+    reader.ue()?; // max_num_ref_frames
+    reader.skip(1); // gaps_in_frame_num_value_allowed_flag
+
+    let width_in_mbs = reader.ue()? + 1;
+    let height_in_map_units = reader.ue()? + 1;
+    let frame_mbs_only = reader.bit()?;
+    if frame_mbs_only == 0 {
+        reader.skip(1); // mb_adaptive_frame_field_flag
+    }
+    reader.skip(1); // direct_8x8_inference_flag
+
+    let (mut crop_x, mut crop_y) = (0, 0);
+    if reader.bit()? == 1 {
+        let (left, right, top, bottom) = (reader.ue()?, reader.ue()?, reader.ue()?, reader.ue()?);
+        let (sub_width, sub_height) = if separate_colour_plane {
+            (1, 1)
+        } else {
+            chroma_subsampling(info.chroma_format_idc)
+        };
+        crop_x = sub_width * (left + right);
+        crop_y = sub_height * (2 - frame_mbs_only) * (top + bottom);
+    }
+
+    info.width = (width_in_mbs * 16).saturating_sub(crop_x);
+    info.height = ((2 - frame_mbs_only) * height_in_map_units * 16).saturating_sub(crop_y);
+    Ok(info)
+}
+
+fn skip_scaling_list(reader: &mut BitReader, size: usize) -> Result<(), String> {
+    let (mut last_scale, mut next_scale) = (8i32, 8i32);
+    for _ in 0..size {
+        if next_scale != 0 {
+            next_scale = (last_scale + reader.se()? + 256) % 256;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn parse_h265_sps(nal: &[u8]) -> Result<SpsInfo, String> {
+    let rbsp = nal_to_rbsp(nal.get(2..).unwrap_or_default());
+    if rbsp.len() < 13 {
+        return Err("H.265 SPS 数据不完整".to_string());
+    }
+    let max_sub_layers_minus1 = u32::from((rbsp[0] >> 1) & 0x07);
+    let mut info = SpsInfo {
+        profile_tier_level: rbsp[1..13].to_vec(),
+        temporal_layers: max_sub_layers_minus1 + 1,
+        temporal_id_nested: rbsp[0] & 0x01 != 0,
+        ..Default::default()
+    };
+
+    // 跳过 general_profile_tier_level 及子层信息
+    let mut reader = BitReader::new(&rbsp[13..]);
+    let mut sub_layer_flags = Vec::new();
+    for _ in 0..max_sub_layers_minus1 {
+        sub_layer_flags.push((reader.bit()?, reader.bit()?));
+    }
+    if max_sub_layers_minus1 > 0 {
+        reader.skip(2 * (8 - max_sub_layers_minus1 as usize));
+    }
+    for (profile_present, level_present) in sub_layer_flags {
+        if profile_present == 1 {
+            reader.skip(88);
+        }
+        if level_present == 1 {
+            reader.skip(8);
+        }
+    }
+
+    reader.ue()?; // sps_seq_parameter_set_id
+    info.chroma_format_idc = reader.ue()?;
+    let mut separate_colour_plane = false;
+    if info.chroma_format_idc == 3 {
+        separate_colour_plane = reader.bit()? == 1;
+    }
+    let width = reader.ue()?;
+    let height = reader.ue()?;
+
+    let (mut crop_x, mut crop_y) = (0, 0);
+    if reader.bit()? == 1 {
+        let (left, right, top, bottom) = (reader.ue()?, reader.ue()?, reader.ue()?, reader.ue()?);
+        let (sub_width, sub_height) = if separate_colour_plane {
+            (1, 1)
+        } else {
+            chroma_subsampling(info.chroma_format_idc)
+        };
+        crop_x = sub_width * (left + right);
+        crop_y = sub_height * (top + bottom);
+    }
+    info.bit_depth_luma_minus8 = reader.ue()?;
+    info.bit_depth_chroma_minus8 = reader.ue()?;
+
+    info.width = width.saturating_sub(crop_x);
+    info.height = height.saturating_sub(crop_y);
+    Ok(info)
+}
+
+// 色度采样对应的裁剪单位
+fn chroma_subsampling(chroma_format_idc: u32) -> (u32, u32) {
+    match chroma_format_idc {
+        1 => (2, 2),
+        2 => (2, 1),
+        _ => (1, 1),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct AacConfig {
+    object_type: u8,
+    frequency_index: u8,
+    channels: u8,
+}
+
+impl AacConfig {
+    fn sample_rate(&self) -> u32 {
+        AAC_SAMPLE_RATES[self.frequency_index as usize]
+    }
+
+    // AudioSpecificConfig
+    fn to_bytes(self) -> [u8; 2] {
+        let value = (u16::from(self.object_type) << 11)
+            | (u16::from(self.frequency_index) << 7)
+            | (u16::from(self.channels) << 3);
+        value.to_be_bytes()
+    }
+}
+
+// ==================== MP4 封装 ====================
+
+struct Sample {
+    offset: u64, // 在 mdat 数据中的偏移
+    size: u32,
+    dts: i64,
+    composition_offset: u32,
+    keyframe: bool,
+}
+
+// 将音视频两条轨道的 33 位 PES 时间戳映射到同一条从 0 开始的时间轴（90kHz）
+//
+// 两条轨道共用一个锚点：遇到不连续点时，之后第一个时间戳接在两条轨道已写出的最晚结束时间之后，
+// 另一条轨道随后的时间戳按同一锚点换算，音画的相对位置保持不变。
+#[derive(Default)]
+struct Timeline {
+    anchor: Option<(i64, i64)>, // 最近一次映射的原始时间戳和对应的时间
+    end: i64,                   // 已写出样本的最晚结束时间
+    discontinuity: bool,        // 下一个时间戳前有分片标记的不连续点
+}
+
+impl Timeline {
+    // 返回映射后的时间，以及是否在此处重新锚定
+    fn map(&mut self, raw: i64) -> (i64, bool) {
+        if let Some((anchor_raw, anchor_time)) = self.anchor.filter(|_| !self.discontinuity) {
+            let time = anchor_time + timestamp_delta(raw, anchor_raw);
+            if (time - self.end).abs() <= MAX_TIMESTAMP_GAP {
+                self.anchor = Some((raw, time));
+                return (time, false);
+            }
+        }
+        self.discontinuity = false;
+        self.anchor = Some((raw, self.end));
+        (self.end, true)
+    }
+
+    fn extend(&mut self, end: i64) {
+        self.end = self.end.max(end);
+    }
+}
+
+// 在两种时间刻度之间换算
+fn rescale(value: i64, from: u32, to: u32) -> i64 {
+    value * i64::from(to) / i64::from(from)
+}
+
+// 由 PES 时间戳推算的音频帧时间与紧接上一帧的时间相差不到半帧，或者比它更早时，
+// 视为时间戳抖动或重叠，按连续排列；明显更晚时保留空隙
+fn audio_frame_dts(from_pts: Option<i64>, contiguous: Option<i64>) -> i64 {
+    let frame = i64::from(AAC_FRAME_SAMPLES);
+    match (from_pts, contiguous) {
+        (Some(dts), Some(next)) if dts < next + frame / 2 => next,
+        (Some(dts), _) => dts,
+        (None, next) => next.unwrap_or(0),
+    }
+}
+
+struct VideoTrack {
+    codec: VideoCodec,
+    vps: Vec<Vec<u8>>,
+    sps: Vec<Vec<u8>>,
+    pps: Vec<Vec<u8>>,
+    sps_info: Option<SpsInfo>,
+    samples: Vec<Sample>,
+    step: i64, // 最近的帧间隔，时间戳缺失或倒退时按它接续
+}
+
+#[derive(Default)]
+struct AudioTrack {
+    config: Option<AacConfig>,
+    samples: Vec<Sample>, // 时间戳以采样率为刻度
+    remainder: Vec<u8>,
+    total_bytes: u64,
+}
+
+struct Muxer {
+    mdat: BufWriter<File>,
+    mdat_size: u64,
+    timeline: Timeline,
+    video: Option<VideoTrack>,
+    audio: AudioTrack,
+}
+
+impl Muxer {
+    fn new(mdat_path: &Path) -> Result<Self, String> {
+        // 样本数据先写入临时文件，封装时再复制到 mdat 中
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(mdat_path)
+            .map_err(|e| format!("创建临时文件失败: {}", e))?;
+        Ok(Muxer {
+            mdat: BufWriter::new(file),
+            mdat_size: 0,
+            timeline: Timeline::default(),
+            video: None,
+            audio: AudioTrack::default(),
+        })
+    }
+
+    // 跨越不连续点的不完整音频帧丢弃，下一个时间戳重新锚定
+    fn mark_discontinuity(&mut self) {
+        self.audio.remainder.clear();
+        self.timeline.discontinuity = true;
+    }
+
+    fn push_pes(&mut self, pes: Pes, video: Option<(u16, VideoCodec)>) -> Result<(), String> {
+        match video {
+            Some((pid, codec)) if pid == pes.pid => self.push_video(pes, codec),
+            _ => self.push_audio(pes),
+        }
+    }
+
+    fn push_video(&mut self, pes: Pes, codec: VideoCodec) -> Result<(), String> {
+        let track = self.video.get_or_insert_with(|| VideoTrack {
+            codec,
+            vps: Vec::new(),
+            sps: Vec::new(),
+            pps: Vec::new(),
+            sps_info: None,
+            samples: Vec::new(),
+            step: DEFAULT_FRAME_DURATION,
+        });
+        if track.codec != codec {
+            return Err("视频编码在流中发生变化".to_string());
+        }
+
+        let mut keyframe = false;
+        let mut units = Vec::new();
+        for nal in split_nal_units(&pes.data) {
+            let kind = match codec {
+                VideoCodec::H264 => nal[0] & 0x1F,
+                VideoCodec::H265 => (nal[0] >> 1) & 0x3F,
+            };
+            // 参数集放入样本描述，访问单元分隔符和填充数据丢弃
+            match (codec, kind) {
+                (VideoCodec::H264, 7) | (VideoCodec::H265, 33) => {
+                    if track.sps_info.is_none() {
+                        track.sps_info = Some(match codec {
+                            VideoCodec::H264 => parse_h264_sps(nal)?,
+                            VideoCodec::H265 => parse_h265_sps(nal)?,
+                        });
+                    }
+                    add_parameter_set(&mut track.sps, nal);
+                }
+                (VideoCodec::H264, 8) | (VideoCodec::H265, 34) => {
+                    add_parameter_set(&mut track.pps, nal)
+                }
+                (VideoCodec::H265, 32) => add_parameter_set(&mut track.vps, nal),
+                (VideoCodec::H264, 9 | 12) | (VideoCodec::H265, 35 | 38) => {}
+                (VideoCodec::H264, 5) | (VideoCodec::H265, 16..=21) => {
+                    keyframe = true;
+                    units.push(nal);
+                }
+                _ => units.push(nal),
+            }
+        }
+
+        // 第一个关键帧之前的数据无法独立解码
+        if units.is_empty() || (track.samples.is_empty() && (!keyframe || track.sps_info.is_none()))
+        {
+            return Ok(());
+        }
+
+        let last_dts = track.samples.last().map(|sample| sample.dts);
+        let (mut dts, rebased, composition_offset) = match pes.dts {
+            Some(dts_raw) => {
+                let (dts, rebased) = self.timeline.map(dts_raw);
+                let pts_raw = pes.pts.unwrap_or(dts_raw);
+                (
+                    dts,
+                    rebased,
+                    timestamp_delta(pts_raw, dts_raw).max(0) as u32,
+                )
+            }
+            None => (last_dts.map_or(0, |last| last + track.step), true, 0),
+        };
+        if let Some(last) = last_dts {
+            if dts <= last {
+                dts = last + track.step;
+            } else if !rebased {
+                track.step = dts - last;
+            }
+        }
+        self.timeline.extend(dts + track.step);
+
+        // 样本数据使用 4 字节长度前缀
+        let offset = self.mdat_size;
+        let mut size = 0u64;
+        for nal in units {
+            self.mdat
+                .write_all(&(nal.len() as u32).to_be_bytes())
+                .and_then(|_| self.mdat.write_all(nal))
+                .map_err(|e| format!("写入临时文件失败: {}", e))?;
+            size += 4 + nal.len() as u64;
+        }
+        self.mdat_size += size;
+
+        track.samples.push(Sample {
+            offset,
+            size: size as u32,
+            dts,
+            composition_offset,
+            keyframe,
+        });
+        Ok(())
+    }
+
+    fn push_audio(&mut self, pes: Pes) -> Result<(), String> {
+        let track = &mut self.audio;
+        let mut data = std::mem::take(&mut track.remainder);
+        // 从上一个 PES 留下的帧按连续排列，本 PES 中的帧从 PES 时间戳开始依次排列
+        let pes_start = data.len();
+        data.extend_from_slice(&pes.data);
+        let mut pes_time = None;
+        let mut pes_frames = 0;
+
+        let mut pos = 0;
+        while pos + 7 <= data.len() {
+            let Some((config, header_length, frame_length)) = parse_adts_header(&data[pos..])
+            else {
+                pos += 1;
+                continue;
+            };
+            // 跨 PES 的不完整帧留到下一个 PES 拼接
+            if pos + frame_length > data.len() {
+                break;
+            }
+
+            match track.config {
+                None => {
+                    if config.frequency_index as usize >= AAC_SAMPLE_RATES.len() {
+                        return Err("不支持的AAC采样率".to_string());
+                    }
+                    if config.channels == 0 {
+                        return Err("不支持的AAC声道配置".to_string());
+                    }
+                    track.config = Some(config);
+                }
+                Some(current) if current != config => {
+                    return Err("音频参数在流中发生变化".to_string());
+                }
+                Some(_) => {}
+            }
+            let sample_rate = config.sample_rate();
+
+            let from_pts = match pes.pts {
+                Some(pts) if pos >= pes_start => {
+                    let time = *pes_time.get_or_insert_with(|| self.timeline.map(pts).0);
+                    pes_frames += 1;
+                    Some(
+                        rescale(time, MPEG_TIMESCALE, sample_rate)
+                            + (pes_frames - 1) * i64::from(AAC_FRAME_SAMPLES),
+                    )
+                }
+                _ => None,
+            };
+            let contiguous = track
+                .samples
+                .last()
+                .map(|sample| sample.dts + i64::from(AAC_FRAME_SAMPLES));
+            let dts = audio_frame_dts(from_pts, contiguous);
+            self.timeline.extend(rescale(
+                dts + i64::from(AAC_FRAME_SAMPLES),
+                sample_rate,
+                MPEG_TIMESCALE,
+            ));
+
+            let frame = &data[pos + header_length..pos + frame_length];
+            self.mdat
+                .write_all(frame)
+                .map_err(|e| format!("写入临时文件失败: {}", e))?;
+            track.samples.push(Sample {
+                offset: self.mdat_size,
+                size: frame.len() as u32,
+                dts,
+                composition_offset: 0,
+                keyframe: true,
+            });
+            self.mdat_size += frame.len() as u64;
+            track.total_bytes += frame.len() as u64;
+            pos += frame_length;
+        }
+        track.remainder = data[pos..].to_vec();
+        Ok(())
+    }
+
+    fn flush_audio(&mut self) -> Result<(), String> {
+        self.audio.remainder.clear();
+        self.mdat
+            .flush()
+            .map_err(|e| format!("写入临时文件失败: {}", e))
+    }
+
+    fn finish(self, output: &Path) -> Result<RemuxSummary, String> {
+        let Muxer {
+            mdat,
+            mdat_size,
+            video,
+            audio,
+            ..
+        } = self;
+        let mdat_file = mdat
+            .into_inner()
+            .map_err(|e| format!("写入临时文件失败: {}", e))?;
+
+        let video = video.filter(|track| !track.samples.is_empty());
+        let audio = Some(audio).filter(|track| !track.samples.is_empty());
+        if video.is_none() && audio.is_none() {
+            return Err("未找到可封装的音视频数据".to_string());
+        }
+
+        let mut tracks = Vec::new();
+        if let Some(track) = &video {
+            tracks.push(TrackLayout::video(track)?);
+        }
+        if let Some(track) = &audio {
+            tracks.push(TrackLayout::audio(track)?);
+        }
+
+        // 两条轨道在同一时间轴上，起始时间不同时用空编辑对齐
+        if let (Some(video), Some(audio)) = (&video, &audio) {
+            let first_video = &video.samples[0];
+            let video_start = first_video.dts + i64::from(first_video.composition_offset);
+            let audio_start = rescale(
+                audio.samples[0].dts,
+                audio
+                    .config
+                    .map_or(MPEG_TIMESCALE, |config| config.sample_rate()),
+                MPEG_TIMESCALE,
+            );
+            let delta = audio_start - video_start;
+            let delay =
+                delta.unsigned_abs() * u64::from(MOVIE_TIMESCALE) / u64::from(MPEG_TIMESCALE);
+            let delayed = if delta > 0 { 1 } else { 0 };
+            tracks[delayed].start_delay = delay;
+        }
+
+        let ftyp = build_ftyp(video.as_ref().map(|track| track.codec));
+        let mdat_header_length: u64 = if mdat_size + 8 > u64::from(u32::MAX) {
+            16
+        } else {
+            8
+        };
+
+        // 先按 32 位偏移计算 moov 大小，文件超过 4GB 时改用 co64
+        let mut use_co64 = false;
+        let mut moov_length = build_moov(&tracks, 0, use_co64).len() as u64;
+        let mut data_start = ftyp.len() as u64 + moov_length + mdat_header_length;
+        if data_start + mdat_size > u64::from(u32::MAX) {
+            use_co64 = true;
+            moov_length = build_moov(&tracks, 0, use_co64).len() as u64;
+            data_start = ftyp.len() as u64 + moov_length + mdat_header_length;
+        }
+        let moov = build_moov(&tracks, data_start, use_co64);
+
+        let file = File::create(output).map_err(|e| format!("创建输出文件失败: {}", e))?;
+        let mut writer = BufWriter::new(file);
+        let write_error = |e: io::Error| format!("写入输出文件失败: {}", e);
+        writer.write_all(&ftyp).map_err(write_error)?;
+        writer.write_all(&moov).map_err(write_error)?;
+        if mdat_header_length == 16 {
+            writer.write_all(&1u32.to_be_bytes()).map_err(write_error)?;
+            writer.write_all(b"mdat").map_err(write_error)?;
+            writer
+                .write_all(&(mdat_size + 16).to_be_bytes())
+                .map_err(write_error)?;
+        } else {
+            writer
+                .write_all(&((mdat_size + 8) as u32).to_be_bytes())
+                .map_err(write_error)?;
+            writer.write_all(b"mdat").map_err(write_error)?;
+        }
+
+        let mut mdat_file = mdat_file;
+        io::Seek::rewind(&mut mdat_file).map_err(write_error)?;
+        io::copy(&mut mdat_file, &mut writer).map_err(write_error)?;
+        writer.flush().map_err(write_error)?;
+
+        let duration_secs = tracks
+            .iter()
+            .map(|track| {
+                (track.start_delay as f64 / f64::from(MOVIE_TIMESCALE))
+                    + (track.duration as f64 / f64::from(track.timescale))
+            })
+            .fold(0.0, f64::max);
+        let sps_info = video.as_ref().and_then(|track| track.sps_info.clone());
+        Ok(RemuxSummary {
+            video_codec: video.as_ref().map(|track| {
+                match track.codec {
+                    VideoCodec::H264 => "h264",
+                    VideoCodec::H265 => "h265",
+                }
+                .to_string()
+            }),
+            width: sps_info.as_ref().map_or(0, |info| info.width),
+            height: sps_info.as_ref().map_or(0, |info| info.height),
+            has_audio: audio.is_some(),
+            duration_secs,
+        })
+    }
+}
+
+// 解析 ADTS 帧头，返回音频参数、帧头长度和包含帧头的帧长度
+fn parse_adts_header(header: &[u8]) -> Option<(AacConfig, usize, usize)> {
+    if header.len() < 7 || header[0] != 0xFF || header[1] & 0xF0 != 0xF0 {
+        return None;
+    }
+    let header_length = if header[1] & 0x01 != 0 { 7 } else { 9 };
+    let frame_length = (usize::from(header[3] & 0x03) << 11)
+        | (usize::from(header[4]) << 3)
+        | usize::from(header[5] >> 5);
+    if frame_length <= header_length {
+        return None;
+    }
+    let config = AacConfig {
+        object_type: ((header[2] >> 6) & 0x03) + 1,
+        frequency_index: (header[2] >> 2) & 0x0F,
+        channels: ((header[2] & 0x01) << 2) | (header[3] >> 6),
+    };
+    Some((config, header_length, frame_length))
+}
+
+fn add_parameter_set(sets: &mut Vec<Vec<u8>>, nal: &[u8]) {
+    if !sets.iter().any(|set| set == nal) {
+        sets.push(nal.to_vec());
+    }
+}
+
+// 写入 moov 所需的单个轨道信息
+struct TrackLayout<'a> {
+    track_id: u32,
+    handler: &'static [u8; 4],
+    timescale: u32,
+    samples: &'a [Sample],
+    durations: Vec<u32>,
+    duration: u64,    // 轨道时长（轨道时间刻度）
+    start_delay: u64, // 起始空编辑时长（影片时间刻度）
+    sample_entry: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+impl<'a> TrackLayout<'a> {
+    fn video(track: &'a VideoTrack) -> Result<Self, String> {
+        let info = track
+            .sps_info
+            .as_ref()
+            .ok_or_else(|| "缺少视频参数集".to_string())?;
+        if track.pps.is_empty() || (track.codec == VideoCodec::H265 && track.vps.is_empty()) {
+            return Err("缺少视频参数集".to_string());
+        }
+
+        let samples = &track.samples;
+        let durations = sample_durations(samples, DEFAULT_FRAME_DURATION as u32);
+
+        let config = match track.codec {
+            VideoCodec::H264 => (
+                b"avc1",
+                mp4_box_bytes(b"avcC", |b| build_avcc(b, track, info)),
+            ),
+            VideoCodec::H265 => (
+                b"hvc1",
+                mp4_box_bytes(b"hvcC", |b| build_hvcc(b, track, info)),
+            ),
+        };
+        let sample_entry = mp4_box_bytes(config.0, |b| {
+            b.extend_from_slice(&[0; 6]);
+            put(b, 1u16.to_be_bytes()); // data_reference_index
+            b.extend_from_slice(&[0; 16]);
+            put(b, (info.width as u16).to_be_bytes());
+            put(b, (info.height as u16).to_be_bytes());
+            put(b, 0x0048_0000u32.to_be_bytes()); // 72 dpi
+            put(b, 0x0048_0000u32.to_be_bytes());
+            put(b, 0u32.to_be_bytes());
+            put(b, 1u16.to_be_bytes()); // frame_count
+            b.extend_from_slice(&[0; 32]); // compressorname
+            put(b, 0x0018u16.to_be_bytes());
+            put(b, (-1i16).to_be_bytes());
+            b.extend_from_slice(&config.1);
+        });
+
+        Ok(TrackLayout {
+            track_id: 1,
+            handler: b"vide",
+            timescale: MPEG_TIMESCALE,
+            samples,
+            duration: durations.iter().map(|d| u64::from(*d)).sum(),
+            durations,
+            start_delay: 0,
+            sample_entry,
+            width: info.width,
+            height: info.height,
+        })
+    }
+
+    fn audio(track: &'a AudioTrack) -> Result<Self, String> {
+        let config = track.config.ok_or_else(|| "缺少音频参数".to_string())?;
+        let sample_rate = config.sample_rate();
+        let durations = sample_durations(&track.samples, AAC_FRAME_SAMPLES);
+        let duration: u64 = durations.iter().map(|d| u64::from(*d)).sum();
+        let bitrate = (track.total_bytes * 8 * u64::from(sample_rate))
+            .checked_div(duration)
+            .unwrap_or(0) as u32;
+
+        let esds = mp4_full_box_bytes(b"esds", 0, 0, |b| {
+            let decoder_specific = descriptor(0x05, &config.to_bytes());
+            let mut decoder_config = vec![0x40, 0x15, 0, 0, 0]; // AAC，音频流，缓冲区大小
+            put(&mut decoder_config, bitrate.to_be_bytes());
+            put(&mut decoder_config, bitrate.to_be_bytes());
+            decoder_config.extend_from_slice(&decoder_specific);
+
+            let mut es = vec![0, 2, 0]; // ES_ID，标志
+            es.extend_from_slice(&descriptor(0x04, &decoder_config));
+            es.extend_from_slice(&descriptor(0x06, &[0x02]));
+            b.extend_from_slice(&descriptor(0x03, &es));
+        });
+        let sample_entry = mp4_box_bytes(b"mp4a", |b| {
+            b.extend_from_slice(&[0; 6]);
+            put(b, 1u16.to_be_bytes()); // data_reference_index
+            b.extend_from_slice(&[0; 8]);
+            put(b, u16::from(config.channels).to_be_bytes());
+            put(b, 16u16.to_be_bytes()); // samplesize
+            put(b, 0u32.to_be_bytes());
+            put(b, (sample_rate << 16).to_be_bytes());
+            b.extend_from_slice(&esds);
+        });
+
+        Ok(TrackLayout {
+            track_id: 2,
+            handler: b"soun",
+            timescale: sample_rate,
+            samples: &track.samples,
+            durations,
+            duration,
+            start_delay: 0,
+            sample_entry,
+            width: 0,
+            height: 0,
+        })
+    }
+
+    fn movie_duration(&self) -> u64 {
+        self.duration * u64::from(MOVIE_TIMESCALE) / u64::from(self.timescale)
+    }
+}
+
+// 由相邻样本的解码时间计算每个样本的时长，最后一个样本沿用上一个时长
+fn sample_durations(samples: &[Sample], default: u32) -> Vec<u32> {
+    let mut durations: Vec<u32> = samples
+        .windows(2)
+        .map(|pair| (pair[1].dts - pair[0].dts) as u32)
+        .collect();
+    durations.push(durations.last().copied().unwrap_or(default));
+    durations
+}
+
+fn put<const N: usize>(buf: &mut Vec<u8>, bytes: [u8; N]) {
+    buf.extend_from_slice(&bytes);
+}
+
+fn mp4_box(buf: &mut Vec<u8>, kind: &[u8; 4], body: impl FnOnce(&mut Vec<u8>)) {
+    let start = buf.len();
+    buf.extend_from_slice(&[0; 4]);
+    buf.extend_from_slice(kind);
+    body(buf);
+    let size = (buf.len() - start) as u32;
+    buf[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+fn mp4_full_box(
+    buf: &mut Vec<u8>,
+    kind: &[u8; 4],
+    version: u8,
+    flags: u32,
+    body: impl FnOnce(&mut Vec<u8>),
+) {
+    mp4_box(buf, kind, |b| {
+        b.push(version);
+        b.extend_from_slice(&flags.to_be_bytes()[1..]);
+        body(b);
+    });
+}
+
+fn mp4_box_bytes(kind: &[u8; 4], body: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+    let mut buf = Vec::new();
+    mp4_box(&mut buf, kind, body);
+    buf
+}
+
+fn mp4_full_box_bytes(
+    kind: &[u8; 4],
+    version: u8,
+    flags: u32,
+    body: impl FnOnce(&mut Vec<u8>),
+) -> Vec<u8> {
+    let mut buf = Vec::new();
+    mp4_full_box(&mut buf, kind, version, flags, body);
+    buf
+}
+
+// MPEG-4 描述符，内容均小于 128 字节，长度用单字节表示
+fn descriptor(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut buf = vec![tag, body.len() as u8];
+    buf.extend_from_slice(body);
+    buf
+}
+
+fn build_avcc(buf: &mut Vec<u8>, track: &VideoTrack, info: &SpsInfo) {
+    let first_sps = &track.sps[0];
+    buf.push(1); // configurationVersion
+    buf.extend_from_slice(&first_sps[1..4]); // profile、兼容性标志、level
+    buf.push(0xFF); // 长度前缀 4 字节
+    buf.push(0xE0 | track.sps.len().min(31) as u8);
+    for sps in track.sps.iter().take(31) {
+        put(buf, (sps.len() as u16).to_be_bytes());
+        buf.extend_from_slice(sps);
+    }
+    buf.push(track.pps.len().min(255) as u8);
+    for pps in track.pps.iter().take(255) {
+        put(buf, (pps.len() as u16).to_be_bytes());
+        buf.extend_from_slice(pps);
+    }
+    if matches!(first_sps[1], 100 | 110 | 122 | 144) {
+        buf.push(0xFC | info.chroma_format_idc as u8);
+        buf.push(0xF8 | info.bit_depth_luma_minus8 as u8);
+        buf.push(0xF8 | info.bit_depth_chroma_minus8 as u8);
+        buf.push(0);
+    }
+}
+
+fn build_hvcc(buf: &mut Vec<u8>, track: &VideoTrack, info: &SpsInfo) {
+    buf.push(1); // configurationVersion
+    buf.extend_from_slice(&info.profile_tier_level);
+    put(buf, 0xF000u16.to_be_bytes()); // min_spatial_segmentation_idc
+    buf.push(0xFC); // parallelismType
+    buf.push(0xFC | info.chroma_format_idc as u8);
+    buf.push(0xF8 | info.bit_depth_luma_minus8 as u8);
+    buf.push(0xF8 | info.bit_depth_chroma_minus8 as u8);
+    put(buf, 0u16.to_be_bytes()); // avgFrameRate
+    buf.push(
+        ((info.temporal_layers as u8 & 0x07) << 3)
+            | (u8::from(info.temporal_id_nested) << 2)
+            | 0x03, // 长度前缀 4 字节
+    );
+
+    let arrays = [(32u8, &track.vps), (33, &track.sps), (34, &track.pps)];
+    buf.push(arrays.len() as u8);
+    for (nal_type, units) in arrays {
+        buf.push(0x80 | nal_type); // array_completeness：参数集只在样本描述中
+        put(buf, (units.len() as u16).to_be_bytes());
+        for unit in units.iter() {
+            put(buf, (unit.len() as u16).to_be_bytes());
+            buf.extend_from_slice(unit);
+        }
+    }
+}
+
+fn build_ftyp(video_codec: Option<VideoCodec>) -> Vec<u8> {
+    mp4_box_bytes(b"ftyp", |b| {
+        b.extend_from_slice(b"isom");
+        put(b, 0x200u32.to_be_bytes());
+        b.extend_from_slice(b"isomiso2mp41");
+        match video_codec {
+            Some(VideoCodec::H264) => b.extend_from_slice(b"avc1"),
+            Some(VideoCodec::H265) => b.extend_from_slice(b"hvc1"),
+            None => {}
+        }
+    })
+}
+
+const UNITY_MATRIX: [u32; 9] = [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000];
+
+fn put_matrix(buf: &mut Vec<u8>) {
+    for value in UNITY_MATRIX {
+        put(buf, value.to_be_bytes());
+    }
+}
+
+fn clamp_u32(value: u64) -> u32 {
+    u32::try_from(value).unwrap_or(u32::MAX)
+}
+
+fn build_moov(tracks: &[TrackLayout], data_start: u64, use_co64: bool) -> Vec<u8> {
+    let movie_duration = tracks
+        .iter()
+        .map(|track| track.start_delay + track.movie_duration())
+        .max()
+        .unwrap_or(0);
+
+    mp4_box_bytes(b"moov", |b| {
+        mp4_full_box(b, b"mvhd", 0, 0, |b| {
+            put(b, 0u32.to_be_bytes()); // creation_time
+            put(b, 0u32.to_be_bytes()); // modification_time
+            put(b, MOVIE_TIMESCALE.to_be_bytes());
+            put(b, clamp_u32(movie_duration).to_be_bytes());
+            put(b, 0x0001_0000u32.to_be_bytes()); // rate
+            put(b, 0x0100u16.to_be_bytes()); // volume
+            b.extend_from_slice(&[0; 10]);
+            put_matrix(b);
+            b.extend_from_slice(&[0; 24]);
+            put(b, (tracks.len() as u32 + 1).to_be_bytes()); // next_track_ID
+        });
+        for track in tracks {
+            build_trak(b, track, data_start, use_co64);
+        }
+    })
+}
+
+fn build_trak(buf: &mut Vec<u8>, track: &TrackLayout, data_start: u64, use_co64: bool) {
+    let is_video = track.handler == b"vide";
+    let first_offset = track
+        .samples
+        .first()
+        .map_or(0, |sample| sample.composition_offset);
+
+    mp4_box(buf, b"trak", |b| {
+        mp4_full_box(b, b"tkhd", 0, 0x03, |b| {
+            put(b, 0u32.to_be_bytes());
+            put(b, 0u32.to_be_bytes());
+            put(b, track.track_id.to_be_bytes());
+            put(b, 0u32.to_be_bytes());
+            put(
+                b,
+                clamp_u32(track.start_delay + track.movie_duration()).to_be_bytes(),
+            );
+            b.extend_from_slice(&[0; 8]);
+            put(b, 0u16.to_be_bytes()); // layer
+            put(b, 0u16.to_be_bytes()); // alternate_group
+            put(b, if is_video { 0u16 } else { 0x0100 }.to_be_bytes());
+            put(b, 0u16.to_be_bytes());
+            put_matrix(b);
+            put(b, (track.width << 16).to_be_bytes());
+            put(b, (track.height << 16).to_be_bytes());
+        });
+
+        // 编辑列表：起始延迟用空编辑，B 帧的显示时间偏移从第一帧的合成偏移开始
+        if track.start_delay > 0 || first_offset > 0 {
+            mp4_box(b, b"edts", |b| {
+                let entries = if track.start_delay > 0 { 2u32 } else { 1 };
+                mp4_full_box(b, b"elst", 0, 0, |b| {
+                    put(b, entries.to_be_bytes());
+                    if track.start_delay > 0 {
+                        put(b, clamp_u32(track.start_delay).to_be_bytes());
+                        put(b, (-1i32).to_be_bytes());
+                        put(b, 0x0001_0000u32.to_be_bytes());
+                    }
+                    put(b, clamp_u32(track.movie_duration()).to_be_bytes());
+                    put(b, first_offset.to_be_bytes());
+                    put(b, 0x0001_0000u32.to_be_bytes());
+                });
+            });
+        }
+
+        mp4_box(b, b"mdia", |b| {
+            mp4_full_box(b, b"mdhd", 0, 0, |b| {
+                put(b, 0u32.to_be_bytes());
+                put(b, 0u32.to_be_bytes());
+                put(b, track.timescale.to_be_bytes());
+                put(b, clamp_u32(track.duration).to_be_bytes());
+                put(b, 0x55C4u16.to_be_bytes()); // 语言 "und"
+                put(b, 0u16.to_be_bytes());
+            });
+            mp4_full_box(b, b"hdlr", 0, 0, |b| {
+                put(b, 0u32.to_be_bytes());
+                b.extend_from_slice(track.handler);
+                b.extend_from_slice(&[0; 12]);
+                b.extend_from_slice(if is_video {
+                    b"VideoHandler\0"
+                } else {
+                    b"SoundHandler\0"
+                });
+            });
+            mp4_box(b, b"minf", |b| {
+                if is_video {
+                    mp4_full_box(b, b"vmhd", 0, 0x01, |b| b.extend_from_slice(&[0; 8]));
+                } else {
+                    mp4_full_box(b, b"smhd", 0, 0, |b| b.extend_from_slice(&[0; 4]));
+                }
+                mp4_box(b, b"dinf", |b| {
+                    mp4_full_box(b, b"dref", 0, 0, |b| {
+                        put(b, 1u32.to_be_bytes());
+                        mp4_full_box(b, b"url ", 0, 0x01, |_| {});
+                    });
+                });
+                build_stbl(b, track, data_start, use_co64);
+            });
+        });
+    });
+}
+
+fn build_stbl(buf: &mut Vec<u8>, track: &TrackLayout, data_start: u64, use_co64: bool) {
+    let samples = track.samples;
+
+    mp4_box(buf, b"stbl", |b| {
+        mp4_full_box(b, b"stsd", 0, 0, |b| {
+            put(b, 1u32.to_be_bytes());
+            b.extend_from_slice(&track.sample_entry);
+        });
+
+        let durations = run_lengths(track.durations.iter().copied());
+        mp4_full_box(b, b"stts", 0, 0, |b| {
+            put(b, (durations.len() as u32).to_be_bytes());
+            for (count, duration) in durations {
+                put(b, count.to_be_bytes());
+                put(b, duration.to_be_bytes());
+            }
+        });
+
+        if samples.iter().any(|sample| sample.composition_offset != 0) {
+            let offsets = run_lengths(samples.iter().map(|sample| sample.composition_offset));
+            mp4_full_box(b, b"ctts", 0, 0, |b| {
+                put(b, (offsets.len() as u32).to_be_bytes());
+                for (count, offset) in offsets {
+                    put(b, count.to_be_bytes());
+                    put(b, offset.to_be_bytes());
+                }
+            });
+        }
+
+        if !samples.iter().all(|sample| sample.keyframe) {
+            let keyframes: Vec<u32> = samples
+                .iter()
+                .enumerate()
+                .filter(|(_, sample)| sample.keyframe)
+                .map(|(index, _)| index as u32 + 1)
+                .collect();
+            mp4_full_box(b, b"stss", 0, 0, |b| {
+                put(b, (keyframes.len() as u32).to_be_bytes());
+                for number in keyframes {
+                    put(b, number.to_be_bytes());
+                }
+            });
+        }
+
+        // 每个样本单独作为一个块，块偏移即样本偏移
+        mp4_full_box(b, b"stsc", 0, 0, |b| {
+            put(b, 1u32.to_be_bytes());
+            put(b, 1u32.to_be_bytes()); // first_chunk
+            put(b, 1u32.to_be_bytes()); // samples_per_chunk
+            put(b, 1u32.to_be_bytes()); // sample_description_index
+        });
+        mp4_full_box(b, b"stsz", 0, 0, |b| {
+            put(b, 0u32.to_be_bytes());
+            put(b, (samples.len() as u32).to_be_bytes());
+            for sample in samples {
+                put(b, sample.size.to_be_bytes());
+            }
+        });
+        if use_co64 {
+            mp4_full_box(b, b"co64", 0, 0, |b| {
+                put(b, (samples.len() as u32).to_be_bytes());
+                for sample in samples {
+                    put(b, (data_start + sample.offset).to_be_bytes());
+                }
+            });
+        } else {
+            mp4_full_box(b, b"stco", 0, 0, |b| {
+                put(b, (samples.len() as u32).to_be_bytes());
+                for sample in samples {
+                    put(b, ((data_start + sample.offset) as u32).to_be_bytes());
+                }
+            });
+        }
+    });
+}
+
+// 连续相同的值合并为 (次数, 值)
+fn run_lengths(values: impl Iterator<Item = u32>) -> Vec<(u32, u32)> {
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for value in values {
+        match runs.last_mut() {
+            Some((count, last)) if *last == value => *count += 1,
+            _ => runs.push((1, value)),
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 48kHz 下一个 AAC 帧正好是 1920 个 90kHz 时钟
+    const FRAME_TICKS: i64 = 1920;
+
+    fn encode_timestamp(prefix: u8, value: i64) -> [u8; 5] {
+        [
+            (prefix << 4) | (((value >> 30) & 0x07) as u8) << 1 | 1,
+            (value >> 22) as u8,
+            ((value >> 15) as u8) << 1 | 1,
+            (value >> 7) as u8,
+            (value as u8) << 1 | 1,
+        ]
+    }
+
+    fn pes_payload(pts: Option<i64>, dts: Option<i64>, data: &[u8]) -> Vec<u8> {
+        let mut header = Vec::new();
+        let mut flags = 0;
+        if let Some(pts) = pts {
+            flags = 0x80;
+            header.extend_from_slice(&encode_timestamp(if dts.is_some() { 3 } else { 2 }, pts));
+        }
+        if let Some(dts) = dts {
+            flags = 0xC0;
+            header.extend_from_slice(&encode_timestamp(1, dts));
+        }
+        let mut payload = vec![0, 0, 1, 0xE0, 0, 0, 0x80, flags, header.len() as u8];
+        payload.extend_from_slice(&header);
+        payload.extend_from_slice(data);
+        payload
+    }
+
+    // 不带 CRC 的 ADTS 帧，AAC LC
+    fn adts_frame(frequency_index: u8, channels: u8, body_length: usize) -> Vec<u8> {
+        let frame_length = 7 + body_length;
+        let mut frame = vec![
+            0xFF,
+            0xF1,
+            (1 << 6) | (frequency_index << 2) | (channels >> 2),
+            ((channels & 0x03) << 6) | (frame_length >> 11) as u8,
+            (frame_length >> 3) as u8,
+            ((frame_length & 0x07) as u8) << 5 | 0x1F,
+            0xFC,
+        ];
+        frame.resize(frame_length, 0xAB);
+        frame
+    }
+
+    fn audio_pes(pts: Option<i64>, data: Vec<u8>) -> Pes {
+        Pes {
+            pid: 0x101,
+            pts,
+            dts: pts,
+            data,
+        }
+    }
+
+    fn with_muxer(name: &str, test: impl FnOnce(&mut Muxer)) {
+        let path = std::env::temp_dir().join(format!("remux_{}_{}.mdat", name, std::process::id()));
+        let mut muxer = Muxer::new(&path).unwrap();
+        test(&mut muxer);
+        drop(muxer);
+        let _ = fs::remove_file(&path);
+    }
+
+    fn audio_dts(muxer: &Muxer) -> Vec<i64> {
+        muxer
+            .audio
+            .samples
+            .iter()
+            .map(|sample| sample.dts)
+            .collect()
+    }
+
+    #[test]
+    fn pes_header_reads_pts_and_dts() {
+        let pts = 0x1_2345_6789;
+        let payload = pes_payload(Some(pts), Some(pts - 3000), &[1, 2, 3]);
+        let pes = parse_pes_header(0x100, &payload).unwrap();
+        assert_eq!(pes.pts, Some(pts));
+        assert_eq!(pes.dts, Some(pts - 3000));
+        assert_eq!(pes.data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn pes_header_uses_pts_when_dts_is_absent() {
+        let pes = parse_pes_header(0x100, &pes_payload(Some(90000), None, &[])).unwrap();
+        assert_eq!(pes.dts, Some(90000));
+
+        let pes = parse_pes_header(0x100, &pes_payload(None, None, &[7])).unwrap();
+        assert_eq!((pes.pts, pes.dts), (None, None));
+    }
+
+    #[test]
+    fn pes_header_requires_start_code() {
+        let mut payload = pes_payload(Some(0), None, &[]);
+        payload[2] = 2;
+        assert!(parse_pes_header(0x100, &payload).is_none());
+        assert!(parse_pes_header(0x100, &[0, 0, 1]).is_none());
+    }
+
+    #[test]
+    fn timestamp_delta_handles_wrap() {
+        assert_eq!(timestamp_delta(5, TIMESTAMP_WRAP - 5), 10);
+        assert_eq!(timestamp_delta(TIMESTAMP_WRAP - 5, 5), -10);
+        assert_eq!(timestamp_delta(3000, 0), 3000);
+    }
+
+    #[test]
+    fn adts_header_is_parsed() {
+        let frame = adts_frame(4, 2, 100);
+        let (config, header_length, frame_length) = parse_adts_header(&frame).unwrap();
+        assert_eq!(config.object_type, 2);
+        assert_eq!(config.sample_rate(), 44100);
+        assert_eq!(config.channels, 2);
+        assert_eq!((header_length, frame_length), (7, 107));
+        assert_eq!(config.to_bytes(), [0x12, 0x10]);
+
+        // protection_absent 为 0 时帧头带 2 字节 CRC
+        let mut with_crc = frame.clone();
+        with_crc[1] = 0xF0;
+        assert_eq!(parse_adts_header(&with_crc).unwrap().1, 9);
+    }
+
+    #[test]
+    fn adts_header_rejects_invalid_data() {
+        let mut frame = adts_frame(4, 2, 100);
+        frame[0] = 0xFE;
+        assert!(parse_adts_header(&frame).is_none());
+        assert!(parse_adts_header(&adts_frame(4, 2, 0)).is_none());
+        assert!(parse_adts_header(&[0xFF, 0xF1, 0x50]).is_none());
+    }
+
+    #[test]
+    fn timeline_starts_at_zero_and_handles_wrap() {
+        let mut timeline = Timeline::default();
+        assert_eq!(timeline.map(TIMESTAMP_WRAP - 3000), (0, true));
+        timeline.extend(3000);
+        assert_eq!(timeline.map(0), (3000, false));
+    }
+
+    #[test]
+    fn timeline_shares_anchor_between_tracks() {
+        let mut timeline = Timeline::default();
+        // 视频先出现，音频按同一锚点换算，相对位置不变
+        assert_eq!(timeline.map(900_000), (0, true));
+        timeline.extend(3000);
+        assert_eq!(timeline.map(900_000 + 1800), (1800, false));
+        assert_eq!(timeline.map(900_000 - 600), (-600, false));
+    }
+
+    #[test]
+    fn timeline_reanchors_after_timestamp_jump() {
+        let mut timeline = Timeline::default();
+        timeline.map(0);
+        timeline.extend(6000);
+        // 时间戳跳变超过阈值，接在已写出的最晚结束时间之后
+        let jumped = 3000 + 20 * i64::from(MPEG_TIMESCALE);
+        assert_eq!(timeline.map(jumped), (6000, true));
+        // 另一条轨道随后的时间戳沿用新锚点
+        assert_eq!(timeline.map(jumped + 1000), (7000, false));
+    }
+
+    #[test]
+    fn timeline_reanchors_at_marked_discontinuity() {
+        let mut timeline = Timeline::default();
+        timeline.map(90_000);
+        timeline.extend(6000);
+        // 未标记时小幅倒退仍按原锚点换算
+        assert_eq!(timeline.map(88_000), (-2000, false));
+
+        timeline.discontinuity = true;
+        assert_eq!(timeline.map(88_000), (6000, true));
+        assert!(!timeline.discontinuity);
+        assert_eq!(timeline.map(88_000 + 1920), (7920, false));
+    }
+
+    #[test]
+    fn audio_frame_dts_follows_pts_and_absorbs_jitter() {
+        assert_eq!(audio_frame_dts(Some(500), None), 500);
+        assert_eq!(audio_frame_dts(Some(1030), Some(1024)), 1024);
+        assert_eq!(audio_frame_dts(Some(900), Some(1024)), 1024);
+        assert_eq!(audio_frame_dts(Some(48_000), Some(1024)), 48_000);
+        assert_eq!(audio_frame_dts(None, Some(1024)), 1024);
+        assert_eq!(audio_frame_dts(None, None), 0);
+    }
+
+    #[test]
+    fn rescale_converts_between_timescales() {
+        assert_eq!(rescale(FRAME_TICKS, MPEG_TIMESCALE, 48000), 1024);
+        assert_eq!(rescale(1024, 48000, MPEG_TIMESCALE), FRAME_TICKS);
+        assert_eq!(rescale(90_000, MPEG_TIMESCALE, 44100), 44100);
+    }
+
+    #[test]
+    fn audio_dts_comes_from_pes_pts() {
+        with_muxer("audio_pts", |muxer| {
+            let two_frames = [adts_frame(3, 2, 50), adts_frame(3, 2, 60)].concat();
+            let start = 1_000_000;
+            muxer
+                .push_audio(audio_pes(Some(start), two_frames.clone()))
+                .unwrap();
+            // 时间戳有 1 个时钟的抖动，仍按连续排列
+            muxer
+                .push_audio(audio_pes(
+                    Some(start + 2 * FRAME_TICKS + 1),
+                    two_frames.clone(),
+                ))
+                .unwrap();
+            // 中间缺了一秒音频，保留空隙
+            muxer
+                .push_audio(audio_pes(
+                    Some(start + 90_000 + 4 * FRAME_TICKS),
+                    two_frames,
+                ))
+                .unwrap();
+            assert_eq!(
+                audio_dts(muxer),
+                vec![0, 1024, 2048, 3072, 48000 + 4096, 48000 + 5120]
+            );
+        });
+    }
+
+    #[test]
+    fn audio_frame_split_across_pes_keeps_order() {
+        with_muxer("audio_split", |muxer| {
+            let first = adts_frame(3, 2, 50);
+            let second = adts_frame(3, 2, 60);
+            let third = adts_frame(3, 2, 70);
+            let (head, tail) = second.split_at(20);
+
+            let start = 500_000;
+            muxer
+                .push_audio(audio_pes(Some(start), [first.as_slice(), head].concat()))
+                .unwrap();
+            // 第二个 PES 的时间戳属于其中第一个完整开始的帧
+            muxer
+                .push_audio(audio_pes(
+                    Some(start + 2 * FRAME_TICKS),
+                    [tail, third.as_slice()].concat(),
+                ))
+                .unwrap();
+            assert_eq!(audio_dts(muxer), vec![0, 1024, 2048]);
+            assert_eq!(muxer.audio.samples[1].size, 60);
+        });
+    }
+
+    #[test]
+    fn discontinuity_reanchors_audio_after_written_end() {
+        with_muxer("audio_discontinuity", |muxer| {
+            let frame = adts_frame(3, 2, 50);
+            muxer
+                .push_audio(audio_pes(Some(2_000_000), frame.clone()))
+                .unwrap();
+            muxer.mark_discontinuity();
+            // 插播片段的时间戳从头开始
+            muxer.push_audio(audio_pes(Some(0), frame)).unwrap();
+            assert_eq!(audio_dts(muxer), vec![0, 1024]);
+            assert_eq!(muxer.timeline.end, 2 * FRAME_TICKS);
+        });
+    }
+
+    #[test]
+    fn sample_durations_follow_dts() {
+        let sample = |dts| Sample {
+            offset: 0,
+            size: 0,
+            dts,
+            composition_offset: 0,
+            keyframe: true,
+        };
+        let samples = [sample(0), sample(1024), sample(4096)];
+        assert_eq!(sample_durations(&samples, 1024), vec![1024, 3072, 3072]);
+        assert_eq!(sample_durations(&samples[..1], 1024), vec![1024]);
+    }
+}
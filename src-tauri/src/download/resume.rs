@@ -0,0 +1,262 @@
+use crate::download::http_client::HttpClient;
+use crate::download::manifest::strip_query;
+use crate::download::types::METADATA_SCHEMA_VERSION;
+use reqwest::header::{HeaderMap, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::fs;
+
+// 续传信息文件名，位于章节临时目录中
+const RESUME_FILE: &str = "resume.json";
+
+/// 直链视频的续传信息，用于确认服务器上的文件没有变化
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ResumeState {
+    pub schema_version: u32,
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub total_size: Option<u64>,
+}
+
+/// 下载请求的结果
+pub struct DirectDownload {
+    pub response: Option<Response>, // 为 None 时本地文件已经完整
+    pub resume_from: u64,           // 响应内容在文件中的起始位置，0 表示重新下载
+    pub total_size: Option<u64>,    // 文件总大小（未知时为 None）
+}
+
+impl ResumeState {
+    /// 从完整下载的响应头中记录校验信息
+    pub fn from_response(url: &str, response: &Response) -> Self {
+        let headers = response.headers();
+        ResumeState {
+            schema_version: METADATA_SCHEMA_VERSION,
+            url: url.to_string(),
+            etag: header_string(headers, ETAG),
+            last_modified: header_string(headers, LAST_MODIFIED),
+            total_size: response.content_length(),
+        }
+    }
+
+    /// 读取与当前地址对应的续传信息
+    pub async fn load(temp_dir: &Path, url: &str) -> Option<Self> {
+        let content = fs::read_to_string(temp_dir.join(RESUME_FILE)).await.ok()?;
+        serde_json::from_str::<ResumeState>(&content)
+            .ok()
+            .filter(|state| strip_query(&state.url) == strip_query(url))
+    }
+
+    /// 保存续传信息，服务器没有提供 ETag 或 Last-Modified 时无法校验，不保存
+    pub async fn save(&self, temp_dir: &Path) -> Result<(), String> {
+        if self.etag.is_none() && self.last_modified.is_none() {
+            Self::remove(temp_dir).await;
+            return Ok(());
+        }
+
+        let content =
+            serde_json::to_string(self).map_err(|e| format!("序列化续传信息失败: {}", e))?;
+        fs::create_dir_all(temp_dir)
+            .await
+            .map_err(|e| format!("创建临时目录失败: {}", e))?;
+        fs::write(temp_dir.join(RESUME_FILE), content)
+            .await
+            .map_err(|e| format!("写入续传信息失败: {}", e))
+    }
+
+    /// 删除续传信息，临时目录为空时一并删除
+    pub async fn remove(temp_dir: &Path) {
+        let _ = fs::remove_file(temp_dir.join(RESUME_FILE)).await;
+        let _ = fs::remove_dir(temp_dir).await;
+    }
+
+    fn if_range_value(&self) -> Option<&str> {
+        if_range_value(self.etag.as_deref(), self.last_modified.as_deref())
+    }
+
+    // 校验 206 响应确实是同一个文件从指定位置开始的剩余部分
+    fn matches(&self, response: &Response, start: u64) -> bool {
+        let headers = response.headers();
+        let Some((range_start, total)) =
+            header_string(headers, CONTENT_RANGE).and_then(|value| parse_content_range(&value))
+        else {
+            return false;
+        };
+        if range_start != start {
+            return false;
+        }
+        if let (Some(expected), Some(total)) = (self.total_size, total) {
+            if expected != total {
+                return false;
+            }
+        }
+        if let (Some(expected), Some(actual)) = (&self.etag, header_string(headers, ETAG)) {
+            if expected.trim_start_matches("W/") != actual.trim_start_matches("W/") {
+                return false;
+            }
+        }
+        if let (Some(expected), Some(actual)) =
+            (&self.last_modified, header_string(headers, LAST_MODIFIED))
+        {
+            if *expected != actual {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// 发起直链下载请求
+///
+/// 本地已有部分文件且续传信息有效时用 Range 请求剩余部分；服务器不支持范围请求、
+/// 文件已变化或校验失败时从头下载。
+pub async fn request_download(
+    client: &HttpClient,
+    url: &str,
+    temp_dir: &Path,
+    partial_size: u64,
+) -> Result<DirectDownload, String> {
+    let state = match partial_size {
+        0 => None,
+        _ => ResumeState::load(temp_dir, url).await,
+    };
+
+    if let Some(state) = state {
+        let mut request = client
+            .get(url)
+            .header(RANGE, format!("bytes={}-", partial_size));
+        if let Some(validator) = state.if_range_value() {
+            request = request.header(IF_RANGE, validator);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("请求失败: {}", e))?;
+
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT if state.matches(&response, partial_size) => {
+                eprintln!("从 {} 字节处继续下载: {}", partial_size, url);
+                return Ok(DirectDownload {
+                    response: Some(response),
+                    resume_from: partial_size,
+                    total_size: state.total_size,
+                });
+            }
+            StatusCode::PARTIAL_CONTENT => eprintln!("续传校验失败，重新下载: {}", url),
+            // 请求的起始位置等于文件大小，说明上次已经下载完整
+            StatusCode::RANGE_NOT_SATISFIABLE => {
+                let total = header_string(response.headers(), CONTENT_RANGE)
+                    .and_then(|value| parse_content_range(&value))
+                    .and_then(|(_, total)| total);
+                if total == Some(partial_size) && state.total_size == Some(partial_size) {
+                    return Ok(DirectDownload {
+                        response: None,
+                        resume_from: partial_size,
+                        total_size: Some(partial_size),
+                    });
+                }
+                eprintln!("续传位置无效，重新下载: {}", url);
+            }
+            // 服务器忽略 Range 或 If-Range 校验失败时直接返回完整内容
+            status if status.is_success() => {
+                eprintln!("服务器不支持续传或文件已变化，重新下载: {}", url);
+                return Ok(DirectDownload {
+                    total_size: response.content_length(),
+                    response: Some(response),
+                    resume_from: 0,
+                });
+            }
+            status => eprintln!("续传请求失败（HTTP {}），重新下载: {}", status, url),
+        }
+    }
+
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("请求失败: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP状态错误: {}", response.status()));
+    }
+    Ok(DirectDownload {
+        total_size: response.content_length(),
+        response: Some(response),
+        resume_from: 0,
+    })
+}
+
+// 解析 Content-Range，"bytes 100-199/1000" 返回 (100, Some(1000))，"bytes */1000" 的起始位置为 0
+pub(crate) fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    let start = match range.trim() {
+        "*" => 0,
+        range => range.split_once('-')?.0.trim().parse().ok()?,
+    };
+    Some((start, total))
+}
+
+// If-Range 只能使用强 ETag，否则使用 Last-Modified
+pub(crate) fn if_range_value<'a>(
+    etag: Option<&'a str>,
+    last_modified: Option<&'a str>,
+) -> Option<&'a str> {
+    etag.filter(|etag| !etag.starts_with("W/"))
+        .or(last_modified)
+}
+
+pub(crate) fn header_string(
+    headers: &HeaderMap,
+    name: reqwest::header::HeaderName,
+) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_content_range_reads_start_and_total() {
+        assert_eq!(
+            parse_content_range("bytes 100-199/1000"),
+            Some((100, Some(1000)))
+        );
+        assert_eq!(parse_content_range(" bytes 0-0/1 "), Some((0, Some(1))));
+    }
+
+    #[test]
+    fn parse_content_range_handles_unknown_parts() {
+        assert_eq!(parse_content_range("bytes 100-199/*"), Some((100, None)));
+        assert_eq!(parse_content_range("bytes */1000"), Some((0, Some(1000))));
+    }
+
+    #[test]
+    fn parse_content_range_rejects_malformed_values() {
+        assert_eq!(parse_content_range("items 0-1/2"), None);
+        assert_eq!(parse_content_range("bytes 0-1"), None);
+        assert_eq!(parse_content_range("bytes abc-1/2"), None);
+        assert_eq!(parse_content_range("bytes 0-1/abc"), None);
+    }
+
+    #[test]
+    fn if_range_value_prefers_strong_etag() {
+        assert_eq!(
+            if_range_value(Some("\"abc\""), Some("Mon, 01 Jan 2024 00:00:00 GMT")),
+            Some("\"abc\"")
+        );
+        assert_eq!(
+            if_range_value(Some("W/\"abc\""), Some("Mon, 01 Jan 2024 00:00:00 GMT")),
+            Some("Mon, 01 Jan 2024 00:00:00 GMT")
+        );
+        assert_eq!(if_range_value(Some("W/\"abc\""), None), None);
+    }
+}
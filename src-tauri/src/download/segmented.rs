@@ -0,0 +1,276 @@
+use crate::download::hls::is_hls_content_type;
+use crate::download::http_client::HttpClient;
+use crate::download::manifest::strip_query;
+use crate::download::resume::{header_string, if_range_value, parse_content_range};
+use crate::download::types::METADATA_SCHEMA_VERSION;
+use reqwest::header::{CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+// 分段信息文件名，位于章节临时目录中
+const PARTS_FILE: &str = "parts.json";
+// 小于该大小的文件单连接下载，分段的收益抵不过额外的请求
+pub const MIN_SEGMENTED_SIZE: u64 = 16 * 1024 * 1024;
+const MIN_PART_SIZE: u64 = 4 * 1024 * 1024;
+
+/// 服务器对范围请求的支持情况
+pub struct RangeSupport {
+    pub total_size: u64,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub is_playlist: bool, // 地址实际返回的是 HLS 播放列表
+}
+
+/// 分段下载的状态，每个分段的进度以分段文件的大小为准
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct PartsState {
+    pub schema_version: u32,
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub total_size: u64,
+    pub parts: Vec<PartRange>,
+}
+
+/// 文件中的一个分段，end 为闭区间
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy)]
+#[serde(default)]
+pub struct PartRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl PartRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+/// 用 1 字节的范围请求探测服务器是否支持分段下载
+pub async fn probe_range_support(client: &HttpClient, url: &str) -> Option<RangeSupport> {
+    let response = client
+        .get(url)
+        .header(RANGE, "bytes=0-0")
+        .send()
+        .await
+        .ok()?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return None;
+    }
+
+    let headers = response.headers();
+    let (_, total_size) =
+        header_string(headers, CONTENT_RANGE).and_then(|value| parse_content_range(&value))?;
+    Some(RangeSupport {
+        total_size: total_size?,
+        etag: header_string(headers, ETAG),
+        last_modified: header_string(headers, LAST_MODIFIED),
+        is_playlist: header_string(headers, CONTENT_TYPE)
+            .is_some_and(|value| is_hls_content_type(&value)),
+    })
+}
+
+impl PartsState {
+    /// 按连接数平均划分文件，每段不小于 MIN_PART_SIZE
+    pub fn plan(url: &str, support: &RangeSupport, connections: usize) -> Self {
+        let total_size = support.total_size;
+        let count = (total_size / MIN_PART_SIZE).clamp(1, connections.max(1) as u64);
+        let part_size = total_size.div_ceil(count);
+
+        let parts = (0..count)
+            .map(|index| index * part_size)
+            .take_while(|start| *start < total_size)
+            .map(|start| PartRange {
+                start,
+                end: (start + part_size).min(total_size) - 1,
+            })
+            .collect();
+
+        PartsState {
+            schema_version: METADATA_SCHEMA_VERSION,
+            url: url.to_string(),
+            etag: support.etag.clone(),
+            last_modified: support.last_modified.clone(),
+            total_size,
+            parts,
+        }
+    }
+
+    /// 读取上次的分段状态，服务器上的文件变化或无法校验时返回 None
+    pub async fn load(temp_dir: &Path, url: &str, support: &RangeSupport) -> Option<Self> {
+        let content = fs::read_to_string(temp_dir.join(PARTS_FILE)).await.ok()?;
+        let state = serde_json::from_str::<PartsState>(&content).ok()?;
+
+        let has_validator = state.etag.is_some() || state.last_modified.is_some();
+        let unchanged = strip_query(&state.url) == strip_query(url)
+            && state.total_size == support.total_size
+            && state.etag == support.etag
+            && state.last_modified == support.last_modified;
+        let covers_file = state.parts.first().is_some_and(|part| part.start == 0)
+            && state.parts.last().map(|part| part.end + 1) == Some(state.total_size)
+            && state
+                .parts
+                .windows(2)
+                .all(|pair| pair[0].end + 1 == pair[1].start);
+
+        (has_validator && unchanged && covers_file).then_some(state)
+    }
+
+    pub async fn save(&self, temp_dir: &Path) -> Result<(), String> {
+        let content =
+            serde_json::to_string(self).map_err(|e| format!("序列化分段信息失败: {}", e))?;
+        fs::create_dir_all(temp_dir)
+            .await
+            .map_err(|e| format!("创建临时目录失败: {}", e))?;
+        fs::write(temp_dir.join(PARTS_FILE), content)
+            .await
+            .map_err(|e| format!("写入分段信息失败: {}", e))
+    }
+
+    /// 请求分段时使用的 If-Range 值
+    pub fn if_range_value(&self) -> Option<&str> {
+        if_range_value(self.etag.as_deref(), self.last_modified.as_deref())
+    }
+
+    /// 各分段已下载的大小，超出分段长度的文件视为损坏并清空
+    pub async fn downloaded_sizes(&self, temp_dir: &Path) -> Vec<u64> {
+        let mut sizes = Vec::with_capacity(self.parts.len());
+        for (index, part) in self.parts.iter().enumerate() {
+            let path = part_file_path(temp_dir, index);
+            let size = fs::metadata(&path)
+                .await
+                .map(|metadata| metadata.len())
+                .unwrap_or(0);
+            if size > part.len() {
+                let _ = fs::remove_file(&path).await;
+                sizes.push(0);
+            } else {
+                sizes.push(size);
+            }
+        }
+        sizes
+    }
+
+    /// 删除分段文件和分段信息，临时目录为空时一并删除
+    pub async fn remove(temp_dir: &Path) {
+        if let Ok(mut entries) = fs::read_dir(temp_dir).await {
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let file_name = entry.file_name();
+                let file_name = file_name.to_string_lossy();
+                if file_name.starts_with("part_") && file_name.ends_with(".bin") {
+                    let _ = fs::remove_file(entry.path()).await;
+                }
+            }
+        }
+        let _ = fs::remove_file(temp_dir.join(PARTS_FILE)).await;
+        let _ = fs::remove_dir(temp_dir).await;
+    }
+}
+
+/// 分段在临时目录中的文件路径
+pub fn part_file_path(temp_dir: &Path, index: usize) -> PathBuf {
+    temp_dir.join(format!("part_{:02}.bin", index))
+}
+
+/// 请求分段中从 start 开始的剩余部分，并确认服务器返回的正是这一范围
+pub async fn request_part(
+    client: &HttpClient,
+    url: &str,
+    start: u64,
+    end: u64,
+    if_range: Option<&str>,
+) -> Result<Response, String> {
+    let mut request = client
+        .get(url)
+        .header(RANGE, format!("bytes={}-{}", start, end));
+    if let Some(validator) = if_range {
+        request = request.header(IF_RANGE, validator);
+    }
+
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("请求失败: {}", e))?;
+    match response.status() {
+        StatusCode::PARTIAL_CONTENT => {}
+        // If-Range 校验失败时服务器返回完整文件
+        status if status.is_success() => return Err("服务器上的文件已变化".to_string()),
+        status => return Err(format!("HTTP状态: {}", status)),
+    }
+
+    let range_start = header_string(response.headers(), CONTENT_RANGE)
+        .and_then(|value| parse_content_range(&value))
+        .map(|(range_start, _)| range_start);
+    if range_start != Some(start) {
+        return Err("服务器返回的范围与请求不一致".to_string());
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn support(total_size: u64) -> RangeSupport {
+        RangeSupport {
+            total_size,
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+            is_playlist: false,
+        }
+    }
+
+    fn assert_covers(state: &PartsState) {
+        assert_eq!(state.parts.first().map(|part| part.start), Some(0));
+        assert_eq!(
+            state.parts.last().map(|part| part.end + 1),
+            Some(state.total_size)
+        );
+        for pair in state.parts.windows(2) {
+            assert_eq!(pair[0].end + 1, pair[1].start);
+        }
+    }
+
+    #[test]
+    fn plan_uses_single_part_for_small_files() {
+        let state = PartsState::plan("https://example.com/a.mp4", &support(1000), 4);
+        assert_eq!(state.parts.len(), 1);
+        assert_eq!(state.parts[0].start, 0);
+        assert_eq!(state.parts[0].end, 999);
+        assert_eq!(state.etag.as_deref(), Some("\"abc\""));
+    }
+
+    #[test]
+    fn plan_limits_parts_by_min_part_size() {
+        let state = PartsState::plan("u", &support(MIN_PART_SIZE * 2), 8);
+        assert_eq!(state.parts.len(), 2);
+        assert!(state.parts.iter().all(|part| part.len() == MIN_PART_SIZE));
+        assert_covers(&state);
+    }
+
+    #[test]
+    fn plan_gives_uneven_remainder_to_last_part() {
+        let total_size = MIN_PART_SIZE * 3 + 5;
+        let state = PartsState::plan("u", &support(total_size), 3);
+        assert_eq!(state.parts.len(), 3);
+        assert_covers(&state);
+        let part_size = total_size.div_ceil(3);
+        assert_eq!(state.parts[0].len(), part_size);
+        assert_eq!(state.parts[1].len(), part_size);
+        assert_eq!(state.parts[2].len(), total_size - 2 * part_size);
+        assert_eq!(
+            state.parts.iter().map(PartRange::len).sum::<u64>(),
+            total_size
+        );
+    }
+
+    #[test]
+    fn plan_treats_zero_connections_as_one() {
+        let state = PartsState::plan("u", &support(MIN_PART_SIZE * 4), 0);
+        assert_eq!(state.parts.len(), 1);
+        assert_covers(&state);
+    }
+}
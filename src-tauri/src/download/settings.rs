@@ -0,0 +1,143 @@
+use crate::download::ffmpeg::{clear_ffmpeg_status, validate_ffmpeg_path};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+use tokio::fs;
+
+// HLS 分片默认并发数及上限，并发过高容易被服务器限流
+const DEFAULT_SEGMENT_CONCURRENCY: usize = 4;
+const MAX_SEGMENT_CONCURRENCY: usize = 16;
+// 直链视频分段下载的默认连接数及上限，1 表示单连接下载
+const DEFAULT_DIRECT_CONNECTIONS: usize = 4;
+const MAX_DIRECT_CONNECTIONS: usize = 16;
+
+/// 下载设置，缺失的字段使用默认值
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DownloadSettings {
+    pub video_quality: VideoQualityPreference,
+    pub segment_concurrency: usize,  // HLS 分片并发下载数
+    pub direct_connections: usize,   // 直链视频分段下载的连接数
+    pub local_playlist: bool,        // 下载 HLS 时生成本地播放列表，可以边下边播
+    pub use_ffmpeg: bool,            // 检测到 ffmpeg 时用它合并/转封装视频
+    pub ffmpeg_path: Option<String>, // ffmpeg 可执行文件路径，为空时从 PATH 中查找
+}
+
+impl Default for DownloadSettings {
+    fn default() -> Self {
+        DownloadSettings {
+            video_quality: VideoQualityPreference::default(),
+            segment_concurrency: DEFAULT_SEGMENT_CONCURRENCY,
+            direct_connections: DEFAULT_DIRECT_CONNECTIONS,
+            local_playlist: true,
+            use_ffmpeg: true,
+            ffmpeg_path: None,
+        }
+    }
+}
+
+/// HLS 多码率视频的清晰度偏好
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct VideoQualityPreference {
+    pub strategy: String,           // "highest" 或 "lowest"
+    pub max_height: Option<u32>,    // 分辨率上限（高度，如 1080）
+    pub max_bandwidth: Option<u64>, // 带宽上限（bps）
+}
+
+impl Default for VideoQualityPreference {
+    fn default() -> Self {
+        VideoQualityPreference {
+            strategy: "highest".to_string(),
+            max_height: None,
+            max_bandwidth: None,
+        }
+    }
+}
+
+/// 获取下载设置
+#[tauri::command]
+pub async fn get_download_settings(app_handle: AppHandle) -> Result<DownloadSettings, String> {
+    load_download_settings(&app_handle).await
+}
+
+/// 保存下载设置
+#[tauri::command]
+pub async fn set_download_settings(
+    app_handle: AppHandle,
+    settings: DownloadSettings,
+) -> Result<(), String> {
+    validate_download_settings(&settings)?;
+
+    let settings_path = get_download_settings_path(&app_handle)?;
+    if let Some(parent) = settings_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("创建配置目录失败: {}", e))?;
+    }
+
+    let content = serde_json::to_string_pretty(&settings)
+        .map_err(|e| format!("序列化下载设置失败: {}", e))?;
+    fs::write(&settings_path, content)
+        .await
+        .map_err(|e| format!("写入下载设置失败: {}", e))?;
+
+    // ffmpeg 路径可能已变化，清除旧的检测结果，不在保存设置时运行新路径
+    clear_ffmpeg_status().await;
+    Ok(())
+}
+
+/// 读取下载设置，不存在或无效时使用默认值
+pub async fn load_download_settings(app_handle: &AppHandle) -> Result<DownloadSettings, String> {
+    let settings_path = get_download_settings_path(app_handle)?;
+
+    let settings = match fs::read_to_string(&settings_path).await {
+        Ok(content) => serde_json::from_str::<DownloadSettings>(&content).ok(),
+        Err(_) => None,
+    };
+
+    let settings = settings.unwrap_or_default();
+    if let Err(e) = validate_download_settings(&settings) {
+        eprintln!("下载设置无效，使用默认设置: {}", e);
+        return Ok(DownloadSettings::default());
+    }
+
+    Ok(settings)
+}
+
+fn validate_download_settings(settings: &DownloadSettings) -> Result<(), String> {
+    let quality = &settings.video_quality;
+    if !matches!(quality.strategy.as_str(), "highest" | "lowest") {
+        return Err(format!("未知的清晰度策略: {}", quality.strategy));
+    }
+    if quality.max_height == Some(0) || quality.max_bandwidth == Some(0) {
+        return Err("清晰度上限必须大于0".to_string());
+    }
+    if !(1..=MAX_SEGMENT_CONCURRENCY).contains(&settings.segment_concurrency) {
+        return Err(format!(
+            "分片并发数应在 1 到 {} 之间",
+            MAX_SEGMENT_CONCURRENCY
+        ));
+    }
+    if !(1..=MAX_DIRECT_CONNECTIONS).contains(&settings.direct_connections) {
+        return Err(format!(
+            "直链下载连接数应在 1 到 {} 之间",
+            MAX_DIRECT_CONNECTIONS
+        ));
+    }
+    if let Some(path) = settings.ffmpeg_path.as_deref() {
+        if !path.trim().is_empty() {
+            validate_ffmpeg_path(path)?;
+        }
+    }
+    Ok(())
+}
+
+fn get_download_settings_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("无法获取应用数据目录: {}", e))?;
+
+    Ok(app_data_dir.join("config").join("download_settings.json"))
+}
@@ -1,17 +1,23 @@
+use crate::download::types::{VideoLine, METADATA_SCHEMA_VERSION};
+use crate::download::utils::get_downloads_path;
+use crate::path_guard::SafeSegment;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::path::PathBuf;
-use tauri::{AppHandle, Manager};
+use tauri::AppHandle;
 use tokio::fs;
 
 /// 下载任务信息
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)] // 缺失的字段使用默认值，兼容旧版本文件
 pub struct DownloadTask {
+    pub schema_version: u32,
     pub cartoon_uuid: String,
     pub cartoon_name: String,
     pub chapter_uuid: String,
     pub chapter_name: String,
     pub video_url: String,
+    pub video_lines: Vec<VideoLine>, // 候选线路，恢复任务时一并传回下载命令
     pub cover: String,
     pub cartoon_detail: Option<Value>,
     pub status: String, // "downloading", "paused", "completed", "error"
@@ -22,12 +28,9 @@ pub struct DownloadTask {
 
 /// 获取任务存储路径
 async fn get_tasks_storage_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
-    let resource_dir = app_handle
-        .path()
-        .resource_dir()
-        .map_err(|e| format!("获取资源目录失败: {}", e))?;
+    let downloads_path = get_downloads_path(app_handle).await?;
 
-    let tasks_dir = resource_dir.join("downloads").join("tasks");
+    let tasks_dir = downloads_path.join("tasks");
 
     // 确保目录存在
     if !tasks_dir.exists() {
@@ -40,7 +43,7 @@ async fn get_tasks_storage_path(app_handle: &AppHandle) -> Result<PathBuf, Strin
 }
 
 /// 读取所有任务
-async fn read_all_tasks(app_handle: &AppHandle) -> Result<Vec<DownloadTask>, String> {
+pub(crate) async fn read_all_tasks(app_handle: &AppHandle) -> Result<Vec<DownloadTask>, String> {
     let tasks_file = get_tasks_storage_path(app_handle).await?;
 
     if !tasks_file.exists() {
@@ -62,7 +65,10 @@ async fn read_all_tasks(app_handle: &AppHandle) -> Result<Vec<DownloadTask>, Str
 }
 
 /// 保存所有任务
-async fn save_all_tasks(app_handle: &AppHandle, tasks: &[DownloadTask]) -> Result<(), String> {
+pub(crate) async fn save_all_tasks(
+    app_handle: &AppHandle,
+    tasks: &[DownloadTask],
+) -> Result<(), String> {
     let tasks_file = get_tasks_storage_path(app_handle).await?;
 
     let content =
@@ -94,11 +100,12 @@ pub async fn get_active_download_tasks(app_handle: AppHandle) -> Result<Vec<Down
 #[tauri::command]
 pub async fn save_download_task(
     app_handle: AppHandle,
-    cartoon_uuid: String,
+    cartoon_uuid: SafeSegment,
     cartoon_name: String,
-    chapter_uuid: String,
+    chapter_uuid: SafeSegment,
     chapter_name: String,
     video_url: String,
+    video_lines: Option<Vec<VideoLine>>,
     cover: String,
     cartoon_detail: Value,
     status: String,
@@ -122,11 +129,13 @@ pub async fn save_download_task(
     } else {
         // 创建新任务
         let new_task = DownloadTask {
-            cartoon_uuid,
+            schema_version: METADATA_SCHEMA_VERSION,
+            cartoon_uuid: cartoon_uuid.into(),
             cartoon_name,
-            chapter_uuid,
+            chapter_uuid: chapter_uuid.into(),
             chapter_name,
             video_url,
+            video_lines: video_lines.unwrap_or_default(),
             cover,
             cartoon_detail: Some(cartoon_detail),
             status,
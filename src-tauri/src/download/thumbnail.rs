@@ -0,0 +1,688 @@
+use crate::download::ffmpeg::{detected_ffmpeg_path, extract_keyframe_with_ffmpeg, part_path_of};
+use crate::download::naming::join_relative;
+use crate::download::probe::{
+    child_boxes, find_box, is_mp4_box_type, read_moov, read_range, read_u16, read_u32, read_u64,
+};
+use crate::download::remux::{
+    parse_pes_header, psi_section, split_nal_units, STREAM_TYPE_H264, STREAM_TYPE_H265,
+    TS_PACKET_SIZE, TS_SYNC_BYTE,
+};
+use crate::download::types::MediaInfo;
+use jpeg_encoder::{ColorType, Encoder};
+use openh264::decoder::Decoder;
+use openh264::formats::YUVSource;
+use std::fs::File;
+use std::path::Path;
+
+// 在时长的 10% 处取画面，避开片头黑屏和标题
+const THUMBNAIL_POSITION: f64 = 0.1;
+const THUMBNAIL_WIDTH: u32 = 320;
+const THUMBNAIL_EXTENSION: &str = "thumb.jpg";
+const THUMBNAIL_QUALITY: u8 = 80;
+// TS 文件从目标位置起最多读取的数据量，超过仍未找到关键帧时放弃
+const TS_SCAN_LIMIT: u64 = 32 * 1024 * 1024;
+const TS_READ_CHUNK: u64 = TS_PACKET_SIZE as u64 * 5000;
+// 单帧超过该大小视为文件损坏
+const MAX_SAMPLE_SIZE: u64 = 64 * 1024 * 1024;
+// 亮度标准差低于该值的画面视为纯色：多半是解码出错后的填充画面，也不适合作为缩略图
+const MIN_LUMA_DEVIATION: f64 = 2.0;
+
+/// 为已下载的视频生成缩略图，保存在视频旁边
+///
+/// 优先用 OpenH264 在 CPU 上解码关键帧；H.265 等不支持的编码、解码失败或画面未通过检查时，
+/// 在 ffmpeg 可用时改用 ffmpeg 截取。返回相对章节目录的缩略图文件名，失败时返回 None，
+/// 不影响下载结果。
+pub async fn generate_thumbnail(
+    chapter_path: &Path,
+    video_file: &str,
+    media: Option<&MediaInfo>,
+) -> Option<String> {
+    // 只有音频的文件没有画面可截取
+    if media.is_some_and(|media| media.video_codec.is_none()) {
+        return None;
+    }
+
+    let thumbnail_file = thumbnail_file_name(video_file);
+    let input = join_relative(chapter_path, video_file);
+    let output = join_relative(chapter_path, &thumbnail_file);
+    let expected_size = media
+        .map(|media| (media.width as usize, media.height as usize))
+        .filter(|&(width, height)| width > 0 && height > 0);
+    let result = {
+        let (input, output) = (input.clone(), output.clone());
+        tokio::task::spawn_blocking(move || write_thumbnail(&input, &output, expected_size))
+            .await
+            .map_err(|e| format!("生成缩略图任务异常退出: {}", e))
+            .and_then(|result| result)
+    };
+    let error = match result {
+        Ok(()) => return Some(thumbnail_file),
+        Err(e) => e,
+    };
+
+    let Some(ffmpeg) = detected_ffmpeg_path().await else {
+        eprintln!("生成缩略图失败: {}", error);
+        return None;
+    };
+    eprintln!("内置解码器无法生成缩略图（{}），改用 ffmpeg", error);
+    let seek_secs = media.map_or(0.0, |media| media.duration_secs * THUMBNAIL_POSITION);
+    match extract_keyframe_with_ffmpeg(&ffmpeg, &input, seek_secs, THUMBNAIL_WIDTH, &output).await {
+        Ok(()) => Some(thumbnail_file),
+        Err(e) => {
+            eprintln!("生成缩略图失败: {}", e);
+            None
+        }
+    }
+}
+
+/// 视频对应的缩略图文件名，如 "第01集.mp4" 对应 "第01集.thumb.jpg"
+pub fn thumbnail_file_name(video_file: &str) -> String {
+    Path::new(video_file)
+        .with_extension(THUMBNAIL_EXTENSION)
+        .to_string_lossy()
+        .to_string()
+}
+
+// 解码时长 10% 处的关键帧，检查后缩小保存为 JPEG；先写入 .part 文件，完成后再改名
+fn write_thumbnail(
+    input: &Path,
+    output: &Path,
+    expected_size: Option<(usize, usize)>,
+) -> Result<(), String> {
+    let keyframe = read_keyframe(input, THUMBNAIL_POSITION)?;
+    let frame = decode_keyframe(&keyframe)?;
+    check_frame(&frame, expected_size)?;
+    let (width, height, rgb) = scale_to_rgb(&frame, THUMBNAIL_WIDTH as usize);
+
+    let mut jpeg = Vec::new();
+    Encoder::new(&mut jpeg, THUMBNAIL_QUALITY)
+        .encode(&rgb, width as u16, height as u16, ColorType::Rgb)
+        .map_err(|e| format!("编码缩略图失败: {}", e))?;
+
+    let part_path = part_path_of(output);
+    std::fs::write(&part_path, jpeg)
+        .and_then(|_| std::fs::rename(&part_path, output))
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&part_path);
+            format!("保存缩略图失败: {}", e)
+        })
+}
+
+// 解码得到的一帧画面，YUV 4:2:0，色度平面的宽高为亮度的一半（向上取整）
+struct Frame {
+    width: usize,
+    height: usize,
+    luma: Vec<u8>,
+    cb: Vec<u8>,
+    cr: Vec<u8>,
+}
+
+impl Frame {
+    fn chroma_width(&self) -> usize {
+        self.width.div_ceil(2)
+    }
+}
+
+// 用 OpenH264 在 CPU 上解码关键帧，参数集和条带按 Annex B 格式一次送入
+fn decode_keyframe(keyframe: &Keyframe) -> Result<Frame, String> {
+    let mut stream = Vec::new();
+    for nal in keyframe.parameter_sets.iter().chain(&keyframe.nal_units) {
+        stream.extend_from_slice(&[0, 0, 0, 1]);
+        stream.extend_from_slice(nal);
+    }
+
+    let mut decoder = Decoder::new().map_err(|e| format!("初始化 H.264 解码器失败: {}", e))?;
+    let yuv = decoder
+        .decode(&stream)
+        .map_err(|e| format!("解码关键帧失败: {}", e))?
+        .ok_or("解码器没有输出画面")?;
+
+    let (width, height) = yuv.dimensions();
+    let (luma_stride, cb_stride, cr_stride) = yuv.strides();
+    let chroma_width = width.div_ceil(2);
+    let copy_plane = |plane: &[u8], stride: usize, width: usize, height: usize| {
+        (0..height)
+            .map(|row| plane.get(row * stride..row * stride + width))
+            .collect::<Option<Vec<_>>>()
+            .map(|rows| rows.concat())
+            .ok_or("解码器输出的画面数据不完整")
+    };
+    Ok(Frame {
+        width,
+        height,
+        luma: copy_plane(yuv.y(), luma_stride, width, height)?,
+        cb: copy_plane(yuv.u(), cb_stride, chroma_width, height.div_ceil(2))?,
+        cr: copy_plane(yuv.v(), cr_stride, chroma_width, height.div_ceil(2))?,
+    })
+}
+
+// 写入前检查画面：尺寸与探测到的视频一致，且不是纯色
+fn check_frame(frame: &Frame, expected_size: Option<(usize, usize)>) -> Result<(), String> {
+    if frame.width == 0 || frame.height == 0 {
+        return Err("解码得到的画面尺寸无效".to_string());
+    }
+    if let Some((width, height)) = expected_size {
+        if (frame.width, frame.height) != (width, height) {
+            return Err(format!(
+                "解码得到的画面尺寸 {}x{} 与视频 {}x{} 不一致",
+                frame.width, frame.height, width, height
+            ));
+        }
+    }
+
+    let count = frame.luma.len() as f64;
+    let mean = frame
+        .luma
+        .iter()
+        .map(|&value| f64::from(value))
+        .sum::<f64>()
+        / count;
+    let variance = frame
+        .luma
+        .iter()
+        .map(|&value| (f64::from(value) - mean).powi(2))
+        .sum::<f64>()
+        / count;
+    if variance.sqrt() < MIN_LUMA_DEVIATION {
+        return Err("解码得到的画面为纯色".to_string());
+    }
+    Ok(())
+}
+
+// 转换为 RGB 并按面积平均缩小到指定宽度（不放大），返回 (宽, 高, RGB 数据)
+fn scale_to_rgb(frame: &Frame, max_width: usize) -> (usize, usize, Vec<u8>) {
+    let width = frame.width.min(max_width);
+    let height = ((frame.height * width + frame.width / 2) / frame.width).max(1);
+    // 高清视频按 BT.709 转换，标清按 BT.601，均为有限范围
+    let (cr_to_r, cb_to_g, cr_to_g, cb_to_b) = if frame.height >= 720 {
+        (1.793, 0.213, 0.533, 2.112)
+    } else {
+        (1.596, 0.392, 0.813, 2.017)
+    };
+    let chroma_width = frame.chroma_width();
+    let chroma_height = frame.height.div_ceil(2);
+    // 目标像素对应的源区域 [start, end)
+    let span = |i: usize, target: usize, source: usize| {
+        let start = i * source / target;
+        (start, ((i + 1) * source / target).max(start + 1))
+    };
+    let average =
+        |plane: &[u8], stride: usize, (x0, x1): (usize, usize), (y0, y1): (usize, usize)| {
+            let sum: u64 = (y0..y1)
+                .flat_map(|y| &plane[y * stride + x0..y * stride + x1])
+                .map(|&value| u64::from(value))
+                .sum();
+            sum as f64 / ((x1 - x0) * (y1 - y0)) as f64
+        };
+
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        let rows = span(y, height, frame.height);
+        let chroma_rows = (rows.0 / 2, rows.1.div_ceil(2).min(chroma_height));
+        for x in 0..width {
+            let columns = span(x, width, frame.width);
+            let chroma_columns = (columns.0 / 2, columns.1.div_ceil(2).min(chroma_width));
+            let luma = 1.164 * (average(&frame.luma, frame.width, columns, rows) - 16.0);
+            let cb = average(&frame.cb, chroma_width, chroma_columns, chroma_rows) - 128.0;
+            let cr = average(&frame.cr, chroma_width, chroma_columns, chroma_rows) - 128.0;
+            for value in [
+                luma + cr_to_r * cr,
+                luma - cb_to_g * cb - cr_to_g * cr,
+                luma + cb_to_b * cb,
+            ] {
+                rgb.push(value.round().clamp(0.0, 255.0) as u8);
+            }
+        }
+    }
+    (width, height, rgb)
+}
+
+// 关键帧的 H.264 数据：容器中携带的参数集和该帧的 NAL 单元
+struct Keyframe {
+    parameter_sets: Vec<Vec<u8>>,
+    nal_units: Vec<Vec<u8>>,
+}
+
+// 读取视频中 position（时长或文件大小的比例）处之后的第一个关键帧
+fn read_keyframe(path: &Path, position: f64) -> Result<Keyframe, String> {
+    let read_error = |e: std::io::Error| format!("读取视频文件 {} 失败: {}", path.display(), e);
+    let mut file = File::open(path).map_err(read_error)?;
+    let file_size = file.metadata().map_err(read_error)?.len();
+
+    let head = read_range(&mut file, 0, (TS_PACKET_SIZE as u64 + 1).min(file_size))?;
+    if head.len() > TS_PACKET_SIZE
+        && head[0] == TS_SYNC_BYTE
+        && head[TS_PACKET_SIZE] == TS_SYNC_BYTE
+    {
+        read_ts_keyframe(&mut file, file_size, position)
+    } else if head.len() >= 8 && is_mp4_box_type(&head[4..8]) {
+        read_mp4_keyframe(&mut file, file_size, position)
+    } else {
+        Err(format!("无法识别的视频格式: {}", path.display()))
+    }
+}
+
+// ==================== MP4 ====================
+
+fn read_mp4_keyframe(file: &mut File, file_size: u64, position: f64) -> Result<Keyframe, String> {
+    let moov = read_moov(file, file_size)?;
+    let stbl = child_boxes(&moov)
+        .into_iter()
+        .filter(|(kind, _)| *kind == b"trak")
+        .filter_map(|(_, trak)| find_box(trak, b"mdia"))
+        .find(|mdia| find_box(mdia, b"hdlr").and_then(|hdlr| hdlr.get(8..12)) == Some(b"vide"))
+        .and_then(|mdia| find_box(find_box(mdia, b"minf")?, b"stbl"))
+        .ok_or("未找到视频轨道")?;
+
+    let stsd = find_box(stbl, b"stsd").ok_or("缺少视频编码信息")?;
+    let (kind, entry) = child_boxes(stsd.get(8..).unwrap_or_default())
+        .into_iter()
+        .next()
+        .ok_or("缺少视频编码信息")?;
+    match kind {
+        b"avc1" | b"avc3" => {}
+        b"hvc1" | b"hev1" => return Err("内置解码器不支持 H.265 视频".to_string()),
+        _ => {
+            return Err(format!(
+                "内置解码器不支持 {} 编码",
+                String::from_utf8_lossy(kind)
+            ))
+        }
+    }
+    // 视觉样本描述的固定字段共 78 字节，之后是 avcC 等子 box
+    let (length_size, parameter_sets) = entry
+        .get(78..)
+        .and_then(|boxes| find_box(boxes, b"avcC"))
+        .and_then(parse_avcc)
+        .ok_or("avcC 无效")?;
+
+    let target = find_box(stbl, b"stts")
+        .and_then(|stts| sample_at_position(stts, position))
+        .ok_or("stts 无效")?;
+    // 没有 stss 时所有帧都是关键帧
+    let sample = match find_box(stbl, b"stss") {
+        Some(stss) => {
+            let count = read_u32(stss, 4).unwrap_or(0) as usize;
+            let sync_samples: Vec<u64> = (0..count)
+                .map_while(|i| read_u32(stss, 8 + i * 4))
+                .map(|number| number.saturating_sub(1))
+                .collect();
+            sync_samples
+                .iter()
+                .copied()
+                .find(|&sample| sample >= target)
+                .or(sync_samples.last().copied())
+                .ok_or("视频中没有关键帧")?
+        }
+        None => target,
+    };
+
+    let (offset, size) = sample_location(stbl, sample).ok_or("样本表无效")?;
+    if size > MAX_SAMPLE_SIZE || offset + size > file_size {
+        return Err("关键帧数据无效".to_string());
+    }
+    let data = read_range(file, offset, size)?;
+    Ok(Keyframe {
+        parameter_sets,
+        nal_units: split_length_prefixed(&data, length_size),
+    })
+}
+
+// avcC 中 NAL 长度字段的字节数和 SPS/PPS
+fn parse_avcc(avcc: &[u8]) -> Option<(usize, Vec<Vec<u8>>)> {
+    let length_size = usize::from(avcc.get(4)? & 0x03) + 1;
+    let mut parameter_sets = Vec::new();
+    let mut pos = 5;
+    // 先是 SPS（个数占低 5 位），然后是 PPS
+    for count_mask in [0x1F, 0xFF] {
+        let count = avcc.get(pos)? & count_mask;
+        pos += 1;
+        for _ in 0..count {
+            let length = read_u16(avcc, pos)? as usize;
+            parameter_sets.push(avcc.get(pos + 2..pos + 2 + length)?.to_vec());
+            pos += 2 + length;
+        }
+    }
+    Some((length_size, parameter_sets))
+}
+
+// 解码时间不早于总时长 position 比例处的第一个样本序号（从 0 开始）
+fn sample_at_position(stts: &[u8], position: f64) -> Option<u64> {
+    let count = read_u32(stts, 4)? as usize;
+    let entries: Vec<(u64, u64)> = (0..count)
+        .map(|i| Some((read_u32(stts, 8 + i * 8)?, read_u32(stts, 12 + i * 8)?)))
+        .collect::<Option<_>>()?;
+    let total: u64 = entries.iter().map(|(count, delta)| count * delta).sum();
+    let target = (total as f64 * position) as u64;
+
+    let (mut time, mut index) = (0, 0);
+    for (count, delta) in entries {
+        if delta > 0 && time + count * delta > target {
+            return Some(index + (target - time).div_ceil(delta));
+        }
+        time += count * delta;
+        index += count;
+    }
+    Some(index.saturating_sub(1))
+}
+
+// 样本在文件中的偏移和大小
+fn sample_location(stbl: &[u8], sample: u64) -> Option<(u64, u64)> {
+    let stsz = find_box(stbl, b"stsz")?;
+    let uniform_size = read_u32(stsz, 4)?;
+    let sample_size = |index: u64| match uniform_size {
+        0 => read_u32(stsz, 12 + index as usize * 4),
+        size => Some(size),
+    };
+
+    let (chunk_offsets, wide) = match find_box(stbl, b"stco") {
+        Some(stco) => (stco, false),
+        None => (find_box(stbl, b"co64")?, true),
+    };
+    let chunk_count = read_u32(chunk_offsets, 4)?;
+    let chunk_offset = |chunk: u64| {
+        if wide {
+            read_u64(chunk_offsets, 8 + chunk as usize * 8)
+        } else {
+            read_u32(chunk_offsets, 8 + chunk as usize * 4)
+        }
+    };
+
+    // stsc 每项描述从某个块开始、每块包含的样本数，直到下一项的起始块
+    let stsc = find_box(stbl, b"stsc")?;
+    let runs = read_u32(stsc, 4)? as usize;
+    let mut first_sample = 0;
+    for run in 0..runs {
+        let first_chunk = read_u32(stsc, 8 + run * 12)?.checked_sub(1)?;
+        let samples_per_chunk = read_u32(stsc, 12 + run * 12)?;
+        let end_chunk = if run + 1 < runs {
+            read_u32(stsc, 8 + (run + 1) * 12)?.checked_sub(1)?
+        } else {
+            chunk_count
+        };
+        let run_samples = end_chunk.checked_sub(first_chunk)? * samples_per_chunk;
+        if samples_per_chunk > 0 && sample < first_sample + run_samples {
+            let chunk = first_chunk + (sample - first_sample) / samples_per_chunk;
+            let first_in_chunk = sample - (sample - first_sample) % samples_per_chunk;
+            let mut offset = chunk_offset(chunk)?;
+            for index in first_in_chunk..sample {
+                offset += sample_size(index)?;
+            }
+            return Some((offset, sample_size(sample)?));
+        }
+        first_sample += run_samples;
+    }
+    None
+}
+
+// 按长度前缀拆分 MP4 样本中的 NAL 单元
+fn split_length_prefixed(data: &[u8], length_size: usize) -> Vec<Vec<u8>> {
+    let mut units = Vec::new();
+    let mut pos = 0;
+    while pos + length_size <= data.len() {
+        let length = data[pos..pos + length_size]
+            .iter()
+            .fold(0, |length, &byte| length << 8 | usize::from(byte));
+        pos += length_size;
+        let Some(unit) = data.get(pos..pos + length) else {
+            break;
+        };
+        units.push(unit.to_vec());
+        pos += length;
+    }
+    units
+}
+
+// ==================== MPEG-TS ====================
+
+// 从目标位置起查找 IDR 帧，找不到时再从文件开头查找
+fn read_ts_keyframe(file: &mut File, file_size: u64, position: f64) -> Result<Keyframe, String> {
+    let packet_size = TS_PACKET_SIZE as u64;
+    let target = (file_size as f64 * position) as u64 / packet_size * packet_size;
+    let starts = if target > 0 { vec![target, 0] } else { vec![0] };
+    for start in starts {
+        let mut scanner = TsKeyframeScanner::default();
+        let end = file_size.min(start + TS_SCAN_LIMIT);
+        let mut offset = start;
+        while offset < end && scanner.keyframe.is_none() {
+            let length = TS_READ_CHUNK.min(end - offset);
+            scanner.scan(&read_range(file, offset, length)?)?;
+            offset += length;
+        }
+        // 文件末尾的最后一个 PES 之后没有新的 PES 开始
+        if offset >= file_size {
+            scanner.finish_pes();
+        }
+        if let Some(keyframe) = scanner.keyframe {
+            return Ok(keyframe);
+        }
+    }
+    Err("未找到关键帧".to_string())
+}
+
+#[derive(Default)]
+struct TsKeyframeScanner {
+    pmt_pid: Option<u16>,
+    video_pid: Option<u16>,
+    pes: Option<Vec<u8>>, // 正在拼接的视频 PES 负载，从中途开始读取时跳过不完整的 PES
+    parameter_sets: Vec<Vec<u8>>,
+    keyframe: Option<Keyframe>,
+}
+
+impl TsKeyframeScanner {
+    fn scan(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut pos = 0;
+        while pos + TS_PACKET_SIZE <= data.len() && self.keyframe.is_none() {
+            let next_sync = data
+                .get(pos + TS_PACKET_SIZE)
+                .is_none_or(|b| *b == TS_SYNC_BYTE);
+            if data[pos] != TS_SYNC_BYTE || !next_sync {
+                pos += 1;
+                continue;
+            }
+            self.scan_packet(&data[pos..pos + TS_PACKET_SIZE])?;
+            pos += TS_PACKET_SIZE;
+        }
+        Ok(())
+    }
+
+    fn scan_packet(&mut self, packet: &[u8]) -> Result<(), String> {
+        if packet[1] & 0x80 != 0 {
+            return Ok(());
+        }
+        let unit_start = packet[1] & 0x40 != 0;
+        let pid = (u16::from(packet[1] & 0x1F) << 8) | u16::from(packet[2]);
+        let adaptation = (packet[3] >> 4) & 0x03;
+
+        let mut offset = 4;
+        if adaptation & 0x02 != 0 {
+            offset += 1 + packet[4] as usize;
+        }
+        if adaptation & 0x01 == 0 || offset >= TS_PACKET_SIZE {
+            return Ok(());
+        }
+        let payload = &packet[offset..];
+
+        if pid == 0 {
+            if unit_start && self.pmt_pid.is_none() {
+                self.parse_pat(payload);
+            }
+            return Ok(());
+        }
+        if Some(pid) == self.pmt_pid {
+            if unit_start && self.video_pid.is_none() {
+                self.parse_pmt(payload)?;
+            }
+            return Ok(());
+        }
+        if Some(pid) != self.video_pid {
+            return Ok(());
+        }
+
+        if unit_start {
+            self.finish_pes();
+            self.pes = parse_pes_header(pid, payload).map(|pes| pes.data);
+        } else if let Some(pes) = &mut self.pes {
+            pes.extend_from_slice(payload);
+        }
+        Ok(())
+    }
+
+    fn parse_pat(&mut self, payload: &[u8]) {
+        let Some(section) = psi_section(payload, 0x00) else {
+            return;
+        };
+        for entry in section.get(8..).unwrap_or_default().chunks_exact(4) {
+            let program_number = u16::from_be_bytes([entry[0], entry[1]]);
+            if program_number != 0 {
+                self.pmt_pid = Some((u16::from(entry[2] & 0x1F) << 8) | u16::from(entry[3]));
+                return;
+            }
+        }
+    }
+
+    fn parse_pmt(&mut self, payload: &[u8]) -> Result<(), String> {
+        let Some(section) = psi_section(payload, 0x02) else {
+            return Ok(());
+        };
+        if section.len() < 12 {
+            return Ok(());
+        }
+        let program_info_length = (usize::from(section[10] & 0x0F) << 8) | usize::from(section[11]);
+
+        let mut pos = 12 + program_info_length;
+        while pos + 5 <= section.len() {
+            let stream_type = section[pos];
+            let pid = (u16::from(section[pos + 1] & 0x1F) << 8) | u16::from(section[pos + 2]);
+            let es_info_length =
+                (usize::from(section[pos + 3] & 0x0F) << 8) | usize::from(section[pos + 4]);
+            pos += 5 + es_info_length;
+
+            match stream_type {
+                STREAM_TYPE_H264 => {
+                    self.video_pid = Some(pid);
+                    return Ok(());
+                }
+                STREAM_TYPE_H265 => return Err("内置解码器不支持 H.265 视频".to_string()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // 一个视频 PES 已完整：记录其中的参数集，含 IDR 条带且参数集齐全时作为结果
+    fn finish_pes(&mut self) {
+        let Some(pes) = self.pes.take() else {
+            return;
+        };
+        let nal_units: Vec<Vec<u8>> = split_nal_units(&pes)
+            .into_iter()
+            .filter(|nal| !nal.is_empty())
+            .map(<[u8]>::to_vec)
+            .collect();
+        for nal in &nal_units {
+            if matches!(nal[0] & 0x1F, 7 | 8) && !self.parameter_sets.contains(nal) {
+                self.parameter_sets.push(nal.clone());
+            }
+        }
+        let has_parameter_set = |nal_type| {
+            self.parameter_sets
+                .iter()
+                .any(|nal| nal[0] & 0x1F == nal_type)
+        };
+        if nal_units.iter().any(|nal| nal[0] & 0x1F == 5)
+            && has_parameter_set(7)
+            && has_parameter_set(8)
+        {
+            self.keyframe = Some(Keyframe {
+                parameter_sets: self.parameter_sets.clone(),
+                nal_units,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_box(kind: &[u8; 4], entries: &[u32]) -> Vec<u8> {
+        let mut body = vec![0; 4];
+        for value in entries {
+            body.extend_from_slice(&value.to_be_bytes());
+        }
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(&body);
+        data
+    }
+
+    fn gray_frame(width: usize, height: usize, luma: impl Fn(usize) -> u8) -> Frame {
+        let chroma_size = width.div_ceil(2) * height.div_ceil(2);
+        Frame {
+            width,
+            height,
+            luma: (0..width * height).map(luma).collect(),
+            cb: vec![128; chroma_size],
+            cr: vec![128; chroma_size],
+        }
+    }
+
+    #[test]
+    fn parses_avcc_and_length_prefixed_samples() {
+        let avcc = [
+            1, 0x64, 0, 0x1F, 0xFF, 0xE1, 0, 3, 0x67, 0x64, 0, 1, 0, 2, 0x68, 0xEE,
+        ];
+        let (length_size, parameter_sets) = parse_avcc(&avcc).unwrap();
+        assert_eq!(length_size, 4);
+        assert_eq!(parameter_sets, vec![vec![0x67, 0x64, 0], vec![0x68, 0xEE]]);
+        assert!(parse_avcc(&avcc[..10]).is_none());
+
+        // 最后一个 NAL 长度超出样本，丢弃
+        let sample = [0, 0, 0, 2, 0x65, 0x88, 0, 0, 0, 1, 0x06, 0, 0, 0, 9, 1];
+        assert_eq!(
+            split_length_prefixed(&sample, 4),
+            vec![vec![0x65, 0x88], vec![0x06]]
+        );
+    }
+
+    #[test]
+    fn finds_sample_by_time_and_location() {
+        // 前 10 个样本各 100，后 10 个各 200，总时长 3000
+        let stts = full_box(b"stts", &[2, 10, 100, 10, 200]);
+        assert_eq!(sample_at_position(&stts[8..], 0.0), Some(0));
+        assert_eq!(sample_at_position(&stts[8..], 0.1), Some(3));
+        assert_eq!(sample_at_position(&stts[8..], 0.5), Some(13));
+
+        // 块 1、2 各 2 个样本，块 3 有 1 个样本
+        let mut stbl = full_box(b"stsz", &[0, 5, 10, 20, 30, 40, 50]);
+        stbl.extend(full_box(b"stsc", &[2, 1, 2, 1, 3, 1, 1]));
+        stbl.extend(full_box(b"stco", &[3, 1000, 2000, 3000]));
+        assert_eq!(sample_location(&stbl, 0), Some((1000, 10)));
+        assert_eq!(sample_location(&stbl, 3), Some((2030, 40)));
+        assert_eq!(sample_location(&stbl, 4), Some((3000, 50)));
+        assert_eq!(sample_location(&stbl, 5), None);
+    }
+
+    #[test]
+    fn rejects_flat_or_mismatched_frames() {
+        let gradient = gray_frame(16, 16, |i| (i % 256) as u8);
+        assert!(check_frame(&gradient, Some((16, 16))).is_ok());
+        assert!(check_frame(&gradient, None).is_ok());
+        assert!(check_frame(&gradient, Some((1920, 1080))).is_err());
+        assert!(check_frame(&gray_frame(16, 16, |_| 128), None).is_err());
+    }
+
+    #[test]
+    fn scales_and_converts_to_rgb() {
+        // 左半白、右半黑，缩小一半后每个像素仍是纯白或纯黑
+        let frame = gray_frame(4, 2, |i| if i % 4 < 2 { 235 } else { 16 });
+        let (width, height, rgb) = scale_to_rgb(&frame, 2);
+        assert_eq!((width, height), (2, 1));
+        assert_eq!(rgb, vec![255, 255, 255, 0, 0, 0]);
+
+        // 不放大
+        let (width, height, _) = scale_to_rgb(&frame, 320);
+        assert_eq!((width, height), (4, 2));
+    }
+}
@@ -0,0 +1,399 @@
+use crate::download::types::*;
+use crate::download::utils::*;
+use crate::path_guard::{ensure_within, SafeSegment};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Component, Path, PathBuf};
+use tauri::AppHandle;
+use tokio::fs;
+
+// 默认回收站保留天数
+const DEFAULT_TRASH_RETENTION_DAYS: i64 = 30;
+// 下载目录下的资源库目录，用于从旧版本记录的绝对路径中找回相对位置
+const LIBRARY_DIRS: [&str; 2] = ["manga", "cartoons"];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TrashConfig {
+    retention_days: i64,
+}
+
+/// 获取回收站目录路径
+async fn get_trash_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let trash_dir = get_downloads_path(app_handle).await?.join("trash");
+
+    if !trash_dir.exists() {
+        fs::create_dir_all(&trash_dir)
+            .await
+            .map_err(|e| format!("创建回收站目录失败: {}", e))?;
+    }
+
+    Ok(trash_dir)
+}
+
+/// 将目录移动到回收站，返回回收站条目
+///
+/// 回收站与下载目录位于同一位置，移动只是一次重命名，不会复制数据。
+/// 原位置按相对下载目录的路径记录，下载目录移动后仍能恢复。
+pub async fn move_to_trash(
+    app_handle: &AppHandle,
+    origin_path: &Path,
+    media_type: &str,
+    name: &str,
+) -> Result<TrashItem, String> {
+    let downloads_path = get_downloads_path(app_handle).await?;
+    ensure_within(&downloads_path, origin_path)?;
+    let relative_origin = relative_origin_path(&downloads_path, origin_path)?;
+    let trash_dir = get_trash_path(app_handle).await?;
+    let now = chrono::Utc::now();
+    let origin_name = origin_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let id = format!("{}_{}", now.format("%Y%m%d%H%M%S%3f"), origin_name);
+    let item_dir = trash_dir.join(&id);
+
+    fs::create_dir_all(&item_dir)
+        .await
+        .map_err(|e| format!("创建回收站条目失败: {}", e))?;
+
+    let size = path_size(origin_path).await;
+    let item = TrashItem {
+        schema_version: METADATA_SCHEMA_VERSION,
+        id: id.clone(),
+        media_type: media_type.to_string(),
+        name: name.to_string(),
+        origin_path: relative_origin,
+        deleted_time: now.format("%Y-%m-%d %H:%M:%S").to_string(),
+        size,
+    };
+
+    // 先写入条目信息再移动内容，保证回收站中的内容总能被列出和清理
+    let info_result = match serde_json::to_string_pretty(&item) {
+        Ok(content) => fs::write(item_dir.join("trash_info.json"), content)
+            .await
+            .map_err(|e| format!("写入回收站信息失败: {}", e)),
+        Err(e) => Err(format!("序列化回收站信息失败: {}", e)),
+    };
+    if let Err(e) = info_result {
+        let _ = fs::remove_dir_all(&item_dir).await;
+        return Err(e);
+    }
+
+    // 移动失败时内容仍在原位置，撤销已创建的条目
+    if let Err(e) = fs::rename(origin_path, item_dir.join("content")).await {
+        let _ = fs::remove_dir_all(&item_dir).await;
+        return Err(format!("移动到回收站失败: {}", e));
+    }
+
+    println!("已移动到回收站: {} -> {}", item.origin_path, id);
+    Ok(item)
+}
+
+/// 获取回收站中的所有条目
+#[tauri::command]
+pub async fn get_trash_items(app_handle: AppHandle) -> Result<Vec<TrashItem>, String> {
+    // 列出前先清理过期条目
+    purge_expired_trash(&app_handle).await?;
+
+    let mut items = read_trash_items(&app_handle).await?;
+    items.sort_by(|a, b| b.deleted_time.cmp(&a.deleted_time));
+    Ok(items)
+}
+
+/// 从回收站恢复条目到原位置
+#[tauri::command]
+pub async fn restore_trash_item(
+    app_handle: AppHandle,
+    trash_id: SafeSegment,
+) -> Result<DeleteChapterResult, String> {
+    let item_dir = get_trash_path(&app_handle).await?.join(&trash_id);
+    let item = read_trash_info(&item_dir)
+        .await
+        .ok_or_else(|| "回收站条目不存在".to_string())?;
+
+    // 按当前下载目录重建原位置，只允许恢复到下载目录内
+    let downloads_path = get_downloads_path(&app_handle).await?;
+    let origin_path = resolve_origin_path(&downloads_path, &item.origin_path)?;
+    ensure_within(&downloads_path, &origin_path)?;
+    if origin_path.exists() {
+        return Err(format!("原位置已存在同名内容: {}", item.origin_path));
+    }
+
+    if let Some(parent) = origin_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("创建原目录失败: {}", e))?;
+    }
+
+    fs::rename(item_dir.join("content"), &origin_path)
+        .await
+        .map_err(|e| format!("恢复失败: {}", e))?;
+    let _ = fs::remove_dir_all(&item_dir).await;
+
+    println!("已从回收站恢复: {}", item.origin_path);
+    Ok(DeleteChapterResult {
+        success: true,
+        message: format!("\"{}\" 已恢复", item.name),
+        trash_id: None,
+    })
+}
+
+/// 永久删除回收站条目，trash_id 为空时清空回收站
+#[tauri::command]
+pub async fn purge_trash(
+    app_handle: AppHandle,
+    trash_id: Option<SafeSegment>,
+) -> Result<DeleteChapterResult, String> {
+    let trash_dir = get_trash_path(&app_handle).await?;
+
+    let purged = match trash_id {
+        Some(id) => {
+            let item_dir = trash_dir.join(&id);
+            if !item_dir.exists() {
+                return Err("回收站条目不存在".to_string());
+            }
+            fs::remove_dir_all(&item_dir)
+                .await
+                .map_err(|e| format!("永久删除失败: {}", e))?;
+            1
+        }
+        None => {
+            let items = read_trash_items(&app_handle).await?;
+            for item in &items {
+                fs::remove_dir_all(trash_dir.join(&item.id))
+                    .await
+                    .map_err(|e| format!("永久删除失败: {}", e))?;
+            }
+            items.len()
+        }
+    };
+
+    Ok(DeleteChapterResult {
+        success: true,
+        message: format!("已永久删除 {} 个条目", purged),
+        trash_id: None,
+    })
+}
+
+/// 获取回收站保留天数
+#[tauri::command]
+pub async fn get_trash_retention_days(app_handle: AppHandle) -> Result<i64, String> {
+    Ok(read_trash_config(&app_handle).await?.retention_days)
+}
+
+/// 设置回收站保留天数
+#[tauri::command]
+pub async fn set_trash_retention_days(app_handle: AppHandle, days: i64) -> Result<(), String> {
+    if days < 1 {
+        return Err("保留天数至少为1天".to_string());
+    }
+
+    let config_path = get_trash_path(&app_handle).await?.join("trash_config.json");
+    let content = serde_json::to_string_pretty(&TrashConfig {
+        retention_days: days,
+    })
+    .map_err(|e| format!("序列化回收站配置失败: {}", e))?;
+
+    fs::write(&config_path, content)
+        .await
+        .map_err(|e| format!("写入回收站配置失败: {}", e))
+}
+
+/// 清理超过保留期限的回收站条目，返回清理数量
+pub async fn purge_expired_trash(app_handle: &AppHandle) -> Result<usize, String> {
+    let retention_days = read_trash_config(app_handle).await?.retention_days;
+    let trash_dir = get_trash_path(app_handle).await?;
+    let expire_before = (chrono::Utc::now() - chrono::Duration::days(retention_days))
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+
+    let mut purged = 0;
+    for item in read_trash_items(app_handle).await? {
+        if item.deleted_time < expire_before {
+            match fs::remove_dir_all(trash_dir.join(&item.id)).await {
+                Ok(_) => purged += 1,
+                Err(e) => eprintln!("清理过期回收站条目失败 {}: {}", item.id, e),
+            }
+        }
+    }
+
+    if purged > 0 {
+        println!("已清理 {} 个过期回收站条目", purged);
+    }
+    Ok(purged)
+}
+
+// 原位置相对下载目录的路径，以 `/` 分隔，与平台无关
+fn relative_origin_path(downloads_path: &Path, origin_path: &Path) -> Result<String, String> {
+    let relative = origin_path
+        .strip_prefix(downloads_path)
+        .map_err(|_| format!("不在下载目录内: {}", origin_path.display()))?;
+    let segments: Vec<String> = relative
+        .components()
+        .map(|component| match component {
+            Component::Normal(segment) => Ok(segment.to_string_lossy().to_string()),
+            _ => Err(format!("非法的路径: {}", origin_path.display())),
+        })
+        .collect::<Result<_, _>>()?;
+    if segments.is_empty() {
+        return Err("不能将下载目录移到回收站".to_string());
+    }
+    Ok(segments.join("/"))
+}
+
+// 由回收站记录重建原位置
+//
+// 新条目记录相对下载目录的路径；旧版本记录的是绝对路径，下载目录移动后
+// 从资源库目录（manga、cartoons）开始截取，拼接到当前下载目录下
+fn resolve_origin_path(downloads_path: &Path, origin: &str) -> Result<PathBuf, String> {
+    let origin_path = Path::new(origin);
+    if !origin_path.has_root() {
+        let segments: Vec<&str> = origin.split('/').collect();
+        if segments
+            .iter()
+            .any(|segment| segment.is_empty() || *segment == "." || *segment == "..")
+        {
+            return Err(format!("回收站记录的原位置无效: {}", origin));
+        }
+        return Ok(segments
+            .iter()
+            .fold(downloads_path.to_path_buf(), |path, segment| {
+                path.join(segment)
+            }));
+    }
+
+    if let Ok(relative) = origin_path.strip_prefix(downloads_path) {
+        return Ok(downloads_path.join(relative));
+    }
+    let components: Vec<Component> = origin_path.components().collect();
+    let library_start = components.iter().rposition(|component| {
+        matches!(component, Component::Normal(name) if LIBRARY_DIRS.iter().any(|dir| name == dir))
+    });
+    match library_start {
+        Some(start) => Ok(components[start..]
+            .iter()
+            .fold(downloads_path.to_path_buf(), |path, component| {
+                path.join(component)
+            })),
+        None => Err(format!("原位置不在当前下载目录内: {}", origin)),
+    }
+}
+
+/// 读取章节或详情 JSON 中的名称字段，用于回收站显示
+pub async fn read_display_name(json_path: &Path, field: &str) -> Option<String> {
+    let content = fs::read_to_string(json_path).await.ok()?;
+    let value: Value = serde_json::from_str(&content).ok()?;
+    value.get(field)?.as_str().map(|s| s.to_string())
+}
+
+async fn read_trash_config(app_handle: &AppHandle) -> Result<TrashConfig, String> {
+    let config_path = get_trash_path(app_handle).await?.join("trash_config.json");
+
+    let config = match fs::read_to_string(&config_path).await {
+        Ok(content) => serde_json::from_str::<TrashConfig>(&content).ok(),
+        Err(_) => None,
+    };
+
+    Ok(config.unwrap_or(TrashConfig {
+        retention_days: DEFAULT_TRASH_RETENTION_DAYS,
+    }))
+}
+
+async fn read_trash_items(app_handle: &AppHandle) -> Result<Vec<TrashItem>, String> {
+    let trash_dir = get_trash_path(app_handle).await?;
+    let mut items = Vec::new();
+
+    let mut entries = fs::read_dir(&trash_dir)
+        .await
+        .map_err(|e| format!("读取回收站目录失败: {}", e))?;
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let item_dir = entry.path();
+        if !item_dir.is_dir() {
+            continue;
+        }
+        match read_trash_info(&item_dir).await {
+            Some(item) => items.push(item),
+            None => eprintln!("回收站条目信息缺失: {}", item_dir.display()),
+        }
+    }
+
+    Ok(items)
+}
+
+async fn read_trash_info(item_dir: &Path) -> Option<TrashItem> {
+    let content = fs::read_to_string(item_dir.join("trash_info.json"))
+        .await
+        .ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// 在阻塞线程中计算文件或目录大小，避免大目录的遍历占用异步运行时
+async fn path_size(path: &Path) -> u64 {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || calculate_path_size(&path))
+        .await
+        .unwrap_or(0)
+}
+
+/// 递归计算文件或目录大小
+fn calculate_path_size(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| calculate_path_size(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn origin_is_stored_relative_to_downloads() {
+        let downloads = Path::new("/data/downloads");
+        assert_eq!(
+            relative_origin_path(downloads, &downloads.join("manga").join("uuid").join("ch1")),
+            Ok("manga/uuid/ch1".to_string())
+        );
+        assert!(relative_origin_path(downloads, downloads).is_err());
+        assert!(relative_origin_path(downloads, Path::new("/data/other/manga")).is_err());
+    }
+
+    #[test]
+    fn origin_is_rebuilt_under_current_downloads() {
+        let downloads = Path::new("/new/downloads");
+        assert_eq!(
+            resolve_origin_path(downloads, "cartoons/uuid/ep1"),
+            Ok(downloads.join("cartoons").join("uuid").join("ep1"))
+        );
+        assert!(resolve_origin_path(downloads, "cartoons/../../etc").is_err());
+        assert!(resolve_origin_path(downloads, "").is_err());
+
+        // 旧版本的绝对路径：下载目录移动后从资源库目录开始截取
+        assert_eq!(
+            resolve_origin_path(downloads, "/old/downloads/manga/uuid/group/ch1"),
+            Ok(downloads
+                .join("manga")
+                .join("uuid")
+                .join("group")
+                .join("ch1"))
+        );
+        assert_eq!(
+            resolve_origin_path(downloads, "/new/downloads/cartoons/uuid"),
+            Ok(downloads.join("cartoons").join("uuid"))
+        );
+        assert!(resolve_origin_path(downloads, "/etc/passwd").is_err());
+    }
+}
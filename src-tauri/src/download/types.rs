@@ -1,5 +1,10 @@
 use serde::{Deserialize, Serialize};
 
+/// 磁盘元数据（info.json、详情文件、任务文件等）的当前版本
+///
+/// 旧版本写入的文件没有该字段，读取时为 0，启动时由迁移程序升级。
+pub const METADATA_SCHEMA_VERSION: u32 = 1;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ImageInfo {
     pub url: String,
@@ -18,14 +23,17 @@ pub struct DownloadInfo {
     pub manga_detail: Option<MangaDetail>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)] // 缺失的字段使用默认值，兼容旧版本文件
 pub struct ChapterInfo {
+    pub schema_version: u32,
     pub manga_uuid: String,
     pub manga_name: String,
     pub group_path_word: String,
     pub chapter_uuid: String,
     pub chapter_name: String,
-    pub total_images: usize, // 添加总图片数量字段
+    pub chapter_index: Option<usize>, // 章节序号（从1开始），用于排序和导出编号，旧版本文件中为空
+    pub total_images: usize,          // 添加总图片数量字段
     pub images: Vec<String>,
     pub download_time: String,
 }
@@ -58,8 +66,10 @@ pub struct DownloadResult {
     pub chapter_path: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)] // 缺失的字段使用默认值，兼容旧版本文件
 pub struct MangaDetail {
+    pub schema_version: u32,
     pub uuid: String,
     pub name: String,
     pub path_word: String,
@@ -72,8 +82,10 @@ pub struct MangaDetail {
 }
 
 // 动画相关类型
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)] // 缺失的字段使用默认值，兼容旧版本文件
 pub struct CartoonDetail {
+    pub schema_version: u32,
     pub uuid: String,
     pub name: String,
     pub path_word: String,
@@ -96,20 +108,61 @@ pub struct CartoonDownloadInfo {
     pub chapter_uuid: String,
     pub chapter_name: String,
     pub video_url: String,
+    pub video_lines: Vec<VideoLine>, // 按顺序尝试的候选线路，第一条为首选
     pub cover: String,
     pub cartoon_detail: Option<CartoonDetail>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// 视频线路，当前线路下载失败时切换到下一条
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct VideoLine {
+    pub line: Option<String>, // 线路标识，如 "line3"
+    pub url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)] // 缺失的字段使用默认值，兼容旧版本文件
 pub struct CartoonChapterInfo {
+    pub schema_version: u32,
     pub cartoon_uuid: String,
     pub cartoon_name: String,
     pub chapter_uuid: String,
     pub chapter_name: String,
+    pub chapter_index: Option<usize>, // 剧集序号（从1开始），用于排序和导出编号，旧版本文件中为空
     pub video_file: String,
     pub file_size: u64,
     pub download_time: String,
     pub is_completed: bool,
+    pub quality: Option<VideoQuality>, // HLS 多码率视频选中的清晰度
+    pub video_line: Option<VideoLine>, // 完成下载的线路
+    pub media: Option<MediaInfo>,      // 从视频文件解析出的媒体信息，解析失败时为空
+    pub thumbnail: Option<String>,     // 缩略图文件名，相对章节目录
+}
+
+// 视频清晰度
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct VideoQuality {
+    pub label: String, // 如 "1080p"
+    pub bandwidth: Option<u64>,
+    pub resolution: Option<String>, // 如 "1920x1080"
+    pub codecs: Option<String>,
+}
+
+// 视频文件的媒体信息
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct MediaInfo {
+    pub container: String,  // "mp4" 或 "mpegts"
+    pub duration_secs: f64, // 时长（秒），无法确定时为 0
+    pub width: u32,
+    pub height: u32,
+    pub video_codec: Option<String>, // 如 "h264"、"hevc"
+    pub audio_codec: Option<String>, // 如 "aac"
+    pub audio_channels: Option<u32>,
+    pub audio_sample_rate: Option<u32>,
+    pub bitrate: u64, // 平均码率（bit/s），由文件大小和时长计算
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -131,6 +184,7 @@ pub struct CartoonDownloadResult {
 pub struct DeleteChapterResult {
     pub success: bool,
     pub message: String,
+    pub trash_id: Option<String>, // 回收站条目ID，用于撤销删除
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -177,3 +231,70 @@ pub struct ChapterDownloadDetail {
     pub downloaded_images: usize,
     pub progress: f64,
 }
+
+// 资源库一致性检查发现的问题
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LibraryIssue {
+    pub id: String,
+    // "missing_chapter_info", "image_list_mismatch", "stale_incomplete_episode",
+    // "orphan_temp_segments", "missing_detail", "missing_cover", "empty_folder", "stale_task"
+    pub kind: String,
+    pub media_type: String, // "manga", "cartoon", "task"
+    pub path: String,
+    pub message: String,
+    pub fixes: Vec<String>, // 可用的修复操作: "delete", "rebuild_metadata", "resume"
+    pub owner_uuid: Option<String>,
+    pub chapter_uuid: Option<String>,
+}
+
+// 资源库一致性检查报告
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LibraryCheckReport {
+    pub issues: Vec<LibraryIssue>,
+    pub scanned_manga: usize,
+    pub scanned_cartoons: usize,
+    pub scanned_tasks: usize,
+    pub check_time: String,
+}
+
+// 资源库问题修复结果
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LibraryRepairResult {
+    pub success: bool,
+    pub message: String,
+}
+
+// 回收站条目
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)] // 缺失的字段使用默认值，兼容旧版本文件
+pub struct TrashItem {
+    pub schema_version: u32,
+    pub id: String,
+    pub media_type: String, // "manga", "manga_chapter", "cartoon", "cartoon_chapter"
+    pub name: String,
+    pub origin_path: String,
+    pub deleted_time: String,
+    pub size: u64,
+}
+
+// 资源库导出结果
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LibraryExportResult {
+    pub success: bool,
+    pub target_path: String,
+    pub exported_files: usize,
+    pub skipped_files: usize, // 目标已存在且大小一致的文件
+    pub linked_files: usize,  // 以硬链接方式导出的文件
+    pub errors: Vec<String>,
+}
+
+// 资源库迁移报告
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct LibraryMigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub moved_paths: Vec<String>, // "旧路径 -> 新路径"
+    pub upgraded_files: usize,
+    pub backup_path: Option<String>,
+    pub errors: Vec<String>,
+}
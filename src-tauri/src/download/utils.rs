@@ -1,8 +1,15 @@
+use crate::download::migration::wait_for_library_migration;
 use std::path::PathBuf;
 use tauri::{AppHandle, Manager};
 
-/// 获取下载根目录路径
+/// 获取下载根目录路径，启动时的资源库迁移完成前会一直等待
 pub async fn get_downloads_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    wait_for_library_migration(app_handle).await;
+    library_root(app_handle)
+}
+
+/// 获取下载根目录路径，不等待迁移（仅供迁移本身使用）
+pub fn library_root(app_handle: &AppHandle) -> Result<PathBuf, String> {
     let resource_dir = app_handle
         .path()
         .resource_dir()
@@ -20,3 +27,146 @@ pub async fn get_manga_downloads_path(app_handle: &AppHandle) -> Result<PathBuf,
 pub async fn get_cartoon_downloads_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
     Ok(get_downloads_path(app_handle).await?.join("cartoons"))
 }
+
+/// 将名称转换为在 Windows/macOS/Linux 上都合法的文件名
+pub fn sanitize_filename(name: &str) -> String {
+    const MAX_LEN: usize = 180;
+    const RESERVED: [&str; 22] = [
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    // 限制长度（按字节截断到字符边界）
+    if sanitized.len() > MAX_LEN {
+        let mut end = MAX_LEN;
+        while !sanitized.is_char_boundary(end) {
+            end -= 1;
+        }
+        sanitized.truncate(end);
+    }
+
+    // Windows 不允许以空格或点结尾
+    let mut sanitized = sanitized.trim().trim_end_matches(['.', ' ']).to_string();
+
+    if sanitized.is_empty() {
+        return "_".to_string();
+    }
+
+    let stem = sanitized.split('.').next().unwrap_or("").to_uppercase();
+    if RESERVED.contains(&stem.as_str()) {
+        sanitized.insert(0, '_');
+    }
+
+    sanitized
+}
+
+/// 自然排序比较：名称中的数字按数值比较（第9话 < 第10话）
+pub fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let mut num_a = String::new();
+                while let Some(c) = a_chars.peek().copied().filter(|c| c.is_ascii_digit()) {
+                    num_a.push(c);
+                    a_chars.next();
+                }
+                let mut num_b = String::new();
+                while let Some(c) = b_chars.peek().copied().filter(|c| c.is_ascii_digit()) {
+                    num_b.push(c);
+                    b_chars.next();
+                }
+
+                let trimmed_a = num_a.trim_start_matches('0');
+                let trimmed_b = num_b.trim_start_matches('0');
+                let ordering = trimmed_a
+                    .len()
+                    .cmp(&trimmed_b.len())
+                    .then_with(|| trimmed_a.cmp(trimmed_b));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                if ca != cb {
+                    return ca.cmp(&cb);
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+/// 章节排序：按保存的章节序号排列，序号相同或缺失时按名称自然排序
+///
+/// 旧版本下载的章节没有序号，排在有序号的章节之后。
+pub fn chapter_cmp(
+    a_index: Option<usize>,
+    a_name: &str,
+    b_index: Option<usize>,
+    b_name: &str,
+) -> std::cmp::Ordering {
+    a_index
+        .is_none()
+        .cmp(&b_index.is_none())
+        .then_with(|| a_index.cmp(&b_index))
+        .then_with(|| natural_cmp(a_name, b_name))
+}
+
+/// 读取章节信息 JSON 中的章节序号
+pub fn json_chapter_index(chapter: &serde_json::Value) -> Option<usize> {
+    chapter
+        .get("chapter_index")?
+        .as_u64()
+        .map(|index| index as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Ordering;
+
+    #[test]
+    fn natural_cmp_compares_numbers_by_value() {
+        assert_eq!(natural_cmp("第9话", "第10话"), Ordering::Less);
+        assert_eq!(natural_cmp("第010话", "第10话"), Ordering::Equal);
+    }
+
+    #[test]
+    fn chapter_cmp_prefers_saved_index() {
+        assert_eq!(
+            chapter_cmp(Some(2), "第1话", Some(10), "第2话"),
+            Ordering::Less
+        );
+        assert_eq!(
+            chapter_cmp(Some(1), "番外", Some(2), "第1话"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn chapter_cmp_puts_missing_index_last() {
+        assert_eq!(
+            chapter_cmp(None, "第1话", Some(5), "第5话"),
+            Ordering::Greater
+        );
+        assert_eq!(chapter_cmp(None, "第9话", None, "第10话"), Ordering::Less);
+    }
+}
@@ -0,0 +1,273 @@
+use crate::download::probe::{is_mp4_box_type, probe_media};
+use crate::download::remux::{TS_PACKET_SIZE, TS_SYNC_BYTE};
+use crate::download::types::MediaInfo;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const VERIFY_BUFFER_SIZE: usize = 256 * 1024;
+const NULL_PACKET_PID: u16 = 0x1FFF;
+// 实际时长与播放列表时长的允许误差：取固定秒数和比例中较大的一个
+const DURATION_TOLERANCE_SECS: f64 = 2.0;
+const DURATION_TOLERANCE_RATIO: f64 = 0.02;
+// 连续计数器错误占带负载 TS 包的比例超过该值才视为损坏，少量跳变（源站丢包）只记录警告
+const MAX_COUNTER_ERROR_RATIO: f64 = 0.01;
+
+/// 合并结果的预期，用于校验输出文件
+#[derive(Debug, Default)]
+pub struct MergeExpectation {
+    pub part_sizes: Vec<u64>, // 直接拼接时依次写入的初始化片段和分片大小，转封装时为空
+    pub source_size: u64,     // 所有分片的总大小
+    pub playlist_duration: f64, // 播放列表中 #EXTINF 的总时长
+    pub discontinuous: bool,  // 播放列表包含不连续标记，TS 时间戳可能重新开始
+}
+
+/// 校验合并或转封装后的视频文件，通过时返回解析出的媒体信息
+///
+/// 检查文件大小、容器结构（TS 同步字节和连续计数器，或 MP4 box 结构）以及时长，
+/// 任一项不符合时返回具体的问题描述。
+pub async fn verify_merged_output(
+    path: &Path,
+    expectation: MergeExpectation,
+) -> Result<MediaInfo, String> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || verify_output(&path, &expectation))
+        .await
+        .map_err(|e| format!("校验任务异常退出: {}", e))?
+}
+
+fn verify_output(path: &Path, expectation: &MergeExpectation) -> Result<MediaInfo, String> {
+    let read_error = |e: std::io::Error| format!("读取输出文件失败: {}", e);
+    let mut file = File::open(path).map_err(read_error)?;
+    let file_size = file.metadata().map_err(read_error)?.len();
+
+    // 直接拼接的文件大小必须等于各部分之和；转封装去掉了 TS 包头，只检查是否明显偏小
+    let merged = !expectation.part_sizes.is_empty();
+    if merged {
+        let expected: u64 = expectation.part_sizes.iter().sum();
+        if file_size != expected {
+            return Err(format!(
+                "合并后的文件大小 {} bytes 与分片总大小 {} bytes 不一致",
+                file_size, expected
+            ));
+        }
+    } else if file_size == 0 || file_size < expectation.source_size / 2 {
+        return Err(format!(
+            "转封装后的文件大小异常: {} bytes，分片共 {} bytes",
+            file_size, expectation.source_size
+        ));
+    }
+
+    let mut head = [0u8; TS_PACKET_SIZE];
+    let head_length = file.read(&mut head).map_err(read_error)?;
+    file.seek(SeekFrom::Start(0)).map_err(read_error)?;
+    let is_ts = head_length > 0 && head[0] == TS_SYNC_BYTE;
+    if is_ts {
+        let part_sizes = if merged {
+            expectation.part_sizes.clone()
+        } else {
+            vec![file_size]
+        };
+        verify_ts_packets(file, &part_sizes)?;
+    } else if head_length >= 8 && is_mp4_box_type(&head[4..8]) {
+        verify_mp4_boxes(&mut file, file_size)?;
+    } else {
+        return Err("无法识别输出文件的格式".to_string());
+    }
+
+    let info = probe_media(path)?;
+    // 不连续的 TS 时间戳会重新开始，无法据此计算时长
+    let duration_known = info.duration_secs > 0.0 && !(is_ts && expectation.discontinuous);
+    if !merged && info.duration_secs <= 0.0 {
+        return Err("无法读取转封装后视频的时长".to_string());
+    }
+    if duration_known && expectation.playlist_duration > 0.0 {
+        let tolerance =
+            DURATION_TOLERANCE_SECS.max(expectation.playlist_duration * DURATION_TOLERANCE_RATIO);
+        if (info.duration_secs - expectation.playlist_duration).abs() > tolerance {
+            return Err(format!(
+                "视频时长 {:.1} 秒与播放列表时长 {:.1} 秒相差过大",
+                info.duration_secs, expectation.playlist_duration
+            ));
+        }
+    }
+    Ok(info)
+}
+
+// 逐个分片检查 TS 包的同步字节和连续计数器；不同分片之间计数器允许不连续
+// 同步字节和包长错误说明文件结构损坏，直接失败；计数器错误按整体比例判断
+fn verify_ts_packets(file: File, part_sizes: &[u64]) -> Result<(), String> {
+    let mut reader = BufReader::with_capacity(VERIFY_BUFFER_SIZE, file);
+    let mut packet = [0u8; TS_PACKET_SIZE];
+    let (mut payload_packets, mut counter_errors) = (0u64, 0u64);
+
+    for (index, size) in part_sizes.iter().enumerate() {
+        if size % TS_PACKET_SIZE as u64 != 0 {
+            return Err(format!(
+                "片段{}的大小 {} bytes 不是 TS 包长的整数倍",
+                index, size
+            ));
+        }
+
+        let mut counters: HashMap<u16, u8> = HashMap::new();
+        let mut first_error = None;
+        let mut errors = 0;
+        for packet_index in 0..size / TS_PACKET_SIZE as u64 {
+            reader
+                .read_exact(&mut packet)
+                .map_err(|e| format!("读取片段{}失败: {}", index, e))?;
+            if packet[0] != TS_SYNC_BYTE {
+                return Err(format!(
+                    "片段{}第 {} 个 TS 包的同步字节错误",
+                    index, packet_index
+                ));
+            }
+
+            let pid = (u16::from(packet[1] & 0x1F) << 8) | u16::from(packet[2]);
+            let adaptation = (packet[3] >> 4) & 0x03;
+            let counter = packet[3] & 0x0F;
+            // 只有带负载的包递增计数器；自适应字段中的不连续标志允许计数器跳变
+            if pid == NULL_PACKET_PID || adaptation & 0x01 == 0 {
+                continue;
+            }
+            payload_packets += 1;
+            let discontinuity = adaptation & 0x02 != 0 && packet[4] > 0 && packet[5] & 0x80 != 0;
+            if let Some(previous) = counters.insert(pid, counter) {
+                // 计数器相同为重复包，是允许的
+                if counter != previous && counter != (previous + 1) & 0x0F && !discontinuity {
+                    errors += 1;
+                    first_error.get_or_insert((pid, packet_index));
+                }
+            }
+        }
+
+        if let Some((pid, packet_index)) = first_error {
+            eprintln!(
+                "警告: 片段{}中 PID 0x{:04X} 的连续计数器在第 {} 个包处不连续（共 {} 处）",
+                index, pid, packet_index, errors
+            );
+            counter_errors += errors;
+        }
+    }
+
+    if counter_errors as f64 > payload_packets as f64 * MAX_COUNTER_ERROR_RATIO {
+        return Err(format!(
+            "连续计数器不连续的 TS 包过多: {}/{}",
+            counter_errors, payload_packets
+        ));
+    }
+    Ok(())
+}
+
+// 检查顶层 box 首尾相接直到文件末尾，并包含 moov 和媒体数据
+fn verify_mp4_boxes(file: &mut File, file_size: u64) -> Result<(), String> {
+    let read_error = |e: std::io::Error| format!("读取输出文件失败: {}", e);
+    let (mut has_moov, mut has_media) = (false, false);
+    let mut offset = 0;
+    while offset < file_size {
+        if file_size - offset < 8 {
+            return Err(format!(
+                "文件末尾有 {} bytes 不完整的数据",
+                file_size - offset
+            ));
+        }
+        let mut header = [0u8; 16];
+        let header_length = (file_size - offset).min(16) as usize;
+        file.seek(SeekFrom::Start(offset)).map_err(read_error)?;
+        file.read_exact(&mut header[..header_length])
+            .map_err(read_error)?;
+
+        let kind = &header[4..8];
+        if !kind.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+            return Err(format!("偏移 {} 处的 box 类型无效", offset));
+        }
+        let size = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => file_size - offset,
+            1 if header_length == 16 => u64::from_be_bytes([
+                header[8], header[9], header[10], header[11], header[12], header[13], header[14],
+                header[15],
+            ]),
+            size => u64::from(size),
+        };
+        if size < 8 || size > file_size - offset {
+            return Err(format!(
+                "{} box 的大小 {} bytes 超出文件范围（偏移 {}）",
+                String::from_utf8_lossy(kind),
+                size,
+                offset
+            ));
+        }
+
+        match kind {
+            b"moov" => has_moov = true,
+            b"mdat" | b"moof" => has_media = true,
+            _ => {}
+        }
+        offset += size;
+    }
+
+    if !has_moov {
+        return Err("缺少 moov box".to_string());
+    }
+    if !has_media {
+        return Err("缺少媒体数据".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    // 带负载、无自适应字段的 TS 包
+    fn ts_packet(pid: u16, counter: u8) -> [u8; TS_PACKET_SIZE] {
+        let mut packet = [0xFFu8; TS_PACKET_SIZE];
+        packet[0] = TS_SYNC_BYTE;
+        packet[1] = (pid >> 8) as u8 & 0x1F;
+        packet[2] = pid as u8;
+        packet[3] = 0x10 | (counter & 0x0F);
+        packet
+    }
+
+    fn verify_packets(name: &str, packets: &[[u8; TS_PACKET_SIZE]]) -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("verify_{}_{}.ts", name, std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        for packet in packets {
+            file.write_all(packet).unwrap();
+        }
+        drop(file);
+
+        let size = (packets.len() * TS_PACKET_SIZE) as u64;
+        let result = verify_ts_packets(File::open(&path).unwrap(), &[size]);
+        let _ = std::fs::remove_file(&path);
+        result
+    }
+
+    #[test]
+    fn continuous_counters_pass() {
+        let packets: Vec<_> = (0..300).map(|i| ts_packet(0x100, i as u8)).collect();
+        assert!(verify_packets("continuous", &packets).is_ok());
+    }
+
+    #[test]
+    fn occasional_counter_jump_is_only_a_warning() {
+        let mut packets: Vec<_> = (0..300).map(|i| ts_packet(0x100, i as u8)).collect();
+        packets[150] = ts_packet(0x100, 9);
+        assert!(verify_packets("jump", &packets).is_ok());
+    }
+
+    #[test]
+    fn frequent_counter_errors_fail() {
+        let packets: Vec<_> = (0..300).map(|i| ts_packet(0x100, (i * 3) as u8)).collect();
+        assert!(verify_packets("broken", &packets).is_err());
+    }
+
+    #[test]
+    fn wrong_sync_byte_fails() {
+        let mut packets: Vec<_> = (0..10).map(|i| ts_packet(0x100, i as u8)).collect();
+        packets[5][0] = 0x00;
+        assert!(verify_packets("sync", &packets).is_err());
+    }
+}
@@ -1,6 +1,4 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
-mod cache;
-
 
 #[tauri::command]
 fn greet(name: &str) -> String {
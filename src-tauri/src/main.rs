@@ -2,10 +2,14 @@
 
 mod cache;
 mod download;
+mod path_guard;
 
 #[tauri::command]
-fn open_browser(url: String) {
-    tauri_plugin_opener::open_url(&url, None::<&str>).unwrap();
+fn open_browser(url: String) -> Result<(), String> {
+    // 只允许打开 http/https 链接
+    let url = path_guard::validate_http_url(&url)?;
+    tauri_plugin_opener::open_url(url.as_str(), None::<&str>)
+        .map_err(|e| format!("打开链接失败: {}", e))
 }
 
 fn main() {
@@ -24,6 +28,38 @@ fn main() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_window_state::Builder::default().build())
         .plugin(tauri_plugin_http::init())
+        .setup(|app| {
+            // 后台升级旧版本资源库，访问资源库的命令会等待迁移结束
+            let app_handle = app.handle().clone();
+            download::start_library_migration(&app_handle);
+
+            // 创建共享的 HTTP 客户端，下载命令都从这里取用
+            tauri::async_runtime::block_on(download::init_http_client(&app_handle));
+
+            // 后台检测 ffmpeg，检测完成前下载使用内置处理
+            let ffmpeg_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = download::refresh_ffmpeg_status(&ffmpeg_handle).await {
+                    eprintln!("检测 ffmpeg 失败: {}", e);
+                }
+            });
+
+            // 按配置启动本地视频服务（默认关闭）
+            let media_server_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = download::apply_media_server_config(&media_server_handle).await {
+                    eprintln!("启动本地视频服务失败: {}", e);
+                }
+            });
+
+            // 启动时清理过期的回收站条目
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = download::purge_expired_trash(&app_handle).await {
+                    eprintln!("清理回收站失败: {}", e);
+                }
+            });
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             open_browser,
             cache::get_webview_data_dir,
@@ -46,6 +82,11 @@ fn main() {
             download::get_local_manga_chapters,
             download::download_cartoon_chapter,
             download::get_cartoon_download_progress,
+            download::get_cartoon_local_playlist,
+            download::estimate_download_size,
+            download::get_http_client_config,
+            download::set_http_client_config,
+            download::reload_http_client,
             download::pause_cartoon_download,
             download::resume_cartoon_download,
             download::cancel_cartoon_download,
@@ -60,9 +101,31 @@ fn main() {
             download::save_download_task,
             download::update_download_task_status,
             download::remove_download_task,
+            download::check_library_consistency,
+            download::repair_library_issue,
+            download::get_trash_items,
+            download::restore_trash_item,
+            download::purge_trash,
+            download::get_trash_retention_days,
+            download::set_trash_retention_days,
+            download::export_library,
+            download::get_naming_config,
+            download::set_naming_config,
+            download::preview_naming_template,
+            download::get_download_settings,
+            download::set_download_settings,
+            download::get_ffmpeg_status,
+            download::detect_ffmpeg,
+            download::get_media_server_config,
+            download::set_media_server_config,
+            download::get_media_server_status,
+            download::get_player_config,
+            download::choose_player_executable,
+            download::set_player_arguments,
+            download::reset_player_config,
+            download::open_cartoon_chapter_in_player,
+            download::open_cartoon_playlist_in_player,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
-
-
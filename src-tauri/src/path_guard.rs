@@ -0,0 +1,257 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Component, Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+/// 路径校验错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathGuardError {
+    /// 标识符为空
+    Empty,
+    /// 标识符包含 `.`、`..`、路径分隔符或控制字符
+    InvalidSegment(String),
+    /// 路径中包含 `..` 等不允许的组成部分
+    InvalidPath(String),
+    /// 路径不在允许的目录范围内
+    OutsideAllowedRoots(String),
+    /// 路径不是目录
+    NotDirectory(String),
+    /// 不是 http(s) 链接
+    InvalidUrl(String),
+}
+
+impl fmt::Display for PathGuardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathGuardError::Empty => write!(f, "标识符不能为空"),
+            PathGuardError::InvalidSegment(s) => write!(f, "非法的标识符: {}", s),
+            PathGuardError::InvalidPath(s) => write!(f, "非法的路径: {}", s),
+            PathGuardError::OutsideAllowedRoots(s) => write!(f, "路径不在允许的目录内: {}", s),
+            PathGuardError::NotDirectory(s) => write!(f, "只能打开目录: {}", s),
+            PathGuardError::InvalidUrl(s) => write!(f, "只允许打开 http/https 链接: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for PathGuardError {}
+
+impl From<PathGuardError> for String {
+    fn from(e: PathGuardError) -> Self {
+        e.to_string()
+    }
+}
+
+/// 经过校验的单级路径标识符（uuid、分组名、文件名等）
+///
+/// 不允许为空、`.`、`..`，不允许包含路径分隔符、盘符冒号或控制字符，
+/// 因此拼接到库路径后不会逃逸出该目录。作为命令参数时在反序列化阶段完成校验。
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SafeSegment(String);
+
+impl SafeSegment {
+    pub fn parse(value: &str) -> Result<Self, PathGuardError> {
+        if value.is_empty() {
+            return Err(PathGuardError::Empty);
+        }
+
+        let has_forbidden_char = value
+            .chars()
+            .any(|c| matches!(c, '/' | '\\' | ':') || c.is_control());
+        if value == "." || value == ".." || has_forbidden_char {
+            return Err(PathGuardError::InvalidSegment(value.to_string()));
+        }
+
+        Ok(SafeSegment(value.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for SafeSegment {
+    type Error = PathGuardError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        SafeSegment::parse(&value)
+    }
+}
+
+impl From<SafeSegment> for String {
+    fn from(segment: SafeSegment) -> Self {
+        segment.0
+    }
+}
+
+impl Deref for SafeSegment {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for SafeSegment {
+    fn as_ref(&self) -> &Path {
+        Path::new(&self.0)
+    }
+}
+
+impl AsRef<str> for SafeSegment {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for SafeSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// 确认路径位于 root 之内且不是 root 本身，不包含 `..` 等组成部分
+///
+/// 已存在的部分解析符号链接后再比较，库内指向库外的链接不会被当作库内路径。
+pub fn ensure_within(root: &Path, path: &Path) -> Result<(), PathGuardError> {
+    let has_parent_dir = path
+        .components()
+        .any(|c| matches!(c, Component::ParentDir | Component::CurDir));
+    if has_parent_dir {
+        return Err(PathGuardError::InvalidPath(path.display().to_string()));
+    }
+
+    let root = resolve_existing(root);
+    let resolved = resolve_existing(path);
+    if resolved == root || !resolved.starts_with(&root) {
+        return Err(PathGuardError::OutsideAllowedRoots(
+            path.display().to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+// 规范化路径中已存在的最长前缀，再拼接其余不存在的部分（如回收站中待恢复的原位置）
+fn resolve_existing(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    loop {
+        if let Ok(canonical) = std::fs::canonicalize(existing) {
+            return missing
+                .iter()
+                .rev()
+                .fold(canonical, |resolved, name| resolved.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+/// 校验允许在文件管理器中打开的路径，返回规范化后的路径
+///
+/// 只允许下载库和应用数据/配置目录下的目录；文件会被系统默认程序打开甚至执行，一律拒绝。
+pub fn validate_explorer_path(
+    app_handle: &AppHandle,
+    path: &str,
+) -> Result<PathBuf, PathGuardError> {
+    let canonical =
+        std::fs::canonicalize(path).map_err(|_| PathGuardError::InvalidPath(path.to_string()))?;
+    if !canonical.is_dir() {
+        return Err(PathGuardError::NotDirectory(path.to_string()));
+    }
+
+    let resolver = app_handle.path();
+    let roots = [
+        resolver.resource_dir().map(|dir| dir.join("downloads")),
+        resolver.app_data_dir(),
+        resolver.app_local_data_dir(),
+        resolver.app_config_dir(),
+    ];
+
+    let allowed = roots
+        .into_iter()
+        .filter_map(|root| root.ok())
+        .filter_map(|root| std::fs::canonicalize(root).ok())
+        .any(|root| canonical.starts_with(root));
+
+    if allowed {
+        Ok(strip_verbatim_prefix(canonical))
+    } else {
+        Err(PathGuardError::OutsideAllowedRoots(path.to_string()))
+    }
+}
+
+// 去掉 Windows 规范化路径的 \\?\ 前缀，资源管理器无法识别带前缀的路径
+fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    let text = path.to_string_lossy();
+    if let Some(rest) = text.strip_prefix(r"\\?\UNC\") {
+        PathBuf::from(format!(r"\\{}", rest))
+    } else if let Some(rest) = text.strip_prefix(r"\\?\") {
+        PathBuf::from(rest)
+    } else {
+        path
+    }
+}
+
+/// 校验只允许 http/https 协议的链接
+pub fn validate_http_url(url: &str) -> Result<reqwest::Url, PathGuardError> {
+    let parsed =
+        reqwest::Url::parse(url).map_err(|_| PathGuardError::InvalidUrl(url.to_string()))?;
+
+    match parsed.scheme() {
+        "http" | "https" => Ok(parsed),
+        _ => Err(PathGuardError::InvalidUrl(url.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("library/manga")).unwrap();
+        root
+    }
+
+    #[test]
+    fn ensure_within_rejects_root_and_parent_components() {
+        let root = temp_root("ensure_within_root");
+        let library = root.join("library");
+
+        assert!(ensure_within(&library, &library.join("manga")).is_ok());
+        // 尚不存在的路径按已存在的前缀判断
+        assert!(ensure_within(&library, &library.join("manga/new/chapter")).is_ok());
+        assert!(ensure_within(&library, &library).is_err());
+        assert!(ensure_within(&library, &library.join("manga/../..")).is_err());
+        assert!(ensure_within(&library, &root.join("other")).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ensure_within_resolves_symlinks() {
+        let root = temp_root("ensure_within_symlink");
+        let library = root.join("library");
+        std::fs::create_dir_all(root.join("outside")).unwrap();
+        std::os::unix::fs::symlink(root.join("outside"), library.join("link")).unwrap();
+        std::os::unix::fs::symlink(&library, root.join("library_link")).unwrap();
+
+        assert!(ensure_within(&library, &library.join("link")).is_err());
+        assert!(ensure_within(&library, &library.join("link/chapter")).is_err());
+        // 通过链接访问库本身和库内路径时按真实位置判断
+        assert!(ensure_within(&library, &root.join("library_link")).is_err());
+        assert!(ensure_within(&library, &root.join("library_link/manga")).is_ok());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -192,6 +192,8 @@ async function downloadCartoonChapter(pathWord, chapterId, line, chapterInfo) {
           chapterUuid: chapterData.uuid,
           chapterName: chapterData.name,
           videoUrl: chapterData.video,
+          // 剧集序号（从1开始），用于本地排序和导出编号
+          chapterIndex: chapterInfo.chapterIndex ?? null,
           // 优先使用动画主封面，如果没有再使用章节封面
           cover:
             chapterInfo.cartoonDetail && chapterInfo.cartoonDetail.cover
@@ -264,6 +266,16 @@ async function getLocalCartoonChapters(cartoonUuid) {
   return await cartoonDownloadManager.getLocalCartoonChapters(cartoonUuid)
 }
 
+/**
+ * 获取正在下载的章节的本地播放列表
+ * @param {string} cartoonUuid 动画UUID
+ * @param {string} chapterUuid 章节UUID
+ * @returns {Promise<Object>}
+ */
+async function getCartoonLocalPlaylist(cartoonUuid, chapterUuid) {
+  return await cartoonDownloadManager.getCartoonLocalPlaylist(cartoonUuid, chapterUuid)
+}
+
 /**
  * 打开本地视频目录
  * @param {string} cartoonUuid 动画UUID
@@ -296,6 +308,7 @@ export {
   deleteLocalCartoon,
   getLocalCartoonDetail,
   getLocalCartoonChapters,
+  getCartoonLocalPlaylist,
   openLocalVideoDirectory,
   getDownloadedCartoonList,
 }
@@ -227,6 +227,8 @@ async function downloadChapter(pathWord, chapterId, chapterInfo, onProgress) {
                 groupPathWord: chapterInfo.group_path_word || 'default',
                 chapterUuid: chapterData.uuid,
                 chapterName: chapterData.name,
+                // 章节序号（接口从0开始，本地从1开始），用于本地排序和导出编号
+                chapterIndex: Number.isInteger(chapterData.index) ? chapterData.index + 1 : null,
                 totalImages: chapterData.size || chapterData.contents.length, // 添加总图片数量
                 images: chapterData.contents.map((image, index) => ({
                     url: image.url,
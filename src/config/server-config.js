@@ -1,3 +1,4 @@
+import { invoke } from '@tauri-apps/api/core'
 import { pathHelper, CONFIG_FILES } from '@/utils/path-helper'
 
 // ============ 默认配置常量 ============
@@ -49,6 +50,7 @@ export async function initializeAllConfigs() {
   const serverExists = await pathHelper.configExists(CONFIG_FILES.SERVER)
   if (!serverExists) {
     await pathHelper.saveConfig(CONFIG_FILES.SERVER, DEFAULT_SERVER_CONFIG)
+    await reloadHttpClient()
     // console.log('已创建默认服务器配置文件')
   }
 
@@ -56,6 +58,8 @@ export async function initializeAllConfigs() {
   const appExists = await pathHelper.configExists(CONFIG_FILES.APP)
   if (!appExists) {
     await pathHelper.saveConfig(CONFIG_FILES.APP, DEFAULT_APP_CONFIG)
+    // API 源决定哪些主机会带上服务器请求头
+    await reloadHttpClient()
     // console.log('已创建默认应用配置文件')
   }
 }
@@ -145,12 +149,19 @@ export async function saveServerConfig(serverPort, requestHeaders = null) {
     newConfig.requestHeaders = requestHeaders
   }
 
-  return await pathHelper.saveConfig(CONFIG_FILES.SERVER, newConfig)
+  const result = await pathHelper.saveConfig(CONFIG_FILES.SERVER, newConfig)
+  if (requestHeaders) {
+    await reloadHttpClient()
+  }
+  return result
 }
 
 // 保存应用配置
 export async function saveAppConfig(config) {
-  return await pathHelper.saveConfig(CONFIG_FILES.APP, config)
+  const result = await pathHelper.saveConfig(CONFIG_FILES.APP, config)
+  // API 源决定哪些主机会带上服务器请求头
+  await reloadHttpClient()
+  return result
 }
 
 // 验证端口号格式
@@ -276,9 +287,19 @@ export async function saveRequestHeaders(headers) {
   const serverConfig = await pathHelper.readConfig(CONFIG_FILES.SERVER, DEFAULT_SERVER_CONFIG)
   serverConfig.requestHeaders = { ...headers }
   await pathHelper.saveConfig(CONFIG_FILES.SERVER, serverConfig)
+  await reloadHttpClient()
   return serverConfig.requestHeaders
 }
 
+// 请求头变化后让后端重新创建下载用的 HTTP 客户端，失败不影响保存
+async function reloadHttpClient() {
+  try {
+    await invoke('reload_http_client')
+  } catch (error) {
+    console.error('重新加载HTTP客户端失败:', error)
+  }
+}
+
 // 获取默认请求头配置
 export function getDefaultRequestHeaders() {
   return { ...DEFAULT_REQUEST_HEADERS }
@@ -38,6 +38,7 @@ export class CartoonDownloadManager {
           chapterUuid: task.chapter_uuid,
           chapterName: task.chapter_name,
           videoUrl: task.video_url,
+          videoLines: task.video_lines,
           cover: task.cover,
           cartoonDetail: task.cartoon_detail,
           startTime: task.start_time,
@@ -258,6 +259,19 @@ export class CartoonDownloadManager {
     })
   }
 
+  /**
+   * 获取正在下载的章节的本地播放列表（边下边播）
+   * @param {string} cartoonUuid 动画UUID
+   * @param {string} chapterUuid 章节UUID
+   * @returns {Promise<Object>}
+   */
+  async getCartoonLocalPlaylist(cartoonUuid, chapterUuid) {
+    return await invoke('get_cartoon_local_playlist', {
+      cartoonUuid,
+      chapterUuid,
+    })
+  }
+
   /**
    * 删除已下载的动画章节
    * @param {string} cartoonUuid 动画UUID
@@ -452,6 +466,8 @@ export class CartoonDownloadManager {
       chapterUuid,
       chapterName,
       videoUrl,
+      videoLines: downloadInfo.videoLines,
+      chapterIndex: downloadInfo.chapterIndex,
       cover,
       cartoonDetail,
       startTime: startTime || new Date().toISOString(),
@@ -475,6 +491,7 @@ export class CartoonDownloadManager {
         chapterUuid,
         chapterName,
         videoUrl,
+        videoLines: downloadInfo.videoLines ?? null,
         cover: cover || '',
         cartoonDetail: cartoonDetail || {},
         status: 'downloading',
@@ -532,10 +549,12 @@ export class CartoonDownloadManager {
         chapterUuid,
         chapterName,
         videoUrl,
+        videoLines: downloadInfo.videoLines ?? null,
         videoFile: downloadInfo.videoFile || `${chapterName}.mp4`,
         fileSize: downloadInfo.fileSize || 0,
         cover,
         cartoonDetail,
+        chapterIndex: downloadInfo.chapterIndex ?? null,
       })
 
       // 下载完成，从活跃列表移除
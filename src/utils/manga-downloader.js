@@ -27,6 +27,7 @@ export class DownloadManager {
       chapterName,
       images,
       mangaDetail, // 新增漫画详情参数
+      chapterIndex,
     } = chapterInfo
 
     const chapterKey = `${mangaUuid}|${groupPathWord}|${chapterUuid}`
@@ -161,6 +162,7 @@ export class DownloadManager {
           filename: `${String(index + 1).padStart(3, '0')}.jpg`,
         })),
         mangaDetail: mangaDetail || null, // 传递漫画详情
+        chapterIndex: chapterIndex ?? null,
       })
 
       // 清除定时器